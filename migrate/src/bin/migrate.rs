@@ -1,6 +1,6 @@
 use clap::Parser;
 use color_eyre::eyre::{Context, Error};
-use migrate::{Migration, Migrator};
+use migrate::{Migration, Migrator, Version};
 use std::fs::File;
 use std::path::PathBuf;
 
@@ -18,8 +18,12 @@ enum Command {
     New {
         /// The name of the eventual schema at this migration (e.g. `users`)
         schema: String,
-        /// The version of this migration (e.g. 3)
-        version: usize,
+        /// The version of this migration (e.g. 3, or a name like `4-tags`)
+        version: Version,
+        /// A version this migration builds on. Defaults to the one before;
+        /// pass it more than once to merge branches.
+        #[arg(long = "parent")]
+        parents: Vec<Version>,
     },
 
     /// Show the schema at a given ID
@@ -27,14 +31,18 @@ enum Command {
         /// The name of the schema you want to retrieve.
         schema: String,
         /// The version of the schema you want to retrieve.
-        version: usize,
+        version: Version,
     },
 }
 
 impl App {
     fn run(&self) -> Result<(), Error> {
         match &self.command {
-            Command::New { schema, version } => {
+            Command::New {
+                schema,
+                version,
+                parents,
+            } => {
                 if !self.dir.exists() {
                     std::fs::create_dir_all(&self.dir).wrap_err_with(|| {
                         format!(
@@ -46,7 +54,9 @@ impl App {
 
                 let blank = Migration {
                     schema: schema.clone(),
-                    version: *version,
+                    version: version.clone(),
+                    parents: parents.clone(),
+                    delete_policy: None,
                     ops: Vec::new(),
                 };
 
@@ -65,7 +75,7 @@ impl App {
                 Ok(())
             }
             Command::Schema { schema, version } => {
-                let mut migrator = Migrator::default();
+                let mut migrations: Vec<Migration> = Vec::new();
                 for entry in self
                     .dir
                     .read_dir()
@@ -76,14 +86,21 @@ impl App {
                     let file =
                         File::open(entry.path()).wrap_err("could not read migration file")?;
 
-                    let migration = serde_json::from_reader(file)
-                        .wrap_err("could not deserialize migration")?;
-
-                    migrator.add_migration(migration);
+                    migrations.push(
+                        serde_json::from_reader(file)
+                            .wrap_err("could not deserialize migration")?,
+                    );
                 }
 
+                // All at once, since moves between tables span migrations.
+                // The migrator works out the order from each one's parents.
+                let mut migrator = Migrator::default();
+                migrator
+                    .add_migrations(migrations)
+                    .wrap_err("could not add migrations")?;
+
                 let schema: jtd::Schema = migrator
-                    .schema(schema, *version)
+                    .schema(schema, version)
                     .wrap_err("could not get schema")?
                     .into();
 
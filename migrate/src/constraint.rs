@@ -0,0 +1,214 @@
+use crate::type_::Error;
+use crate::{Type, Value};
+use std::fmt::Display;
+
+/// A rule a field's values have to follow on top of their type. These only
+/// apply to new writes; data written before a constraint existed still reads
+/// fine.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Constraint {
+    Min(f64),
+    Max(f64),
+    /// In characters for strings, items for arrays, or bytes for bytes.
+    MinLength(usize),
+    MaxLength(usize),
+    /// Matches anywhere in the string, so anchor it with `^` and `$` to
+    /// match the whole thing.
+    Pattern(Pattern),
+    NonEmpty,
+}
+
+impl Constraint {
+    /// Whether this constraint means anything for values of `type_`.
+    pub fn applies_to(&self, type_: &Type) -> bool {
+        let base = match type_ {
+            Type::Nullable(inner) => inner.as_ref(),
+            type_ => type_,
+        };
+
+        match self {
+            Constraint::Min(_) | Constraint::Max(_) => matches!(base, Type::Int | Type::Float),
+            Constraint::MinLength(_) | Constraint::MaxLength(_) | Constraint::NonEmpty => {
+                matches!(base, Type::String | Type::Array(_) | Type::Bytes)
+            }
+            Constraint::Pattern(_) => matches!(base, Type::String),
+        }
+    }
+
+    /// Check a value against this constraint. Values this constraint doesn't
+    /// apply to (including nulls) pass.
+    pub fn check(&self, value: &Value) -> Result<(), Error> {
+        let ok = match (self, value) {
+            (Constraint::Min(min), Value::Int(i)) => *i as f64 >= *min,
+            (Constraint::Min(min), Value::Float(f)) => f >= min,
+            (Constraint::Max(max), Value::Int(i)) => *i as f64 <= *max,
+            (Constraint::Max(max), Value::Float(f)) => f <= max,
+            (Constraint::MinLength(min), value) => length(value).is_none_or(|len| len >= *min),
+            (Constraint::MaxLength(max), value) => length(value).is_none_or(|len| len <= *max),
+            (Constraint::NonEmpty, value) => length(value).is_none_or(|len| len > 0),
+            (Constraint::Pattern(pattern), Value::String(s)) => pattern.0.is_match(s),
+            _ => true,
+        };
+
+        if ok {
+            Ok(())
+        } else {
+            Err(Error::Constraint {
+                constraint: self.clone(),
+                got: value.clone(),
+            })
+        }
+    }
+}
+
+fn length(value: &Value) -> Option<usize> {
+    match value {
+        Value::String(s) => Some(s.chars().count()),
+        Value::Array(items) => Some(items.len()),
+        Value::Bytes(bytes) => Some(bytes.len()),
+        _ => None,
+    }
+}
+
+impl Display for Constraint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Constraint::Min(min) => write!(f, "min {min}"),
+            Constraint::Max(max) => write!(f, "max {max}"),
+            Constraint::MinLength(min) => write!(f, "min length {min}"),
+            Constraint::MaxLength(max) => write!(f, "max length {max}"),
+            Constraint::Pattern(pattern) => write!(f, "pattern /{}/", pattern.0.as_str()),
+            Constraint::NonEmpty => write!(f, "non-empty"),
+        }
+    }
+}
+
+/// A regular expression, compiled when the migration is loaded so that bad
+/// patterns fail early. Boxed since a compiled regex is much bigger than the
+/// other constraints.
+#[derive(Debug, Clone)]
+pub struct Pattern(Box<regex_lite::Regex>);
+
+impl Pattern {
+    pub fn new(pattern: &str) -> Result<Self, regex_lite::Error> {
+        Ok(Self(Box::new(regex_lite::Regex::new(pattern)?)))
+    }
+}
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
+}
+
+impl serde::Serialize for Pattern {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(self.0.as_str())
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Pattern {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let pattern = String::deserialize(deserializer)?;
+
+        Pattern::new(&pattern).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    #[test]
+    fn check_range() {
+        assert!(Constraint::Min(0.0).check(&Value::Int(0)).is_ok());
+        assert!(Constraint::Min(0.0).check(&Value::Int(-1)).is_err());
+        assert!(Constraint::Max(1.5).check(&Value::Float(1.5)).is_ok());
+        assert!(Constraint::Max(1.5).check(&Value::Float(1.6)).is_err());
+    }
+
+    #[test]
+    fn check_length() {
+        assert!(Constraint::MaxLength(3).check(&"héé".into()).is_ok());
+        assert!(Constraint::MaxLength(3).check(&"four".into()).is_err());
+        assert!(
+            Constraint::MinLength(1)
+                .check(&Value::Array(vec![1.into()]))
+                .is_ok()
+        );
+        assert!(
+            Constraint::MinLength(1)
+                .check(&Value::Bytes(Vec::new()))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn check_non_empty() {
+        assert!(Constraint::NonEmpty.check(&"a".into()).is_ok());
+        assert!(Constraint::NonEmpty.check(&"".into()).is_err());
+        assert!(
+            Constraint::NonEmpty
+                .check(&Value::Array(Vec::new()))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn check_pattern() {
+        let slug = Constraint::Pattern(Pattern::new("^[a-z-]+$").unwrap());
+
+        assert!(slug.check(&"hello-world".into()).is_ok());
+        assert!(slug.check(&"Hello World".into()).is_err());
+    }
+
+    #[test]
+    fn check_ignores_null() {
+        assert!(Constraint::NonEmpty.check(&Value::Null).is_ok());
+        assert!(Constraint::Min(1.0).check(&Value::Null).is_ok());
+    }
+
+    #[test]
+    fn applies_to() {
+        assert!(Constraint::Min(0.0).applies_to(&Type::Int));
+        assert!(Constraint::Min(0.0).applies_to(&Type::Nullable(Box::new(Type::Float))));
+        assert!(!Constraint::Min(0.0).applies_to(&Type::String));
+        assert!(Constraint::NonEmpty.applies_to(&Type::Array(Box::new(Type::Int))));
+        assert!(!Constraint::Pattern(Pattern::new(".").unwrap()).applies_to(&Type::Int));
+    }
+
+    #[test]
+    fn deserialize() {
+        let constraints: Vec<Constraint> = serde_json::from_value(json!([
+            {"min": 0},
+            {"max_length": 10},
+            {"pattern": "^a"},
+            "non_empty",
+        ]))
+        .unwrap();
+
+        assert_eq!(
+            constraints,
+            vec![
+                Constraint::Min(0.0),
+                Constraint::MaxLength(10),
+                Constraint::Pattern(Pattern::new("^a").unwrap()),
+                Constraint::NonEmpty,
+            ]
+        );
+    }
+
+    #[test]
+    fn deserialize_bad_pattern() {
+        assert!(serde_json::from_value::<Constraint>(json!({"pattern": "("})).is_err());
+    }
+}
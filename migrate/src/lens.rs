@@ -1,15 +1,50 @@
-use crate::type_::SerdeType;
-use crate::{Schema, Type, Value, value};
+use crate::template::Template;
+use crate::type_::{self, SerdeType};
+use crate::{Constraint, Schema, Type, Value, value};
 use std::collections::BTreeMap;
-use std::collections::btree_map::Entry;
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum Lens {
     Add(AddRemoveField),
     Remove(AddRemoveField),
-    Rename { from: String, to: String },
-    // TODO: value conversion
+    Rename {
+        from: String,
+        to: String,
+    },
+    Convert(Convert),
+    /// Changes the default of an existing field. `from` has to match the
+    /// current default, so the lens can be reversed.
+    #[serde(rename = "set_default")]
+    SetDefault {
+        name: String,
+        from: Value,
+        to: Value,
+    },
+    Wrap(WrapHead),
+    Head(WrapHead),
+    Hoist(HoistPlunge),
+    Plunge(HoistPlunge),
+    Split(SplitMerge),
+    Merge(SplitMerge),
+    #[serde(rename = "move_out")]
+    MoveOut(CrossTable),
+    #[serde(rename = "move_in")]
+    MoveIn(CrossTable),
+    #[serde(rename = "add_variant")]
+    AddVariant(EnumVariant),
+    #[serde(rename = "remove_variant")]
+    RemoveVariant(EnumVariant),
+    #[serde(rename = "map_variants")]
+    MapVariants(MapVariants),
+    #[serde(rename = "make_nullable")]
+    MakeNullable(Nullability),
+    #[serde(rename = "make_required")]
+    MakeRequired(Nullability),
+    #[serde(rename = "add_constraint")]
+    AddConstraint(FieldConstraint),
+    #[serde(rename = "remove_constraint")]
+    RemoveConstraint(FieldConstraint),
 }
 
 impl Lens {
@@ -21,382 +56,4041 @@ impl Lens {
                 from: to.clone(),
                 to: from.clone(),
             },
+            Lens::Convert(lens) => Lens::Convert(lens.reversed()),
+            Lens::SetDefault { name, from, to } => Lens::SetDefault {
+                name: name.clone(),
+                from: to.clone(),
+                to: from.clone(),
+            },
+            Lens::Wrap(lens) => Lens::Head(lens.clone()),
+            Lens::Head(lens) => Lens::Wrap(lens.clone()),
+            Lens::Hoist(lens) => Lens::Plunge(lens.clone()),
+            Lens::Plunge(lens) => Lens::Hoist(lens.clone()),
+            Lens::Split(lens) => Lens::Merge(lens.clone()),
+            Lens::Merge(lens) => Lens::Split(lens.clone()),
+            Lens::MoveOut(lens) => Lens::MoveIn(lens.clone()),
+            Lens::MoveIn(lens) => Lens::MoveOut(lens.clone()),
+            Lens::AddVariant(lens) => Lens::RemoveVariant(lens.clone()),
+            Lens::RemoveVariant(lens) => Lens::AddVariant(lens.clone()),
+            Lens::MapVariants(lens) => Lens::MapVariants(MapVariants {
+                name: lens.name.clone(),
+                forward: lens.backward.clone(),
+                backward: lens.forward.clone(),
+            }),
+            Lens::MakeNullable(lens) => Lens::MakeRequired(lens.clone()),
+            Lens::MakeRequired(lens) => Lens::MakeNullable(lens.clone()),
+            Lens::AddConstraint(lens) => Lens::RemoveConstraint(lens.clone()),
+            Lens::RemoveConstraint(lens) => Lens::AddConstraint(lens.clone()),
         }
     }
 
     pub fn transform_defaults(&self, defaults: &mut BTreeMap<String, Value>) -> Result<(), Error> {
+        self.transform_values(defaults, Target::Defaults)
+    }
+
+    /// Move a record's values from the shape before this lens to the shape
+    /// after it. Records may be partial (for example, a single field op) so
+    /// we only touch fields that are present, and leave filling in defaults
+    /// for missing fields to the caller, who knows the target schema.
+    pub fn transform_record(&self, record: &mut BTreeMap<String, Value>) -> Result<(), Error> {
+        self.transform_values(record, Target::Record)
+    }
+
+    pub fn transform_schema(&self, schema: &mut Schema) -> Result<(), Error> {
+        let lens = self.decoded(schema)?;
+
+        // Work on copies so that a lens that fails halfway leaves the schema
+        // as it was.
+        let (mut types, mut defaults, mut constraints) = schema.clone().into_parts();
+
+        lens.transform_constraints(&types, &mut constraints)?;
+        lens.transform_types(&mut types)?;
+        lens.transform_values(&mut defaults, Target::Defaults)?;
+
+        let transformed = Schema::from_parts(types, defaults, constraints);
+
+        // Conversions can leave a constraint on a type it doesn't fit, and
+        // new constraints have to hold for the default.
+        for (name, field) in transformed.iter() {
+            if let Some(constraint) = field
+                .constraints
+                .iter()
+                .find(|c| !c.applies_to(&field.type_))
+            {
+                return Err(Error::ConstraintNotApplicable {
+                    name: name.clone(),
+                    constraint: constraint.clone(),
+                });
+            }
+            field.validate(&field.default)?;
+        }
+
+        *schema = transformed;
+        Ok(())
+    }
+
+    /// JSON can only write bytes as base64 strings, and most lenses don't
+    /// know the type of the values they carry. Decode them against `schema`,
+    /// the schema before this lens, so that they match what records hold.
+    pub(crate) fn decoded(&self, schema: &Schema) -> Result<Lens, Error> {
+        let decode = |type_: Option<&Type>, value: &mut Value| -> Result<(), Error> {
+            if let Some(type_) = type_ {
+                *value = type_.bytes_from_json(value.clone())?;
+            }
+            Ok(())
+        };
+
+        let mut decoded = self.clone();
+        match &mut decoded {
+            Lens::SetDefault { name, from, to } => {
+                decode(type_at(schema, name), from)?;
+                decode(type_at(schema, name), to)?;
+            }
+            Lens::Wrap(lens) => decode(type_at(schema, &lens.name), &mut lens.default)?,
+            Lens::Head(lens) => {
+                let items = match type_at(schema, &lens.name) {
+                    Some(Type::Array(items)) => Some(items.as_ref()),
+                    _ => None,
+                };
+                decode(items, &mut lens.default)?;
+            }
+            Lens::MakeNullable(lens) | Lens::MakeRequired(lens) => {
+                decode(type_at(schema, &lens.name), &mut lens.default)?;
+            }
+            Lens::Convert(lens) => {
+                if let Some(fallback) = &mut lens.forward_fallback {
+                    decode(Some(&lens.to), fallback)?;
+                }
+                if let Some(fallback) = &mut lens.backward_fallback {
+                    decode(Some(&lens.from), fallback)?;
+                }
+            }
+            _ => {}
+        }
+
+        Ok(decoded)
+    }
+
+    /// Constraints live on top-level fields, keyed by name. `types` is the
+    /// schema before this lens.
+    fn transform_constraints(
+        &self,
+        types: &BTreeMap<String, Type>,
+        constraints: &mut BTreeMap<String, Vec<Constraint>>,
+    ) -> Result<(), Error> {
+        // Moving a constrained field somewhere else, or changing its shape,
+        // would silently change what the constraint means.
+        let reshaped = match self {
+            Lens::Wrap(lens) | Lens::Head(lens) => vec![&lens.name],
+            Lens::Plunge(lens) if !lens.host.contains('.') => vec![&lens.name],
+            Lens::Split(lens) => vec![&lens.name],
+            Lens::Merge(lens) if !lens.name.contains('.') => {
+                lens.template.fields().iter().collect()
+            }
+            _ => Vec::new(),
+        };
+        if let Some(name) = reshaped
+            .into_iter()
+            .find(|name| constraints.contains_key(*name))
+        {
+            return Err(Error::ConstrainedField(name.clone()));
+        }
+
         match self {
-            Lens::Add(lens) => match defaults.entry(lens.name.clone()) {
-                Entry::Vacant(vacant_entry) => {
-                    vacant_entry.insert(lens.default.clone());
-                    Ok(())
+            Lens::Remove(lens) | Lens::MoveOut(CrossTable { field: lens, .. }) => {
+                constraints.remove(&lens.name);
+            }
+            Lens::Rename { from, to } => {
+                if let Some(moved) = constraints.remove(from) {
+                    constraints.insert(to.clone(), moved);
                 }
-                Entry::Occupied(_) => Err(Error::ConflictingFieldOnAdd(lens.name.clone())),
-            },
-            Lens::Remove(lens) => match defaults.remove(&lens.name) {
-                Some(_) => Ok(()),
-                None => Err(Error::MissingFieldOnRemove(lens.name.clone())),
-            },
-            Lens::Rename { from, to } => match defaults.remove(from) {
-                Some(value) => {
-                    defaults.insert(to.clone(), value);
-                    Ok(())
+            }
+            Lens::AddConstraint(lens) => {
+                if !types.contains_key(&lens.name) {
+                    return Err(Error::MissingFieldOnConstraint(lens.name.clone()));
                 }
-                None => Err(Error::MissingFieldOnRename(from.clone())),
-            },
+
+                let existing = constraints.entry(lens.name.clone()).or_default();
+                if existing.contains(&lens.constraint) {
+                    return Err(Error::ConflictingConstraint {
+                        name: lens.name.clone(),
+                        constraint: lens.constraint.clone(),
+                    });
+                }
+                existing.push(lens.constraint.clone());
+            }
+            Lens::RemoveConstraint(lens) => {
+                let existing = constraints.entry(lens.name.clone()).or_default();
+                let Some(index) = existing.iter().position(|c| *c == lens.constraint) else {
+                    return Err(Error::MissingConstraint {
+                        name: lens.name.clone(),
+                        constraint: lens.constraint.clone(),
+                    });
+                };
+                existing.remove(index);
+            }
+            _ => {}
         }
+
+        Ok(())
     }
 
-    pub fn transform_schema(&self, schema: &mut Schema) -> Result<(), Error> {
+    fn transform_types(&self, types: &mut BTreeMap<String, Type>) -> Result<(), Error> {
         match self {
             Lens::Add(lens) => {
-                if schema.contains_key(&lens.name) {
+                let (parent, name) = split_path(&lens.name);
+                let object = object_type_at(types, &parent)?;
+
+                if object.contains_key(name) {
                     Err(Error::ConflictingFieldOnAdd(lens.name.clone()))
                 } else {
-                    schema.insert(
-                        lens.name.clone(),
-                        crate::Field {
-                            type_: lens.type_.clone(),
-                            default: lens.default.clone(),
-                        },
-                    );
+                    object.insert(name.to_string(), lens.type_.clone());
                     Ok(())
                 }
             }
             Lens::Remove(lens) => {
-                if schema.contains_key(&lens.name) {
-                    schema.remove(&lens.name);
-                    Ok(())
+                let (parent, name) = split_path(&lens.name);
+
+                match object_type_at(types, &parent)?.remove(name) {
+                    Some(_) => Ok(()),
+                    None => Err(Error::MissingFieldOnRemove(lens.name.clone())),
+                }
+            }
+            Lens::Rename { from, to } => {
+                let (parent, from_name, to_name) = split_rename(from, to)?;
+                let object = object_type_at(types, &parent)?;
+
+                match object.remove(from_name) {
+                    Some(type_) => {
+                        object.insert(to_name.to_string(), type_);
+                        Ok(())
+                    }
+                    None => Err(Error::MissingFieldOnRename(from.clone())),
+                }
+            }
+            Lens::Convert(lens) => {
+                let (parent, name) = split_path(&lens.name);
+                let type_ = object_type_at(types, &parent)?
+                    .get_mut(name)
+                    .ok_or_else(|| Error::MissingFieldOnConvert(lens.name.clone()))?;
+
+                // Conversions change the underlying type but leave
+                // nullability alone; nulls pass straight through.
+                let base = match type_ {
+                    Type::Nullable(inner) => inner.as_mut(),
+                    type_ => type_,
+                };
+
+                if *base != lens.from {
+                    return Err(Error::TypeMismatchOnConvert {
+                        name: lens.name.clone(),
+                        expected: lens.from.clone(),
+                        got: base.clone(),
+                    });
+                }
+
+                for (fallback, type_) in [
+                    (&lens.forward_fallback, &lens.to),
+                    (&lens.backward_fallback, &lens.from),
+                ] {
+                    if let Some(fallback) = fallback {
+                        type_.validate(fallback)?;
+                    }
+                }
+
+                *base = lens.to.clone();
+                Ok(())
+            }
+            Lens::SetDefault { name: path, to, .. } => {
+                let (parent, name) = split_path(path);
+                let type_ = object_type_at(types, &parent)?
+                    .get(name)
+                    .ok_or_else(|| Error::MissingFieldOnSetDefault(path.clone()))?;
+
+                type_.validate(to)?;
+                Ok(())
+            }
+            Lens::Wrap(lens) => {
+                let (parent, name) = split_path(&lens.name);
+                let type_ = object_type_at(types, &parent)?
+                    .get_mut(name)
+                    .ok_or_else(|| Error::MissingFieldOnWrap(lens.name.clone()))?;
+
+                // Nulls wrap into empty arrays, so the items are never null.
+                // For `Head` to undo this exactly, an empty array has to
+                // come back as null if and only if the field was nullable.
+                let items = match &*type_ {
+                    Type::Nullable(inner) if lens.default == Value::Null => inner.as_ref().clone(),
+                    Type::Nullable(_) => return Err(Error::InvalidWrapDefault(lens.name.clone())),
+                    _ if lens.default == Value::Null => {
+                        return Err(Error::InvalidWrapDefault(lens.name.clone()));
+                    }
+                    base => {
+                        base.validate(&lens.default)?;
+                        base.clone()
+                    }
+                };
+
+                *type_ = Type::Array(Box::new(items));
+                Ok(())
+            }
+            Lens::Head(lens) => {
+                let (parent, name) = split_path(&lens.name);
+                let type_ = object_type_at(types, &parent)?
+                    .get_mut(name)
+                    .ok_or_else(|| Error::MissingFieldOnHead(lens.name.clone()))?;
+
+                let Type::Array(items) = type_ else {
+                    return Err(Error::NotAnArray(lens.name.clone()));
+                };
+
+                let head = if lens.default == Value::Null && !items.is_nullable() {
+                    Type::Nullable(items.clone())
                 } else {
+                    items.as_ref().clone()
+                };
+                head.validate(&lens.default)?;
+
+                *type_ = head;
+                Ok(())
+            }
+            Lens::MakeNullable(lens) => {
+                let (parent, name) = split_path(&lens.name);
+                let type_ = object_type_at(types, &parent)?
+                    .get_mut(name)
+                    .ok_or_else(|| Error::MissingFieldOnNullability(lens.name.clone()))?;
+
+                if type_.is_nullable() {
+                    return Err(Error::AlreadyNullable(lens.name.clone()));
+                }
+                // Checked here too, so that the reverse is known to work.
+                type_.validate(&lens.default)?;
+
+                *type_ = Type::Nullable(Box::new(type_.clone()));
+                Ok(())
+            }
+            Lens::MakeRequired(lens) => {
+                let (parent, name) = split_path(&lens.name);
+                let type_ = object_type_at(types, &parent)?
+                    .get_mut(name)
+                    .ok_or_else(|| Error::MissingFieldOnNullability(lens.name.clone()))?;
+
+                let Type::Nullable(inner) = type_ else {
+                    return Err(Error::NotNullable(lens.name.clone()));
+                };
+                inner.validate(&lens.default)?;
+
+                *type_ = inner.as_ref().clone();
+                Ok(())
+            }
+            Lens::Hoist(lens) => move_type(types, &lens.host, &lens.inner(), &lens.outer()),
+            Lens::MoveOut(lens) => {
+                let name = lens.top_level_name()?;
+
+                match types.remove(name) {
+                    Some(type_) if type_ == lens.field.type_ => Ok(()),
+                    Some(type_) => Err(Error::TypeMismatchOnMove {
+                        name: name.to_string(),
+                        expected: lens.field.type_.clone(),
+                        got: type_,
+                    }),
+                    None => Err(Error::MissingFieldOnMove(name.to_string())),
+                }
+            }
+            Lens::MoveIn(lens) => {
+                let name = lens.top_level_name()?;
+
+                if types.contains_key(name) {
+                    return Err(Error::ConflictingFieldOnMove(name.to_string()));
+                }
+                types.insert(name.to_string(), lens.field.type_.clone());
+                Ok(())
+            }
+            Lens::Split(lens) => {
+                let (parent, name) = split_path(&lens.name);
+                let object = object_type_at(types, &parent)?;
+
+                match object.remove(name) {
+                    Some(Type::String) => {}
+                    Some(_) => return Err(Error::NotAString(lens.name.clone())),
+                    None => return Err(Error::MissingFieldOnSplit(lens.name.clone())),
+                }
+
+                for field in lens.template.fields() {
+                    if object.insert(field.clone(), Type::String).is_some() {
+                        return Err(Error::ConflictingFieldOnAdd(lens.part(field)));
+                    }
+                }
+                Ok(())
+            }
+            Lens::Merge(lens) => {
+                let (parent, name) = split_path(&lens.name);
+                let object = object_type_at(types, &parent)?;
+
+                for field in lens.template.fields() {
+                    match object.remove(field) {
+                        Some(Type::String) => {}
+                        Some(_) => return Err(Error::NotAString(lens.part(field))),
+                        None => return Err(Error::MissingFieldOnMerge(lens.part(field))),
+                    }
+                }
+
+                if object.insert(name.to_string(), Type::String).is_some() {
+                    return Err(Error::ConflictingFieldOnAdd(lens.name.clone()));
+                }
+                Ok(())
+            }
+            Lens::Plunge(lens) => move_type(types, &lens.host, &lens.outer(), &lens.inner()),
+            Lens::AddVariant(lens) => {
+                let variants = variants_at(types, &lens.name)?;
+
+                if variants.contains(&lens.variant) {
+                    return Err(Error::ConflictingVariant {
+                        name: lens.name.clone(),
+                        variant: lens.variant.clone(),
+                    });
+                }
+                lens.check_fallback(variants)?;
+
+                variants.push(lens.variant.clone());
+                Ok(())
+            }
+            Lens::RemoveVariant(lens) => {
+                let variants = variants_at(types, &lens.name)?;
+
+                let Some(index) = variants.iter().position(|v| *v == lens.variant) else {
+                    return Err(Error::MissingVariant {
+                        name: lens.name.clone(),
+                        variant: lens.variant.clone(),
+                    });
+                };
+                variants.remove(index);
+                lens.check_fallback(variants)?;
+
+                Ok(())
+            }
+            Lens::MapVariants(lens) => {
+                let variants = variants_at(types, &lens.name)?;
+
+                for variant in lens.forward.keys() {
+                    if !variants.contains(variant) {
+                        return Err(Error::MissingVariant {
+                            name: lens.name.clone(),
+                            variant: variant.clone(),
+                        });
+                    }
+                }
+
+                let mut mapped: Vec<String> = Vec::with_capacity(variants.len());
+                for variant in variants.iter() {
+                    let variant = lens.forward.get(variant).unwrap_or(variant);
+                    if !mapped.contains(variant) {
+                        mapped.push(variant.clone());
+                    }
+                }
+
+                // Old clients have to be able to read everything new ones
+                // write, so every new variant needs a way back.
+                for variant in &mapped {
+                    if !variants.contains(lens.backward.get(variant).unwrap_or(variant)) {
+                        return Err(Error::UnmappedVariant {
+                            name: lens.name.clone(),
+                            variant: variant.clone(),
+                        });
+                    }
+                }
+                for variant in lens.backward.keys() {
+                    if !mapped.contains(variant) {
+                        return Err(Error::MissingVariant {
+                            name: lens.name.clone(),
+                            variant: variant.clone(),
+                        });
+                    }
+                }
+
+                *variants = mapped;
+                Ok(())
+            }
+            Lens::AddConstraint(_) | Lens::RemoveConstraint(_) => Ok(()),
+        }
+    }
+
+    fn transform_values(
+        &self,
+        values: &mut BTreeMap<String, Value>,
+        target: Target,
+    ) -> Result<(), Error> {
+        match self {
+            Lens::Add(lens) => {
+                let (parent, name) = split_path(&lens.name);
+                let Some(object) = object_at(values, &parent) else {
+                    return Ok(());
+                };
+
+                if object.contains_key(name) {
+                    return Err(Error::ConflictingFieldOnAdd(lens.name.clone()));
+                }
+
+                // Whoever reads a record fills in missing top-level fields
+                // from the target schema, but nothing else will fill in a
+                // sub-field, so we do that here.
+                if target == Target::Defaults || !parent.is_empty() {
+                    object.insert(name.to_string(), lens.default.clone());
+                }
+                Ok(())
+            }
+            Lens::Remove(lens) => {
+                let (parent, name) = split_path(&lens.name);
+                let Some(object) = object_at(values, &parent) else {
+                    return Ok(());
+                };
+
+                if object.remove(name).is_none() && target.requires(&parent) {
                     Err(Error::MissingFieldOnRemove(lens.name.clone()))
+                } else {
+                    Ok(())
+                }
+            }
+            Lens::Rename { from, to } => {
+                let (parent, from_name, to_name) = split_rename(from, to)?;
+                let Some(object) = object_at(values, &parent) else {
+                    return Ok(());
+                };
+
+                match object.remove(from_name) {
+                    Some(value) => {
+                        object.insert(to_name.to_string(), value);
+                        Ok(())
+                    }
+                    None if target.requires(&parent) => {
+                        Err(Error::MissingFieldOnRename(from.clone()))
+                    }
+                    None => Ok(()),
+                }
+            }
+            Lens::Convert(lens) => {
+                let (parent, name) = split_path(&lens.name);
+                let Some(object) = object_at(values, &parent) else {
+                    return Ok(());
+                };
+
+                match object.get_mut(name) {
+                    Some(value) => {
+                        *value = lens.forward(value)?;
+                        Ok(())
+                    }
+                    None if target.requires(&parent) => {
+                        Err(Error::MissingFieldOnConvert(lens.name.clone()))
+                    }
+                    None => Ok(()),
+                }
+            }
+            // Records keep whatever they have. Fields they never wrote get
+            // filled in from the reader's own schema.
+            Lens::SetDefault { .. } if target == Target::Record => Ok(()),
+            Lens::SetDefault {
+                name: path,
+                from,
+                to,
+            } => {
+                let (parent, name) = split_path(path);
+                let Some(object) = object_at(values, &parent) else {
+                    return Ok(());
+                };
+
+                // Nullable sub-fields can be left out, which means null.
+                let current = object.get(name).unwrap_or(&Value::Null);
+                if current != from {
+                    return Err(Error::DefaultMismatch {
+                        name: path.clone(),
+                        expected: from.clone(),
+                        got: current.clone(),
+                    });
+                }
+
+                object.insert(name.to_string(), to.clone());
+                Ok(())
+            }
+            Lens::Wrap(lens) => {
+                let (parent, name) = split_path(&lens.name);
+                let Some(object) = object_at(values, &parent) else {
+                    return Ok(());
+                };
+
+                match object.get_mut(name) {
+                    Some(value) => {
+                        *value = match std::mem::replace(value, Value::Null) {
+                            Value::Null => Value::Array(Vec::new()),
+                            value => Value::Array(vec![value]),
+                        };
+                        Ok(())
+                    }
+                    None if target.requires(&parent) => {
+                        Err(Error::MissingFieldOnWrap(lens.name.clone()))
+                    }
+                    None => Ok(()),
+                }
+            }
+            Lens::Head(lens) => {
+                let (parent, name) = split_path(&lens.name);
+                let Some(object) = object_at(values, &parent) else {
+                    return Ok(());
+                };
+
+                match object.get_mut(name) {
+                    Some(value) => {
+                        *value = match std::mem::replace(value, Value::Null) {
+                            Value::Array(items) => {
+                                items.into_iter().next().unwrap_or(lens.default.clone())
+                            }
+                            _ => lens.default.clone(),
+                        };
+                        Ok(())
+                    }
+                    None if target.requires(&parent) => {
+                        Err(Error::MissingFieldOnHead(lens.name.clone()))
+                    }
+                    None => Ok(()),
+                }
+            }
+            // Every existing value is still valid.
+            Lens::MakeNullable(_) => Ok(()),
+            Lens::MakeRequired(lens) => {
+                let (parent, name) = split_path(&lens.name);
+                let Some(object) = object_at(values, &parent) else {
+                    return Ok(());
+                };
+
+                match object.get_mut(name) {
+                    Some(value) if *value == Value::Null => {
+                        *value = lens.default.clone();
+                        Ok(())
+                    }
+                    Some(_) => Ok(()),
+                    None if target.requires(&parent) => {
+                        Err(Error::MissingFieldOnNullability(lens.name.clone()))
+                    }
+                    // Nullable sub-fields can be left out of objects, but
+                    // required ones can't.
+                    None if !parent.is_empty() => {
+                        object.insert(name.to_string(), lens.default.clone());
+                        Ok(())
+                    }
+                    None => Ok(()),
                 }
             }
-            Lens::Rename { from, to } => match schema.remove(from) {
-                Some(value) => {
-                    schema.insert(to.clone(), value);
+            Lens::Hoist(lens) => move_value(values, &lens.inner(), &lens.outer(), target),
+            // The value now belongs to a row in the other table.
+            Lens::MoveOut(lens) => {
+                let name = &lens.field.name;
+
+                if values.remove(name).is_none() && target == Target::Defaults {
+                    Err(Error::MissingFieldOnMove(name.clone()))
+                } else {
                     Ok(())
                 }
-                None => Err(Error::MissingFieldOnRename(from.clone())),
-            },
+            }
+            // Records only ever get the value from a row in the other table,
+            // which is up to whoever reads them.
+            Lens::MoveIn(_) if target == Target::Record => Ok(()),
+            Lens::MoveIn(lens) => {
+                let name = &lens.field.name;
+
+                if values.contains_key(name) {
+                    return Err(Error::ConflictingFieldOnMove(name.clone()));
+                }
+                values.insert(name.clone(), lens.field.default.clone());
+                Ok(())
+            }
+            Lens::Split(lens) => {
+                let (parent, name) = split_path(&lens.name);
+                let Some(object) = object_at(values, &parent) else {
+                    return Ok(());
+                };
+
+                match object.remove(name) {
+                    Some(Value::String(combined)) => {
+                        let parts = lens.template.split(&combined);
+                        for (field, part) in lens.template.fields().iter().zip(parts) {
+                            object.insert(field.clone(), Value::String(part));
+                        }
+                        Ok(())
+                    }
+                    Some(value) => Err(Error::CouldNotConvert {
+                        value,
+                        to: Type::String,
+                    }),
+                    None if target.requires(&parent) => {
+                        Err(Error::MissingFieldOnSplit(lens.name.clone()))
+                    }
+                    None => Ok(()),
+                }
+            }
+            Lens::Merge(lens) => {
+                let (parent, name) = split_path(&lens.name);
+                let Some(object) = object_at(values, &parent) else {
+                    return Ok(());
+                };
+
+                let fields = lens.template.fields();
+                if let Some(missing) = fields.iter().find(|field| !object.contains_key(*field)) {
+                    if target.requires(&parent) {
+                        return Err(Error::MissingFieldOnMerge(lens.part(missing)));
+                    }
+
+                    // A write that only touched some of the parts has
+                    // nothing to fill in the others with, so it can't be
+                    // merged.
+                    for field in fields {
+                        object.remove(field);
+                    }
+                    return Ok(());
+                }
+
+                let parts = fields
+                    .iter()
+                    .map(|field| match object.remove(field) {
+                        Some(Value::String(part)) => Ok(part),
+                        value => Err(Error::CouldNotConvert {
+                            value: value.unwrap_or(Value::Null),
+                            to: Type::String,
+                        }),
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                let parts: Vec<&str> = parts.iter().map(String::as_str).collect();
+
+                object.insert(name.to_string(), lens.template.render(&parts).into());
+                Ok(())
+            }
+            Lens::Plunge(lens) => move_value(values, &lens.outer(), &lens.inner(), target),
+            // Every existing value is still a valid variant.
+            Lens::AddVariant(_) => Ok(()),
+            Lens::RemoveVariant(lens) => map_variant(values, &lens.name, target, |variant| {
+                (*variant == lens.variant).then_some(&lens.fallback)
+            }),
+            Lens::MapVariants(lens) => map_variant(values, &lens.name, target, |variant| {
+                lens.forward.get(variant)
+            }),
+            // Constraints only apply to new writes, so existing values stay
+            // as they are.
+            Lens::AddConstraint(_) | Lens::RemoveConstraint(_) => Ok(()),
         }
     }
 }
 
-#[derive(Debug, thiserror::Error, PartialEq)]
-pub enum Error {
-    #[error("Unsupported schema type. We can only transform `properties` schemas.")]
-    UnsupportedSchemaType,
-    #[error("Tried to add `{0}`, but it already exists.")]
-    ConflictingFieldOnAdd(String),
-    #[error("Tried to remove `{0}`, but it was not present.")]
-    MissingFieldOnRemove(String),
-    #[error("Tried to rename `{0}`, but it was not present.")]
-    MissingFieldOnRename(String),
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Target {
+    Defaults,
+    Record,
 }
 
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
-pub struct SerdeAddRemoveField {
-    name: String,
-    #[serde(rename = "type")]
-    type_: SerdeType,
-    #[serde(default)]
-    nullable: bool,
-    #[serde(default = "value::Value::null")]
-    default: value::Value,
+impl Target {
+    /// Defaults always have every top-level field, so a missing one means
+    /// the lens doesn't fit. Records can be partial, and objects can leave
+    /// out nullable sub-fields, so there a missing field is fine.
+    fn requires(&self, parent: &[&str]) -> bool {
+        *self == Target::Defaults && parent.is_empty()
+    }
 }
 
-#[derive(Debug, Clone, PartialEq)]
-pub struct AddRemoveField {
-    pub name: String,
-    pub type_: Type,
-    pub default: value::Value,
+/// Lenses address fields inside objects with dotted paths, like
+/// `address.street`. Split one into the path to the containing object and
+/// the field's own name.
+fn split_path(path: &str) -> (Vec<&str>, &str) {
+    let mut parts: Vec<&str> = path.split('.').collect();
+    let name = parts.pop().unwrap_or_default();
+
+    (parts, name)
 }
 
-impl<'de> serde::Deserialize<'de> for AddRemoveField {
-    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
-    where
-        D: serde::Deserializer<'de>,
-    {
-        let SerdeAddRemoveField {
-            name,
-            type_,
-            nullable,
-            default,
-        } = SerdeAddRemoveField::deserialize(deserializer)?;
+fn split_rename<'a>(from: &'a str, to: &'a str) -> Result<(Vec<&'a str>, &'a str, &'a str), Error> {
+    let (from_parent, from_name) = split_path(from);
+    let (to_parent, to_name) = split_path(to);
 
-        let final_type = Type::from_serde(type_, nullable);
+    if from_parent == to_parent {
+        Ok((from_parent, from_name, to_name))
+    } else {
+        Err(Error::RenameAcrossObjects {
+            from: from.to_string(),
+            to: to.to_string(),
+        })
+    }
+}
 
-        final_type
-            .validate(&default)
-            .map_err(serde::de::Error::custom)?;
+/// Find the type of the field at `path`, if there is one, looking through
+/// nullable objects on the way.
+fn type_at<'a>(schema: &'a Schema, path: &str) -> Option<&'a Type> {
+    let mut names = path.split('.');
+    let mut type_ = &schema.get(names.next()?)?.type_;
 
-        Ok(Self {
-            name,
-            type_: final_type,
-            default,
-        })
+    for name in names {
+        let fields = match type_ {
+            Type::Object(fields) => fields,
+            Type::Nullable(inner) => match inner.as_ref() {
+                Type::Object(fields) => fields,
+                _ => return None,
+            },
+            _ => return None,
+        };
+        type_ = fields.get(name)?;
     }
+
+    Some(type_)
 }
 
-impl serde::Serialize for AddRemoveField {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: serde::Serializer,
-    {
-        let converted = SerdeAddRemoveField {
-            name: self.name.clone(),
-            type_: self.type_.to_serde(),
-            nullable: self.type_.is_nullable(),
-            default: self.default.clone(),
+/// Find the sub-fields of the object at `path`, looking through
+/// nullability.
+fn object_type_at<'a>(
+    mut types: &'a mut BTreeMap<String, Type>,
+    path: &[&str],
+) -> Result<&'a mut BTreeMap<String, Type>, Error> {
+    for (i, name) in path.iter().enumerate() {
+        let here = || path[..=i].join(".");
+
+        let type_ = match types.get_mut(*name) {
+            Some(Type::Nullable(inner)) => inner.as_mut(),
+            Some(type_) => type_,
+            None => return Err(Error::MissingParent(here())),
         };
 
-        converted.serialize(serializer)
+        types = match type_ {
+            Type::Object(fields) => fields,
+            _ => return Err(Error::NotAnObject(here())),
+        };
     }
+
+    Ok(types)
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::type_::SerdeType;
-    use crate::value::Value;
-    use serde_json::json;
+/// Move a field's type between a host object and the object containing it.
+fn move_type(
+    types: &mut BTreeMap<String, Type>,
+    host: &str,
+    from: &str,
+    to: &str,
+) -> Result<(), Error> {
+    // A null host would swallow anything plunged into it, and leave nothing
+    // to hoist back out.
+    let (host_parent, host_name) = split_path(host);
+    match object_type_at(types, &host_parent)?.get(host_name) {
+        Some(Type::Object(_)) => {}
+        Some(Type::Nullable(inner)) if matches!(inner.as_ref(), Type::Object(_)) => {
+            return Err(Error::NullableHost(host.to_string()));
+        }
+        Some(_) => return Err(Error::NotAnObject(host.to_string())),
+        None => return Err(Error::MissingParent(host.to_string())),
+    }
+
+    let (from_parent, name) = split_path(from);
+    let (to_parent, _) = split_path(to);
+
+    if object_type_at(types, &to_parent)?.contains_key(name) {
+        return Err(Error::ConflictingFieldOnMove(to.to_string()));
+    }
+
+    let type_ = object_type_at(types, &from_parent)?
+        .remove(name)
+        .ok_or_else(|| Error::MissingFieldOnMove(from.to_string()))?;
+    object_type_at(types, &to_parent)?.insert(name.to_string(), type_);
+
+    Ok(())
+}
+
+fn move_value(
+    values: &mut BTreeMap<String, Value>,
+    from: &str,
+    to: &str,
+    target: Target,
+) -> Result<(), Error> {
+    let (from_parent, name) = split_path(from);
+    let (to_parent, _) = split_path(to);
+
+    let Some(value) = object_at(values, &from_parent).and_then(|object| object.remove(name)) else {
+        return if target.requires(&from_parent) {
+            Err(Error::MissingFieldOnMove(from.to_string()))
+        } else {
+            Ok(())
+        };
+    };
+
+    // A partial record may not have the destination object yet, so we start
+    // one rather than dropping the value. Readers lay it over the object the
+    // row already has (see `Value::overlay`).
+    let destination = match target {
+        Target::Defaults => object_at(values, &to_parent),
+        Target::Record => object_at_or_insert(values, &to_parent),
+    };
+
+    match destination {
+        Some(object) if object.contains_key(name) => {
+            Err(Error::ConflictingFieldOnMove(to.to_string()))
+        }
+        Some(object) => {
+            object.insert(name.to_string(), value);
+            Ok(())
+        }
+        None => Ok(()),
+    }
+}
+
+/// Find the variants of the enum at `path`, looking through nullability.
+fn variants_at<'a>(
+    types: &'a mut BTreeMap<String, Type>,
+    path: &str,
+) -> Result<&'a mut Vec<String>, Error> {
+    let (parent, name) = split_path(path);
+
+    let type_ = match object_type_at(types, &parent)?.get_mut(name) {
+        Some(Type::Nullable(inner)) => inner.as_mut(),
+        Some(type_) => type_,
+        None => return Err(Error::MissingFieldOnEnum(path.to_string())),
+    };
+
+    match type_ {
+        Type::Enum(variants) => Ok(variants),
+        _ => Err(Error::NotAnEnum(path.to_string())),
+    }
+}
+
+/// Replace the variant at `path` with whatever `map` gives back for it, if
+/// anything.
+fn map_variant<'a>(
+    values: &mut BTreeMap<String, Value>,
+    path: &str,
+    target: Target,
+    map: impl Fn(&str) -> Option<&'a String>,
+) -> Result<(), Error> {
+    let (parent, name) = split_path(path);
+    let Some(object) = object_at(values, &parent) else {
+        return Ok(());
+    };
+
+    match object.get_mut(name) {
+        Some(Value::String(variant)) => {
+            if let Some(mapped) = map(variant) {
+                *variant = mapped.clone();
+            }
+            Ok(())
+        }
+        Some(_) => Ok(()),
+        None if target.requires(&parent) => Err(Error::MissingFieldOnEnum(path.to_string())),
+        None => Ok(()),
+    }
+}
+
+/// Find the values of the object at `path`, if there is one. Objects can be
+/// null or left out entirely, in which case there's nothing to change.
+fn object_at<'a>(
+    mut values: &'a mut BTreeMap<String, Value>,
+    path: &[&str],
+) -> Option<&'a mut BTreeMap<String, Value>> {
+    for name in path {
+        values = match values.get_mut(*name) {
+            Some(Value::Object(fields)) => fields,
+            _ => return None,
+        };
+    }
+
+    Some(values)
+}
+
+fn object_at_or_insert<'a>(
+    mut values: &'a mut BTreeMap<String, Value>,
+    path: &[&str],
+) -> Option<&'a mut BTreeMap<String, Value>> {
+    for name in path {
+        values = match values
+            .entry(name.to_string())
+            .or_insert_with(|| Value::Object(BTreeMap::new()))
+        {
+            Value::Object(fields) => fields,
+            _ => return None,
+        };
+    }
+
+    Some(values)
+}
+
+#[derive(Debug, thiserror::Error, PartialEq)]
+pub enum Error {
+    #[error("Unsupported schema type. We can only transform `properties` schemas.")]
+    UnsupportedSchemaType,
+    #[error("Tried to add `{0}`, but it already exists.")]
+    ConflictingFieldOnAdd(String),
+    #[error("Tried to remove `{0}`, but it was not present.")]
+    MissingFieldOnRemove(String),
+    #[error("Tried to rename `{0}`, but it was not present.")]
+    MissingFieldOnRename(String),
+    #[error("Tried to convert `{0}`, but it was not present.")]
+    MissingFieldOnConvert(String),
+    #[error("Tried to convert `{name}` from {expected}, but it was {got}.")]
+    TypeMismatchOnConvert {
+        name: String,
+        expected: Type,
+        got: Type,
+    },
+    #[error("Tried to reach `{0}`, but it was not present.")]
+    MissingParent(String),
+    #[error("Tried to reach inside `{0}`, but it is not an object.")]
+    NotAnObject(String),
+    #[error("Can't rename `{from}` to `{to}`, since they're in different objects.")]
+    RenameAcrossObjects { from: String, to: String },
+    #[error("Tried to change the default of `{0}`, but it was not present.")]
+    MissingFieldOnSetDefault(String),
+    #[error("Expected the default of `{name}` to be `{expected}`, but it was `{got}`.")]
+    DefaultMismatch {
+        name: String,
+        expected: Value,
+        got: Value,
+    },
+    #[error("Tried to wrap `{0}`, but it was not present.")]
+    MissingFieldOnWrap(String),
+    #[error("Tried to take the head of `{0}`, but it was not present.")]
+    MissingFieldOnHead(String),
+    #[error("Tried to take the head of `{0}`, but it is not an array.")]
+    NotAnArray(String),
+    #[error(
+        "The default for wrapping `{0}` must be null if the field is nullable, and a valid value if not."
+    )]
+    InvalidWrapDefault(String),
+    #[error("{0}")]
+    InvalidDefault(#[from] type_::Error),
+    #[error("Tried to move `{0}`, but it was not present.")]
+    MissingFieldOnMove(String),
+    #[error("Tried to move a field to `{0}`, but it already exists.")]
+    ConflictingFieldOnMove(String),
+    #[error("Can't move fields in or out of `{0}`, since it can be null.")]
+    NullableHost(String),
+    #[error("Tried to move `{name}` as {expected}, but it was {got}.")]
+    TypeMismatchOnMove {
+        name: String,
+        expected: Type,
+        got: Type,
+    },
+    #[error("Can only move top-level fields between tables, but `{0}` is nested.")]
+    NestedMove(String),
+    #[error("Tried to change the variants of `{0}`, but it was not present.")]
+    MissingFieldOnEnum(String),
+    #[error("Tried to change the variants of `{0}`, but it is not an enum.")]
+    NotAnEnum(String),
+    #[error("Tried to add `{variant}` to `{name}`, but it already exists.")]
+    ConflictingVariant { name: String, variant: String },
+    #[error("Expected `{name}` to have a `{variant}` variant, but it doesn't.")]
+    MissingVariant { name: String, variant: String },
+    #[error("The fallback for `{name}` must be one of its other variants, but `{fallback}` isn't.")]
+    InvalidFallback { name: String, fallback: String },
+    #[error("`{variant}` in `{name}` has no mapping back to a previous variant.")]
+    UnmappedVariant { name: String, variant: String },
+    #[error("Can't cast {from} to {to}.")]
+    UnsupportedCast { from: Type, to: Type },
+    #[error("Could not convert `{value}` to {to}.")]
+    CouldNotConvert { value: Value, to: Type },
+    #[error("Tried to change whether `{0}` can be null, but it was not present.")]
+    MissingFieldOnNullability(String),
+    #[error("Tried to make `{0}` nullable, but it already is.")]
+    AlreadyNullable(String),
+    #[error("Tried to make `{0}` required, but it already is.")]
+    NotNullable(String),
+    #[error("Tried to split `{0}`, but it was not present.")]
+    MissingFieldOnSplit(String),
+    #[error("Tried to merge `{0}`, but it was not present.")]
+    MissingFieldOnMerge(String),
+    #[error("Can only split and merge strings, but `{0}` is not a string.")]
+    NotAString(String),
+    #[error("Tried to constrain `{0}`, but it was not present.")]
+    MissingFieldOnConstraint(String),
+    #[error("Tried to add {constraint} to `{name}`, but it already has it.")]
+    ConflictingConstraint {
+        name: String,
+        constraint: Constraint,
+    },
+    #[error("Expected `{name}` to have {constraint}, but it doesn't.")]
+    MissingConstraint {
+        name: String,
+        constraint: Constraint,
+    },
+    #[error("{constraint} doesn't make sense for `{name}`.")]
+    ConstraintNotApplicable {
+        name: String,
+        constraint: Constraint,
+    },
+    #[error("Remove the constraints on `{0}` before moving or wrapping it.")]
+    ConstrainedField(String),
+}
+
+/// Changes a field's type. Since clients on either side of the migration
+/// keep writing, we need mappings in both directions: `forward` takes
+/// values from `from` to `to`, and `backward` takes them back. Values a
+/// mapping can't handle become the matching fallback, if there is one;
+/// otherwise they're an error.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Convert {
+    pub name: String,
+    pub from: Type,
+    pub to: Type,
+    pub forward: Mapping,
+    pub backward: Mapping,
+    pub forward_fallback: Option<Value>,
+    pub backward_fallback: Option<Value>,
+}
+
+impl Convert {
+    pub fn reversed(&self) -> Self {
+        Self {
+            name: self.name.clone(),
+            from: self.to.clone(),
+            to: self.from.clone(),
+            forward: self.backward.clone(),
+            backward: self.forward.clone(),
+            forward_fallback: self.backward_fallback.clone(),
+            backward_fallback: self.forward_fallback.clone(),
+        }
+    }
+
+    fn forward(&self, value: &Value) -> Result<Value, Error> {
+        self.forward
+            .apply(value, &self.to)
+            .or_else(|err| self.forward_fallback.clone().ok_or(err))
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Mapping {
+    /// Use the built-in conversion between the two types. Floats are
+    /// truncated towards zero when cast to ints, and strings must parse
+    /// cleanly as the target type.
+    Cast,
+    /// Look values up in a list of `[from, to]` pairs. Values without an
+    /// entry can't be converted.
+    Table(Vec<(Value, Value)>),
+}
+
+impl Mapping {
+    /// Make sure this mapping can produce `to` values from `from` values, as
+    /// far as we can tell without seeing the actual data.
+    fn check(&self, from: &Type, to: &Type) -> Result<(), String> {
+        match self {
+            Mapping::Cast => {
+                if can_cast(from, to) {
+                    Ok(())
+                } else {
+                    Err(Error::UnsupportedCast {
+                        from: from.clone(),
+                        to: to.clone(),
+                    }
+                    .to_string())
+                }
+            }
+            Mapping::Table(entries) => {
+                for (key, value) in entries {
+                    from.validate(key).map_err(|err| err.to_string())?;
+                    to.validate(value).map_err(|err| err.to_string())?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn apply(&self, value: &Value, to: &Type) -> Result<Value, Error> {
+        if *value == Value::Null {
+            return Ok(Value::Null);
+        }
+
+        let converted = match self {
+            Mapping::Cast => cast(value, to),
+            Mapping::Table(entries) => entries
+                .iter()
+                .find(|(key, _)| key == value)
+                .map(|(_, converted)| converted.clone()),
+        };
+
+        converted.ok_or_else(|| Error::CouldNotConvert {
+            value: value.clone(),
+            to: to.clone(),
+        })
+    }
+}
+
+fn can_cast(from: &Type, to: &Type) -> bool {
+    matches!(
+        (from, to),
+        (
+            Type::String,
+            Type::String | Type::Int | Type::Float | Type::Bool
+        ) | (
+            Type::Int,
+            Type::String | Type::Int | Type::Float | Type::Bool
+        ) | (Type::Float, Type::String | Type::Int | Type::Float)
+            | (Type::Bool, Type::String | Type::Int | Type::Bool)
+            | (Type::String, Type::Enum(_))
+            | (Type::Enum(_), Type::String)
+            | (Type::String, Type::Timestamp | Type::Date | Type::Uuid)
+            | (Type::Timestamp | Type::Date | Type::Uuid, Type::String)
+    )
+}
+
+fn cast(value: &Value, to: &Type) -> Option<Value> {
+    match (value, to) {
+        (Value::String(s), Type::String) => Some(s.clone().into()),
+        (Value::String(s), Type::Int) => s.parse::<i64>().ok().map(Value::Int),
+        (Value::String(s), Type::Float) => s.parse::<f64>().ok().map(Value::Float),
+        (Value::String(s), Type::Bool) => s.parse::<bool>().ok().map(Value::Bool),
+        (Value::String(s), Type::Enum(variants)) => variants.contains(s).then(|| s.clone().into()),
+        // These are all strings underneath, so all we need to do is check.
+        (Value::String(_), Type::Timestamp | Type::Date | Type::Uuid) => {
+            to.validate(value).ok().map(|()| value.clone())
+        }
+        (Value::Int(i), Type::String) => Some(i.to_string().into()),
+        (Value::Int(i), Type::Int) => Some(Value::Int(*i)),
+        (Value::Int(i), Type::Float) => Some(Value::Float(*i as f64)),
+        (Value::Int(i), Type::Bool) => Some(Value::Bool(*i != 0)),
+        (Value::Float(f), Type::String) => Some(f.to_string().into()),
+        (Value::Float(f), Type::Int) => {
+            let truncated = f.trunc();
+            if truncated.is_finite() && truncated >= i64::MIN as f64 && truncated < i64::MAX as f64
+            {
+                Some(Value::Int(truncated as i64))
+            } else {
+                None
+            }
+        }
+        (Value::Float(f), Type::Float) => Some(Value::Float(*f)),
+        (Value::Bool(b), Type::String) => Some(b.to_string().into()),
+        (Value::Bool(b), Type::Int) => Some(Value::Int(i64::from(*b))),
+        (Value::Bool(b), Type::Bool) => Some(Value::Bool(*b)),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
+pub struct SerdeConvert {
+    name: String,
+    from: SerdeType,
+    to: SerdeType,
+    forward: Mapping,
+    backward: Mapping,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    forward_fallback: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    backward_fallback: Option<Value>,
+}
+
+impl<'de> serde::Deserialize<'de> for Convert {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let SerdeConvert {
+            name,
+            from,
+            to,
+            forward,
+            backward,
+            forward_fallback,
+            backward_fallback,
+        } = SerdeConvert::deserialize(deserializer)?;
+
+        let from = Type::from(from);
+        let to = Type::from(to);
+
+        forward
+            .check(&from, &to)
+            .map_err(serde::de::Error::custom)?;
+        backward
+            .check(&to, &from)
+            .map_err(serde::de::Error::custom)?;
+
+        Ok(Self {
+            name,
+            from,
+            to,
+            forward,
+            backward,
+            forward_fallback,
+            backward_fallback,
+        })
+    }
+}
+
+impl serde::Serialize for Convert {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let converted = SerdeConvert {
+            name: self.name.clone(),
+            from: self.from.to_serde(),
+            to: self.to.to_serde(),
+            forward: self.forward.clone(),
+            backward: self.backward.clone(),
+            forward_fallback: self.forward_fallback.clone(),
+            backward_fallback: self.backward_fallback.clone(),
+        };
+
+        converted.serialize(serializer)
+    }
+}
+
+/// Turns a single value into a one-item array (`Wrap`), or an array into its
+/// first item (`Head`). `default` is what an empty array turns into.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
+pub struct WrapHead {
+    pub name: String,
+    #[serde(default = "value::Value::null")]
+    pub default: value::Value,
+}
+
+/// Adds or removes one variant of an enum. Values using a removed variant
+/// become `fallback`, which has to be one of the remaining variants.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
+pub struct EnumVariant {
+    pub name: String,
+    pub variant: String,
+    pub fallback: String,
+}
+
+impl EnumVariant {
+    fn check_fallback(&self, variants: &[String]) -> Result<(), Error> {
+        if variants.contains(&self.fallback) {
+            Ok(())
+        } else {
+            Err(Error::InvalidFallback {
+                name: self.name.clone(),
+                fallback: self.fallback.clone(),
+            })
+        }
+    }
+}
+
+/// Renames or merges enum variants. Variants left out of `forward` stay as
+/// they are; `backward` says where each new variant goes for old clients.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
+pub struct MapVariants {
+    pub name: String,
+    #[serde(default)]
+    pub forward: BTreeMap<String, String>,
+    #[serde(default)]
+    pub backward: BTreeMap<String, String>,
+}
+
+/// Splits the string field `name` into one string field per placeholder in
+/// `template` (`Split`), or renders those fields into `name` (`Merge`). The
+/// parts live next to `name`, in the same object.
+///
+/// Merging needs every part, so a record that only has some of them loses
+/// them. Readers should lay partial writes over the rest of the row first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SplitMerge {
+    pub name: String,
+    pub template: Template,
+}
+
+impl SplitMerge {
+    /// The full path of one of the parts.
+    fn part(&self, field: &str) -> String {
+        match self.name.rsplit_once('.') {
+            Some((parent, _)) => format!("{parent}.{field}"),
+            None => field.to_string(),
+        }
+    }
+}
+
+/// Templates can be given in full, or as a list of fields and a separator.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
+pub struct SerdeSplitMerge {
+    name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    template: Option<Template>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    fields: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    separator: Option<String>,
+}
+
+impl<'de> serde::Deserialize<'de> for SplitMerge {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let SerdeSplitMerge {
+            name,
+            template,
+            fields,
+            separator,
+        } = SerdeSplitMerge::deserialize(deserializer)?;
+
+        let template = match (template, fields, separator) {
+            (Some(template), None, None) => template,
+            (None, Some(fields), Some(separator)) => {
+                Template::separated(fields, &separator).map_err(serde::de::Error::custom)?
+            }
+            _ => {
+                return Err(serde::de::Error::custom(
+                    "expected either `template`, or `fields` and `separator`",
+                ));
+            }
+        };
+
+        Ok(Self { name, template })
+    }
+}
+
+impl serde::Serialize for SplitMerge {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let converted = SerdeSplitMerge {
+            name: self.name.clone(),
+            template: Some(self.template.clone()),
+            fields: None,
+            separator: None,
+        };
+
+        converted.serialize(serializer)
+    }
+}
+
+/// Moves a top-level field from the `from` table to the `to` table. Rows are
+/// matched up by `reference`, a field in `to` holding the ID of the `from` row
+/// a value belongs with.
+///
+/// `MoveOut` goes in a migration for `from` and `MoveIn` in one for `to`.
+/// Neither makes sense on its own, so they have to be added together with
+/// `Migrator::add_migrations`.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
+pub struct CrossTable {
+    #[serde(flatten)]
+    pub field: AddRemoveField,
+    pub from: String,
+    pub to: String,
+    pub reference: String,
+}
+
+impl CrossTable {
+    fn top_level_name(&self) -> Result<&str, Error> {
+        if self.field.name.contains('.') {
+            Err(Error::NestedMove(self.field.name.clone()))
+        } else {
+            Ok(&self.field.name)
+        }
+    }
+}
+
+/// Lets a field be null (`MakeNullable`) or stops it (`MakeRequired`). Going
+/// to required, nulls become `default`, so it has to be a valid non-null
+/// value either way.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
+pub struct Nullability {
+    pub name: String,
+    pub default: value::Value,
+}
+
+/// Adds or removes one constraint on a top-level field.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
+pub struct FieldConstraint {
+    pub name: String,
+    pub constraint: Constraint,
+}
+
+/// Moves the field `name` out of the object at `host` and into the object
+/// containing it (`Hoist`), or the other way around (`Plunge`).
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
+pub struct HoistPlunge {
+    pub host: String,
+    pub name: String,
+}
+
+impl HoistPlunge {
+    /// Where the field lives when it's inside the host.
+    fn inner(&self) -> String {
+        format!("{}.{}", self.host, self.name)
+    }
+
+    /// Where the field lives when it's next to the host.
+    fn outer(&self) -> String {
+        match self.host.rsplit_once('.') {
+            Some((parent, _)) => format!("{parent}.{}", self.name),
+            None => self.name.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
+pub struct SerdeAddRemoveField {
+    name: String,
+    #[serde(rename = "type")]
+    type_: SerdeType,
+    #[serde(default)]
+    nullable: bool,
+    #[serde(default = "value::Value::null")]
+    default: value::Value,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AddRemoveField {
+    pub name: String,
+    pub type_: Type,
+    pub default: value::Value,
+}
+
+impl<'de> serde::Deserialize<'de> for AddRemoveField {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let SerdeAddRemoveField {
+            name,
+            type_,
+            nullable,
+            default,
+        } = SerdeAddRemoveField::deserialize(deserializer)?;
+
+        let final_type = Type::from_serde(type_, nullable);
+        let default = final_type
+            .bytes_from_json(default)
+            .map_err(serde::de::Error::custom)?;
+
+        final_type
+            .validate(&default)
+            .map_err(serde::de::Error::custom)?;
+
+        Ok(Self {
+            name,
+            type_: final_type,
+            default,
+        })
+    }
+}
+
+impl serde::Serialize for AddRemoveField {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let converted = SerdeAddRemoveField {
+            name: self.name.clone(),
+            type_: self.type_.to_serde(),
+            nullable: self.type_.is_nullable(),
+            default: self.type_.bytes_to_json(self.default.clone()),
+        };
+
+        converted.serialize(serializer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::type_::SerdeType;
+    use crate::value::Value;
+    use serde_json::json;
+
+    fn bool_to_status() -> Convert {
+        Convert {
+            name: "status".into(),
+            from: Type::Bool,
+            to: Type::String,
+            forward: Mapping::Table(vec![
+                (true.into(), "done".into()),
+                (false.into(), "todo".into()),
+            ]),
+            backward: Mapping::Table(vec![
+                ("done".into(), true.into()),
+                ("todo".into(), false.into()),
+            ]),
+            forward_fallback: None,
+            backward_fallback: None,
+        }
+    }
+
+    mod transform_defaults {
+        use super::*;
+        use pretty_assertions::assert_eq;
+
+        #[test]
+        fn add_field_success() {
+            let mut defaults = BTreeMap::new();
+
+            let lens = Lens::Add(AddRemoveField {
+                name: "test".into(),
+                type_: Type::String,
+                default: "default_value".into(),
+            });
+
+            lens.transform_defaults(&mut defaults).unwrap();
+
+            let mut expected = BTreeMap::new();
+            expected.insert("test".to_string(), "default_value".into());
+            assert_eq!(defaults, expected);
+        }
+
+        #[test]
+        fn add_field_conflict() {
+            let mut defaults = BTreeMap::new();
+            defaults.insert("test".to_string(), "existing_value".into());
+
+            let lens = Lens::Add(AddRemoveField {
+                name: "test".into(),
+                type_: Type::String,
+                default: "default_value".into(),
+            });
+
+            assert_eq!(
+                lens.transform_defaults(&mut defaults).unwrap_err(),
+                Error::ConflictingFieldOnAdd("test".to_string())
+            );
+        }
+
+        #[test]
+        fn remove_field_success() {
+            let mut defaults = BTreeMap::new();
+            defaults.insert("test".to_string(), "existing_value".into());
+
+            let lens = Lens::Remove(AddRemoveField {
+                name: "test".into(),
+                type_: Type::String,
+                default: "unused".into(),
+            });
+
+            lens.transform_defaults(&mut defaults).unwrap();
+
+            assert_eq!(defaults, BTreeMap::new());
+        }
+
+        #[test]
+        fn remove_field_missing() {
+            let mut defaults = BTreeMap::new();
+
+            let lens = Lens::Remove(AddRemoveField {
+                name: "test".into(),
+                type_: Type::String,
+                default: "unused".into(),
+            });
+
+            assert_eq!(
+                lens.transform_defaults(&mut defaults).unwrap_err(),
+                Error::MissingFieldOnRemove("test".to_string())
+            );
+        }
+
+        #[test]
+        fn rename_field_success() {
+            let mut defaults = BTreeMap::new();
+            defaults.insert("test".to_string(), "value".into());
+
+            let lens = Lens::Rename {
+                from: "test".into(),
+                to: "new".into(),
+            };
+
+            lens.transform_defaults(&mut defaults).unwrap();
+
+            let mut expected = BTreeMap::new();
+            expected.insert("new".to_string(), "value".into());
+            assert_eq!(defaults, expected);
+        }
+
+        #[test]
+        fn rename_field_missing() {
+            let mut defaults = BTreeMap::new();
+
+            let lens = Lens::Rename {
+                from: "test".into(),
+                to: "new".into(),
+            };
+
+            assert_eq!(
+                lens.transform_defaults(&mut defaults).unwrap_err(),
+                Error::MissingFieldOnRename("test".to_string())
+            );
+        }
+
+        #[test]
+        fn convert_field_success() {
+            let mut defaults = BTreeMap::from([("status".to_string(), Value::from(false))]);
+
+            let lens = Lens::Convert(bool_to_status());
+
+            lens.transform_defaults(&mut defaults).unwrap();
+
+            assert_eq!(
+                defaults,
+                BTreeMap::from([("status".to_string(), Value::from("todo"))])
+            );
+        }
+
+        #[test]
+        fn convert_field_missing() {
+            let mut defaults = BTreeMap::new();
+
+            let lens = Lens::Convert(bool_to_status());
+
+            assert_eq!(
+                lens.transform_defaults(&mut defaults).unwrap_err(),
+                Error::MissingFieldOnConvert("status".to_string())
+            );
+        }
+    }
+
+    mod transform_record {
+        use super::*;
+        use pretty_assertions::assert_eq;
+
+        #[test]
+        fn add_field_leaves_record_alone() {
+            let mut record = BTreeMap::from([("other".to_string(), Value::from("value"))]);
+
+            let lens = Lens::Add(AddRemoveField {
+                name: "test".into(),
+                type_: Type::String,
+                default: "default".into(),
+            });
+
+            lens.transform_record(&mut record).unwrap();
+
+            assert_eq!(
+                record,
+                BTreeMap::from([("other".to_string(), Value::from("value"))])
+            );
+        }
+
+        #[test]
+        fn add_field_conflict() {
+            let mut record = BTreeMap::from([("test".to_string(), Value::from("value"))]);
+
+            let lens = Lens::Add(AddRemoveField {
+                name: "test".into(),
+                type_: Type::String,
+                default: "default".into(),
+            });
+
+            assert_eq!(
+                lens.transform_record(&mut record).unwrap_err(),
+                Error::ConflictingFieldOnAdd("test".to_string())
+            );
+        }
+
+        #[test]
+        fn remove_field_drops_value() {
+            let mut record = BTreeMap::from([("test".to_string(), Value::from("value"))]);
+
+            let lens = Lens::Remove(AddRemoveField {
+                name: "test".into(),
+                type_: Type::String,
+                default: "unused".into(),
+            });
+
+            lens.transform_record(&mut record).unwrap();
+
+            assert_eq!(record, BTreeMap::new());
+        }
+
+        #[test]
+        fn rename_field_moves_value() {
+            let mut record = BTreeMap::from([("test".to_string(), Value::from("value"))]);
+
+            let lens = Lens::Rename {
+                from: "test".into(),
+                to: "new".into(),
+            };
+
+            lens.transform_record(&mut record).unwrap();
+
+            assert_eq!(
+                record,
+                BTreeMap::from([("new".to_string(), Value::from("value"))])
+            );
+        }
+
+        #[test]
+        fn rename_field_missing_is_ok() {
+            let mut record = BTreeMap::new();
+
+            let lens = Lens::Rename {
+                from: "test".into(),
+                to: "new".into(),
+            };
+
+            lens.transform_record(&mut record).unwrap();
+
+            assert_eq!(record, BTreeMap::new());
+        }
+
+        #[test]
+        fn convert_field_with_table() {
+            let mut record = BTreeMap::from([("status".to_string(), Value::from(true))]);
+
+            Lens::Convert(bool_to_status())
+                .transform_record(&mut record)
+                .unwrap();
+
+            assert_eq!(
+                record,
+                BTreeMap::from([("status".to_string(), Value::from("done"))])
+            );
+        }
+
+        #[test]
+        fn convert_field_round_trips() {
+            let lens = Lens::Convert(bool_to_status());
+
+            for value in [true, false] {
+                let mut record = BTreeMap::from([("status".to_string(), Value::from(value))]);
+
+                lens.transform_record(&mut record).unwrap();
+                lens.reversed().transform_record(&mut record).unwrap();
+
+                assert_eq!(
+                    record,
+                    BTreeMap::from([("status".to_string(), Value::from(value))])
+                );
+            }
+        }
+
+        #[test]
+        fn convert_field_with_cast() {
+            let lens = Lens::Convert(Convert {
+                name: "count".into(),
+                from: Type::String,
+                to: Type::Int,
+                forward: Mapping::Cast,
+                backward: Mapping::Cast,
+                forward_fallback: None,
+                backward_fallback: None,
+            });
+
+            let mut record = BTreeMap::from([("count".to_string(), Value::from("42"))]);
+            lens.transform_record(&mut record).unwrap();
+            assert_eq!(
+                record,
+                BTreeMap::from([("count".to_string(), Value::from(42))])
+            );
+
+            let mut record = BTreeMap::from([("count".to_string(), Value::from("lots"))]);
+            assert_eq!(
+                lens.transform_record(&mut record).unwrap_err(),
+                Error::CouldNotConvert {
+                    value: "lots".into(),
+                    to: Type::Int,
+                }
+            );
+        }
+
+        #[test]
+        fn convert_field_uses_fallbacks() {
+            let lens = Lens::Convert(Convert {
+                forward_fallback: Some("todo".into()),
+                backward_fallback: Some(false.into()),
+                ..bool_to_status()
+            });
+
+            let mut record = BTreeMap::from([("status".to_string(), Value::from(true))]);
+            lens.transform_record(&mut record).unwrap();
+            assert_eq!(
+                record,
+                BTreeMap::from([("status".to_string(), Value::from("done"))])
+            );
+
+            let mut record = BTreeMap::from([("status".to_string(), Value::from("doing"))]);
+            lens.reversed().transform_record(&mut record).unwrap();
+            assert_eq!(
+                record,
+                BTreeMap::from([("status".to_string(), Value::from(false))])
+            );
+        }
+
+        #[test]
+        fn convert_field_with_cast_fallback() {
+            let lens = Lens::Convert(Convert {
+                name: "count".into(),
+                from: Type::String,
+                to: Type::Int,
+                forward: Mapping::Cast,
+                backward: Mapping::Cast,
+                forward_fallback: Some(0.into()),
+                backward_fallback: None,
+            });
+
+            let mut record = BTreeMap::from([("count".to_string(), Value::from("lots"))]);
+            lens.transform_record(&mut record).unwrap();
+            assert_eq!(
+                record,
+                BTreeMap::from([("count".to_string(), Value::from(0))])
+            );
+        }
+
+        #[test]
+        fn convert_field_passes_null_through() {
+            let mut record = BTreeMap::from([("status".to_string(), Value::Null)]);
+
+            Lens::Convert(bool_to_status())
+                .transform_record(&mut record)
+                .unwrap();
+
+            assert_eq!(
+                record,
+                BTreeMap::from([("status".to_string(), Value::Null)])
+            );
+        }
+
+        #[test]
+        fn convert_field_missing_from_table() {
+            let mut record = BTreeMap::from([("status".to_string(), Value::from("doing"))]);
+
+            assert_eq!(
+                Lens::Convert(bool_to_status())
+                    .reversed()
+                    .transform_record(&mut record)
+                    .unwrap_err(),
+                Error::CouldNotConvert {
+                    value: "doing".into(),
+                    to: Type::Bool,
+                }
+            );
+        }
+    }
+
+    mod transform_schema {
+        use crate::Field;
+
+        use super::*;
+        use pretty_assertions::assert_eq;
+
+        #[test]
+        fn add_field_success() {
+            let mut base = Schema::default();
+
+            let lens = Lens::Add(AddRemoveField {
+                name: "test".into(),
+                type_: Type::String,
+                default: "default".into(),
+            });
+
+            lens.transform_schema(&mut base).unwrap();
+
+            assert_eq!(
+                base,
+                Schema::from([(
+                    "test",
+                    Field {
+                        type_: Type::String,
+                        default: "default".into(),
+                        constraints: Vec::new()
+                    }
+                )])
+            );
+        }
+
+        #[test]
+        fn add_field_conflict() {
+            let mut base = Schema::from([(
+                "test",
+                Field {
+                    type_: Type::String,
+                    default: "default".into(),
+                    constraints: Vec::new(),
+                },
+            )]);
+
+            println!("{base:#?}");
+
+            let lens = Lens::Add(AddRemoveField {
+                name: "test".into(),
+                type_: Type::String,
+                default: "unused".into(),
+            });
+
+            assert_eq!(
+                lens.transform_schema(&mut base).unwrap_err(),
+                Error::ConflictingFieldOnAdd("test".to_string())
+            );
+        }
+
+        #[test]
+        fn remove_field_success() {
+            let mut base = Schema::from([(
+                "test",
+                Field {
+                    type_: Type::String,
+                    default: "default".into(),
+                    constraints: Vec::new(),
+                },
+            )]);
+
+            let lens = Lens::Remove(AddRemoveField {
+                name: "test".into(),
+                type_: Type::String,
+                default: "unused".into(),
+            });
+
+            lens.transform_schema(&mut base).unwrap();
+
+            assert_eq!(base, Schema::default());
+        }
+
+        #[test]
+        fn remove_field_missing() {
+            let mut base = Schema::default();
+
+            let lens = Lens::Remove(AddRemoveField {
+                name: "test".into(),
+                type_: Type::String,
+                default: "unused".into(),
+            });
+
+            assert_eq!(
+                lens.transform_schema(&mut base).unwrap_err(),
+                Error::MissingFieldOnRemove("test".to_string())
+            );
+        }
+
+        #[test]
+        fn rename_field_success() {
+            let mut base = Schema::from([(
+                "test",
+                Field {
+                    type_: Type::String,
+                    default: "default".into(),
+                    constraints: Vec::new(),
+                },
+            )]);
+
+            let lens = Lens::Rename {
+                from: "test".into(),
+                to: "new".into(),
+            };
+
+            lens.transform_schema(&mut base).unwrap();
+
+            assert_eq!(
+                base,
+                Schema::from([(
+                    "new",
+                    Field {
+                        type_: Type::String,
+                        default: "default".into(),
+                        constraints: Vec::new(),
+                    },
+                )])
+            );
+        }
+
+        #[test]
+        fn rename_field_missing() {
+            let mut base = Schema::default();
+
+            let lens = Lens::Rename {
+                from: "test".into(),
+                to: "new".into(),
+            };
+
+            assert_eq!(
+                lens.transform_schema(&mut base).unwrap_err(),
+                Error::MissingFieldOnRename("test".to_string())
+            );
+        }
+
+        #[test]
+        fn convert_field_success() {
+            let mut base = Schema::from([(
+                "status",
+                Field {
+                    type_: Type::Nullable(Box::new(Type::Bool)),
+                    default: false.into(),
+                    constraints: Vec::new(),
+                },
+            )]);
+
+            Lens::Convert(bool_to_status())
+                .transform_schema(&mut base)
+                .unwrap();
+
+            assert_eq!(
+                base,
+                Schema::from([(
+                    "status",
+                    Field {
+                        type_: Type::Nullable(Box::new(Type::String)),
+                        default: "todo".into(),
+                        constraints: Vec::new(),
+                    },
+                )])
+            );
+        }
+
+        #[test]
+        fn convert_field_type_mismatch() {
+            let mut base = Schema::from([(
+                "status",
+                Field {
+                    type_: Type::Int,
+                    default: 0.into(),
+                    constraints: Vec::new(),
+                },
+            )]);
+
+            assert_eq!(
+                Lens::Convert(bool_to_status())
+                    .transform_schema(&mut base)
+                    .unwrap_err(),
+                Error::TypeMismatchOnConvert {
+                    name: "status".into(),
+                    expected: Type::Bool,
+                    got: Type::Int,
+                }
+            );
+        }
+
+        #[test]
+        fn convert_field_checks_fallbacks() {
+            let mut base = Schema::from([(
+                "status",
+                Field {
+                    type_: Type::Bool,
+                    default: false.into(),
+                    constraints: Vec::new(),
+                },
+            )]);
+
+            assert_eq!(
+                Lens::Convert(Convert {
+                    backward_fallback: Some("todo".into()),
+                    ..bool_to_status()
+                })
+                .transform_schema(&mut base)
+                .unwrap_err(),
+                Error::InvalidDefault(type_::Error::InvalidValue {
+                    expected: Type::Bool,
+                    got: "todo".into(),
+                })
+            );
+        }
+
+        #[test]
+        fn convert_field_missing() {
+            let mut base = Schema::default();
+
+            assert_eq!(
+                Lens::Convert(bool_to_status())
+                    .transform_schema(&mut base)
+                    .unwrap_err(),
+                Error::MissingFieldOnConvert("status".to_string())
+            );
+        }
+    }
+
+    mod nested {
+        use super::*;
+        use crate::Field;
+        use pretty_assertions::assert_eq;
+
+        fn address_schema() -> Schema {
+            Schema::from([(
+                "address",
+                Field {
+                    type_: Type::Object(BTreeMap::from([("street".to_string(), Type::String)])),
+                    default: Value::Object(BTreeMap::from([(
+                        "street".to_string(),
+                        Value::from(""),
+                    )])),
+                    constraints: Vec::new(),
+                },
+            )])
+        }
+
+        fn add_zip() -> Lens {
+            Lens::Add(AddRemoveField {
+                name: "address.zip".into(),
+                type_: Type::String,
+                default: "00000".into(),
+            })
+        }
+
+        #[test]
+        fn add_sub_field_to_schema() {
+            let mut schema = address_schema();
+
+            add_zip().transform_schema(&mut schema).unwrap();
+
+            assert_eq!(
+                schema,
+                Schema::from([(
+                    "address",
+                    Field {
+                        type_: Type::Object(BTreeMap::from([
+                            ("street".to_string(), Type::String),
+                            ("zip".to_string(), Type::String),
+                        ])),
+                        default: Value::Object(BTreeMap::from([
+                            ("street".to_string(), Value::from("")),
+                            ("zip".to_string(), Value::from("00000")),
+                        ])),
+                        constraints: Vec::new(),
+                    },
+                )])
+            );
+        }
+
+        #[test]
+        fn add_then_remove_sub_field() {
+            let mut schema = address_schema();
+
+            let lens = add_zip();
+            lens.transform_schema(&mut schema).unwrap();
+            lens.reversed().transform_schema(&mut schema).unwrap();
+
+            assert_eq!(schema, address_schema());
+        }
+
+        #[test]
+        fn rename_sub_field() {
+            let mut schema = address_schema();
+            let mut record = BTreeMap::from([(
+                "address".to_string(),
+                Value::Object(BTreeMap::from([(
+                    "street".to_string(),
+                    Value::from("Main"),
+                )])),
+            )]);
+
+            let lens = Lens::Rename {
+                from: "address.street".into(),
+                to: "address.road".into(),
+            };
+            lens.transform_schema(&mut schema).unwrap();
+            lens.transform_record(&mut record).unwrap();
+
+            assert_eq!(
+                schema.get("address").map(|field| &field.type_),
+                Some(&Type::Object(BTreeMap::from([(
+                    "road".to_string(),
+                    Type::String
+                )])))
+            );
+            assert_eq!(
+                record,
+                BTreeMap::from([(
+                    "address".to_string(),
+                    Value::Object(BTreeMap::from([("road".to_string(), Value::from("Main"))])),
+                )])
+            );
+        }
+
+        #[test]
+        fn rename_across_objects() {
+            let lens = Lens::Rename {
+                from: "address.street".into(),
+                to: "street".into(),
+            };
+
+            assert_eq!(
+                lens.transform_schema(&mut address_schema()).unwrap_err(),
+                Error::RenameAcrossObjects {
+                    from: "address.street".into(),
+                    to: "street".into(),
+                }
+            );
+        }
+
+        #[test]
+        fn convert_sub_field() {
+            let mut schema = Schema::from([(
+                "settings",
+                Field {
+                    type_: Type::Nullable(Box::new(Type::Object(BTreeMap::from([(
+                        "volume".to_string(),
+                        Type::Int,
+                    )])))),
+                    default: Value::Null,
+                    constraints: Vec::new(),
+                },
+            )]);
+
+            let lens = Lens::Convert(Convert {
+                name: "settings.volume".into(),
+                from: Type::Int,
+                to: Type::Float,
+                forward: Mapping::Cast,
+                backward: Mapping::Cast,
+                forward_fallback: None,
+                backward_fallback: None,
+            });
+            lens.transform_schema(&mut schema).unwrap();
+
+            assert_eq!(
+                schema,
+                Schema::from([(
+                    "settings",
+                    Field {
+                        type_: Type::Nullable(Box::new(Type::Object(BTreeMap::from([(
+                            "volume".to_string(),
+                            Type::Float,
+                        )])))),
+                        default: Value::Null,
+                        constraints: Vec::new(),
+                    },
+                )])
+            );
+
+            let mut record = BTreeMap::from([(
+                "settings".to_string(),
+                Value::Object(BTreeMap::from([("volume".to_string(), Value::from(11))])),
+            )]);
+            lens.transform_record(&mut record).unwrap();
+
+            assert_eq!(
+                record,
+                BTreeMap::from([(
+                    "settings".to_string(),
+                    Value::Object(BTreeMap::from([("volume".to_string(), Value::from(11.0))])),
+                )])
+            );
+        }
+
+        #[test]
+        fn missing_parent() {
+            assert_eq!(
+                Lens::Add(AddRemoveField {
+                    name: "home.zip".into(),
+                    type_: Type::String,
+                    default: "".into(),
+                })
+                .transform_schema(&mut address_schema())
+                .unwrap_err(),
+                Error::MissingParent("home".into())
+            );
+        }
+
+        #[test]
+        fn not_an_object() {
+            assert_eq!(
+                Lens::Add(AddRemoveField {
+                    name: "address.street.number".into(),
+                    type_: Type::Int,
+                    default: 0.into(),
+                })
+                .transform_schema(&mut address_schema())
+                .unwrap_err(),
+                Error::NotAnObject("address.street".into())
+            );
+        }
+
+        #[test]
+        fn add_sub_field_fills_record() {
+            let mut record = BTreeMap::from([(
+                "address".to_string(),
+                Value::Object(BTreeMap::from([(
+                    "street".to_string(),
+                    Value::from("Main"),
+                )])),
+            )]);
+
+            add_zip().transform_record(&mut record).unwrap();
+
+            assert_eq!(
+                record,
+                BTreeMap::from([(
+                    "address".to_string(),
+                    Value::Object(BTreeMap::from([
+                        ("street".to_string(), Value::from("Main")),
+                        ("zip".to_string(), Value::from("00000")),
+                    ])),
+                )])
+            );
+        }
+
+        #[test]
+        fn null_parent_is_left_alone() {
+            let mut record = BTreeMap::from([("address".to_string(), Value::Null)]);
+
+            add_zip().transform_record(&mut record).unwrap();
+
+            assert_eq!(
+                record,
+                BTreeMap::from([("address".to_string(), Value::Null)])
+            );
+        }
+
+        #[test]
+        fn deserialize_object_field() {
+            let deserialized = serde_json::from_value::<AddRemoveField>(json!({
+                "name": "address",
+                "type": {"object": {
+                    "street": {"type": "string"},
+                    "zip": {"type": "string", "nullable": true},
+                }},
+                "default": {"street": ""},
+            }))
+            .unwrap();
+
+            assert_eq!(
+                deserialized.type_,
+                Type::Object(BTreeMap::from([
+                    ("street".to_string(), Type::String),
+                    ("zip".to_string(), Type::Nullable(Box::new(Type::String))),
+                ]))
+            );
+        }
+    }
+
+    mod arrays {
+        use super::*;
+        use crate::Field;
+        use pretty_assertions::assert_eq;
+
+        fn wrap_tag(default: Value) -> Lens {
+            Lens::Wrap(WrapHead {
+                name: "tag".into(),
+                default,
+            })
+        }
+
+        fn schema(type_: Type, default: Value) -> Schema {
+            Schema::from([(
+                "tag",
+                Field {
+                    type_,
+                    default,
+                    constraints: Vec::new(),
+                },
+            )])
+        }
+
+        #[test]
+        fn wrap_schema() {
+            let mut base = schema(Type::String, "none".into());
+
+            wrap_tag("".into()).transform_schema(&mut base).unwrap();
+
+            assert_eq!(
+                base,
+                schema(
+                    Type::Array(Box::new(Type::String)),
+                    Value::Array(vec!["none".into()])
+                )
+            );
+        }
+
+        #[test]
+        fn wrap_then_head_round_trips_schema() {
+            for (type_, default, wrap_default) in [
+                (Type::String, Value::from("none"), Value::from("")),
+                (
+                    Type::Nullable(Box::new(Type::String)),
+                    Value::Null,
+                    Value::Null,
+                ),
+            ] {
+                let mut base = schema(type_.clone(), default.clone());
+
+                let lens = wrap_tag(wrap_default);
+                lens.transform_schema(&mut base).unwrap();
+                lens.reversed().transform_schema(&mut base).unwrap();
+
+                assert_eq!(base, schema(type_, default));
+            }
+        }
+
+        #[test]
+        fn wrap_checks_default() {
+            assert_eq!(
+                wrap_tag(Value::Null)
+                    .transform_schema(&mut schema(Type::String, "".into()))
+                    .unwrap_err(),
+                Error::InvalidWrapDefault("tag".into())
+            );
+            assert_eq!(
+                wrap_tag("".into())
+                    .transform_schema(&mut schema(
+                        Type::Nullable(Box::new(Type::String)),
+                        Value::Null
+                    ))
+                    .unwrap_err(),
+                Error::InvalidWrapDefault("tag".into())
+            );
+        }
+
+        #[test]
+        fn wrap_record() {
+            let lens = wrap_tag(Value::Null);
+
+            let mut record = BTreeMap::from([("tag".to_string(), Value::from("urgent"))]);
+            lens.transform_record(&mut record).unwrap();
+            assert_eq!(
+                record,
+                BTreeMap::from([("tag".to_string(), Value::Array(vec!["urgent".into()]))])
+            );
+
+            let mut record = BTreeMap::from([("tag".to_string(), Value::Null)]);
+            lens.transform_record(&mut record).unwrap();
+            assert_eq!(
+                record,
+                BTreeMap::from([("tag".to_string(), Value::Array(Vec::new()))])
+            );
+        }
+
+        #[test]
+        fn head_record() {
+            let lens = wrap_tag("none".into()).reversed();
+
+            let mut record = BTreeMap::from([(
+                "tag".to_string(),
+                Value::Array(vec!["urgent".into(), "later".into()]),
+            )]);
+            lens.transform_record(&mut record).unwrap();
+            assert_eq!(
+                record,
+                BTreeMap::from([("tag".to_string(), Value::from("urgent"))])
+            );
+
+            let mut record = BTreeMap::from([("tag".to_string(), Value::Array(Vec::new()))]);
+            lens.transform_record(&mut record).unwrap();
+            assert_eq!(
+                record,
+                BTreeMap::from([("tag".to_string(), Value::from("none"))])
+            );
+        }
+
+        #[test]
+        fn head_without_default_is_nullable() {
+            let mut base = schema(Type::Array(Box::new(Type::Int)), Value::Array(Vec::new()));
+
+            Lens::Head(WrapHead {
+                name: "tag".into(),
+                default: Value::Null,
+            })
+            .transform_schema(&mut base)
+            .unwrap();
+
+            assert_eq!(
+                base,
+                schema(Type::Nullable(Box::new(Type::Int)), Value::Null)
+            );
+        }
+
+        #[test]
+        fn head_of_non_array() {
+            assert_eq!(
+                Lens::Head(WrapHead {
+                    name: "tag".into(),
+                    default: Value::Null,
+                })
+                .transform_schema(&mut schema(Type::String, "".into()))
+                .unwrap_err(),
+                Error::NotAnArray("tag".into())
+            );
+        }
+
+        #[test]
+        fn wrap_and_head_decode_bytes() {
+            let mut base = schema(Type::Bytes, Value::Bytes(vec![4, 5, 6]));
+
+            let wrap: Lens =
+                serde_json::from_value(json!({"wrap": {"name": "tag", "default": "AQID"}}))
+                    .unwrap();
+            assert_eq!(
+                wrap.decoded(&base).unwrap(),
+                wrap_tag(Value::Bytes(vec![1, 2, 3]))
+            );
+            wrap.transform_schema(&mut base).unwrap();
+
+            let head: Lens =
+                serde_json::from_value(json!({"head": {"name": "tag", "default": "AQID"}}))
+                    .unwrap();
+            assert_eq!(
+                head.decoded(&base).unwrap(),
+                wrap_tag(Value::Bytes(vec![1, 2, 3])).reversed()
+            );
+            head.transform_schema(&mut base).unwrap();
+
+            assert_eq!(base, schema(Type::Bytes, Value::Bytes(vec![4, 5, 6])));
+        }
+
+        #[test]
+        fn deserialize_wrap() {
+            assert_eq!(
+                serde_json::from_value::<Lens>(json!({"wrap": {"name": "tag", "default": ""}}))
+                    .unwrap(),
+                wrap_tag("".into())
+            );
+            assert_eq!(
+                serde_json::from_value::<Lens>(json!({"head": {"name": "tag"}})).unwrap(),
+                wrap_tag(Value::Null).reversed()
+            );
+        }
+    }
+
+    mod hoist_plunge {
+        use super::*;
+        use crate::Field;
+        use pretty_assertions::assert_eq;
+
+        fn plunge_bio() -> Lens {
+            Lens::Plunge(HoistPlunge {
+                host: "profile".into(),
+                name: "bio".into(),
+            })
+        }
+
+        fn flat() -> Schema {
+            Schema::from([
+                (
+                    "bio",
+                    Field {
+                        type_: Type::String,
+                        default: "".into(),
+                        constraints: Vec::new(),
+                    },
+                ),
+                (
+                    "profile",
+                    Field {
+                        type_: Type::Object(BTreeMap::from([("avatar".to_string(), Type::String)])),
+                        default: Value::Object(BTreeMap::from([(
+                            "avatar".to_string(),
+                            Value::from("default.png"),
+                        )])),
+                        constraints: Vec::new(),
+                    },
+                ),
+            ])
+        }
+
+        #[test]
+        fn plunge_schema() {
+            let mut schema = flat();
+
+            plunge_bio().transform_schema(&mut schema).unwrap();
+
+            assert_eq!(
+                schema,
+                Schema::from([(
+                    "profile",
+                    Field {
+                        type_: Type::Object(BTreeMap::from([
+                            ("avatar".to_string(), Type::String),
+                            ("bio".to_string(), Type::String),
+                        ])),
+                        default: Value::Object(BTreeMap::from([
+                            ("avatar".to_string(), Value::from("default.png")),
+                            ("bio".to_string(), Value::from("")),
+                        ])),
+                        constraints: Vec::new(),
+                    },
+                )])
+            );
+        }
+
+        #[test]
+        fn hoist_undoes_plunge() {
+            let mut schema = flat();
+
+            let lens = plunge_bio();
+            lens.transform_schema(&mut schema).unwrap();
+            lens.reversed().transform_schema(&mut schema).unwrap();
+
+            assert_eq!(schema, flat());
+        }
+
+        #[test]
+        fn plunge_record() {
+            let mut record = BTreeMap::from([
+                ("bio".to_string(), Value::from("Hello!")),
+                (
+                    "profile".to_string(),
+                    Value::Object(BTreeMap::from([(
+                        "avatar".to_string(),
+                        Value::from("me.png"),
+                    )])),
+                ),
+            ]);
+
+            plunge_bio().transform_record(&mut record).unwrap();
+
+            assert_eq!(
+                record,
+                BTreeMap::from([(
+                    "profile".to_string(),
+                    Value::Object(BTreeMap::from([
+                        ("avatar".to_string(), Value::from("me.png")),
+                        ("bio".to_string(), Value::from("Hello!")),
+                    ])),
+                )])
+            );
+        }
+
+        #[test]
+        fn plunge_partial_record() {
+            let mut record = BTreeMap::from([("bio".to_string(), Value::from("Hello!"))]);
+
+            plunge_bio().transform_record(&mut record).unwrap();
+
+            // Only the plunged sub-field is there, to be laid over the host
+            // the row already has.
+            let mut profile = Value::Object(BTreeMap::from([
+                ("avatar".to_string(), Value::from("me.png")),
+                ("bio".to_string(), Value::from("Hi")),
+            ]));
+            profile.overlay(record.remove("profile").unwrap());
+
+            assert!(record.is_empty());
+            assert_eq!(
+                profile,
+                Value::Object(BTreeMap::from([
+                    ("avatar".to_string(), Value::from("me.png")),
+                    ("bio".to_string(), Value::from("Hello!")),
+                ]))
+            );
+        }
+
+        #[test]
+        fn hoist_record() {
+            let mut record = BTreeMap::from([(
+                "profile".to_string(),
+                Value::Object(BTreeMap::from([
+                    ("avatar".to_string(), Value::from("me.png")),
+                    ("bio".to_string(), Value::from("Hello!")),
+                ])),
+            )]);
+
+            plunge_bio()
+                .reversed()
+                .transform_record(&mut record)
+                .unwrap();
+
+            assert_eq!(
+                record,
+                BTreeMap::from([
+                    ("bio".to_string(), Value::from("Hello!")),
+                    (
+                        "profile".to_string(),
+                        Value::Object(BTreeMap::from([(
+                            "avatar".to_string(),
+                            Value::from("me.png"),
+                        )])),
+                    ),
+                ])
+            );
+        }
+
+        #[test]
+        fn hoist_from_nested_host() {
+            let mut schema = Schema::from([(
+                "settings",
+                Field {
+                    type_: Type::Object(BTreeMap::from([(
+                        "display".to_string(),
+                        Type::Object(BTreeMap::from([("theme".to_string(), Type::String)])),
+                    )])),
+                    default: Value::Object(BTreeMap::from([(
+                        "display".to_string(),
+                        Value::Object(BTreeMap::from([(
+                            "theme".to_string(),
+                            Value::from("light"),
+                        )])),
+                    )])),
+                    constraints: Vec::new(),
+                },
+            )]);
+
+            Lens::Hoist(HoistPlunge {
+                host: "settings.display".into(),
+                name: "theme".into(),
+            })
+            .transform_schema(&mut schema)
+            .unwrap();
+
+            assert_eq!(
+                schema,
+                Schema::from([(
+                    "settings",
+                    Field {
+                        type_: Type::Object(BTreeMap::from([
+                            ("display".to_string(), Type::Object(BTreeMap::new())),
+                            ("theme".to_string(), Type::String),
+                        ])),
+                        default: Value::Object(BTreeMap::from([
+                            ("display".to_string(), Value::Object(BTreeMap::new())),
+                            ("theme".to_string(), Value::from("light")),
+                        ])),
+                        constraints: Vec::new(),
+                    },
+                )])
+            );
+        }
+
+        #[test]
+        fn plunge_conflict() {
+            let mut schema = flat();
+            Lens::Add(AddRemoveField {
+                name: "profile.bio".into(),
+                type_: Type::String,
+                default: "".into(),
+            })
+            .transform_schema(&mut schema)
+            .unwrap();
+
+            assert_eq!(
+                plunge_bio().transform_schema(&mut schema).unwrap_err(),
+                Error::ConflictingFieldOnMove("profile.bio".into())
+            );
+        }
+
+        #[test]
+        fn plunge_missing_field() {
+            let mut schema = flat();
+            schema.remove("bio");
+
+            assert_eq!(
+                plunge_bio().transform_schema(&mut schema).unwrap_err(),
+                Error::MissingFieldOnMove("bio".into())
+            );
+        }
+
+        #[test]
+        fn plunge_into_nullable_host() {
+            let mut schema = Schema::from([
+                (
+                    "bio",
+                    Field {
+                        type_: Type::String,
+                        default: "".into(),
+                        constraints: Vec::new(),
+                    },
+                ),
+                (
+                    "profile",
+                    Field {
+                        type_: Type::Nullable(Box::new(Type::Object(BTreeMap::new()))),
+                        default: Value::Null,
+                        constraints: Vec::new(),
+                    },
+                ),
+            ]);
+
+            assert_eq!(
+                plunge_bio().transform_schema(&mut schema).unwrap_err(),
+                Error::NullableHost("profile".into())
+            );
+        }
+
+        #[test]
+        fn deserialize_plunge() {
+            assert_eq!(
+                serde_json::from_value::<Lens>(json!({
+                    "plunge": {"host": "profile", "name": "bio"}
+                }))
+                .unwrap(),
+                plunge_bio()
+            );
+        }
+    }
+
+    mod enums {
+        use super::*;
+        use crate::Field;
+        use pretty_assertions::assert_eq;
+
+        fn status(variants: &[&str], default: &str) -> Schema {
+            Schema::from([(
+                "status",
+                Field {
+                    type_: Type::Enum(variants.iter().map(|v| v.to_string()).collect()),
+                    default: default.into(),
+                    constraints: Vec::new(),
+                },
+            )])
+        }
+
+        fn archived() -> EnumVariant {
+            EnumVariant {
+                name: "status".into(),
+                variant: "archived".into(),
+                fallback: "done".into(),
+            }
+        }
+
+        fn unpublished() -> Lens {
+            Lens::MapVariants(MapVariants {
+                name: "status".into(),
+                forward: BTreeMap::from([
+                    ("draft".to_string(), "unpublished".to_string()),
+                    ("pending".to_string(), "unpublished".to_string()),
+                ]),
+                backward: BTreeMap::from([("unpublished".to_string(), "draft".to_string())]),
+            })
+        }
+
+        #[test]
+        fn add_variant() {
+            let mut schema = status(&["todo", "done"], "todo");
+
+            Lens::AddVariant(archived())
+                .transform_schema(&mut schema)
+                .unwrap();
+
+            assert_eq!(schema, status(&["todo", "done", "archived"], "todo"));
+        }
+
+        #[test]
+        fn add_variant_conflict() {
+            assert_eq!(
+                Lens::AddVariant(archived())
+                    .transform_schema(&mut status(&["done", "archived"], "done"))
+                    .unwrap_err(),
+                Error::ConflictingVariant {
+                    name: "status".into(),
+                    variant: "archived".into(),
+                }
+            );
+        }
+
+        #[test]
+        fn add_variant_checks_fallback() {
+            assert_eq!(
+                Lens::AddVariant(archived())
+                    .transform_schema(&mut status(&["todo"], "todo"))
+                    .unwrap_err(),
+                Error::InvalidFallback {
+                    name: "status".into(),
+                    fallback: "done".into(),
+                }
+            );
+        }
+
+        #[test]
+        fn add_then_remove_variant() {
+            let mut schema = status(&["todo", "done"], "todo");
+
+            let lens = Lens::AddVariant(archived());
+            lens.transform_schema(&mut schema).unwrap();
+            lens.reversed().transform_schema(&mut schema).unwrap();
+
+            assert_eq!(schema, status(&["todo", "done"], "todo"));
+        }
+
+        #[test]
+        fn remove_variant_uses_fallback() {
+            let mut schema = status(&["todo", "done", "archived"], "archived");
+
+            let lens = Lens::RemoveVariant(archived());
+            lens.transform_schema(&mut schema).unwrap();
+            assert_eq!(schema, status(&["todo", "done"], "done"));
+
+            let mut record = BTreeMap::from([("status".to_string(), Value::from("archived"))]);
+            lens.transform_record(&mut record).unwrap();
+            assert_eq!(
+                record,
+                BTreeMap::from([("status".to_string(), Value::from("done"))])
+            );
+        }
+
+        #[test]
+        fn remove_missing_variant() {
+            assert_eq!(
+                Lens::RemoveVariant(archived())
+                    .transform_schema(&mut status(&["todo", "done"], "todo"))
+                    .unwrap_err(),
+                Error::MissingVariant {
+                    name: "status".into(),
+                    variant: "archived".into(),
+                }
+            );
+        }
+
+        #[test]
+        fn map_variants_collapses() {
+            let mut schema = status(&["draft", "pending", "published"], "pending");
+
+            unpublished().transform_schema(&mut schema).unwrap();
+
+            assert_eq!(schema, status(&["unpublished", "published"], "unpublished"));
+        }
+
+        #[test]
+        fn map_variants_records() {
+            let lens = unpublished();
+
+            let mut record = BTreeMap::from([("status".to_string(), Value::from("pending"))]);
+            lens.transform_record(&mut record).unwrap();
+            assert_eq!(
+                record,
+                BTreeMap::from([("status".to_string(), Value::from("unpublished"))])
+            );
+
+            lens.reversed().transform_record(&mut record).unwrap();
+            assert_eq!(
+                record,
+                BTreeMap::from([("status".to_string(), Value::from("draft"))])
+            );
+
+            let mut record = BTreeMap::from([("status".to_string(), Value::from("published"))]);
+            lens.transform_record(&mut record).unwrap();
+            assert_eq!(
+                record,
+                BTreeMap::from([("status".to_string(), Value::from("published"))])
+            );
+        }
+
+        #[test]
+        fn map_variants_needs_a_way_back() {
+            let lens = Lens::MapVariants(MapVariants {
+                name: "status".into(),
+                forward: BTreeMap::from([("draft".to_string(), "unpublished".to_string())]),
+                backward: BTreeMap::new(),
+            });
+
+            assert_eq!(
+                lens.transform_schema(&mut status(&["draft", "published"], "draft"))
+                    .unwrap_err(),
+                Error::UnmappedVariant {
+                    name: "status".into(),
+                    variant: "unpublished".into(),
+                }
+            );
+        }
+
+        #[test]
+        fn not_an_enum() {
+            let mut schema = Schema::from([(
+                "status",
+                Field {
+                    type_: Type::String,
+                    default: "".into(),
+                    constraints: Vec::new(),
+                },
+            )]);
+
+            assert_eq!(
+                Lens::AddVariant(archived())
+                    .transform_schema(&mut schema)
+                    .unwrap_err(),
+                Error::NotAnEnum("status".into())
+            );
+        }
+
+        #[test]
+        fn cast_string_to_enum() {
+            let lens = Lens::Convert(Convert {
+                name: "status".into(),
+                from: Type::String,
+                to: Type::Enum(vec!["todo".into(), "done".into()]),
+                forward: Mapping::Cast,
+                backward: Mapping::Cast,
+                forward_fallback: None,
+                backward_fallback: None,
+            });
+
+            let mut record = BTreeMap::from([("status".to_string(), Value::from("done"))]);
+            lens.transform_record(&mut record).unwrap();
+            assert_eq!(
+                record,
+                BTreeMap::from([("status".to_string(), Value::from("done"))])
+            );
+
+            let mut record = BTreeMap::from([("status".to_string(), Value::from("doing"))]);
+            assert!(lens.transform_record(&mut record).is_err());
+        }
+
+        #[test]
+        fn deserialize_enum_lenses() {
+            assert_eq!(
+                serde_json::from_value::<Lens>(json!({
+                    "add_variant": {"name": "status", "variant": "archived", "fallback": "done"}
+                }))
+                .unwrap(),
+                Lens::AddVariant(archived())
+            );
+            assert_eq!(
+                serde_json::from_value::<Lens>(json!({
+                    "map_variants": {
+                        "name": "status",
+                        "forward": {"draft": "unpublished", "pending": "unpublished"},
+                        "backward": {"unpublished": "draft"},
+                    }
+                }))
+                .unwrap(),
+                unpublished()
+            );
+        }
+    }
+
+    mod split_merge {
+        use super::*;
+        use crate::Field;
+        use pretty_assertions::assert_eq;
+
+        fn strings(names: &[(&str, &str)]) -> Schema {
+            let mut schema = Schema::default();
+            for (name, default) in names {
+                schema.insert(
+                    name.to_string(),
+                    Field {
+                        type_: Type::String,
+                        default: (*default).into(),
+                        constraints: Vec::new(),
+                    },
+                );
+            }
+            schema
+        }
+
+        fn split_name() -> Lens {
+            Lens::Split(SplitMerge {
+                name: "name".into(),
+                template: "{first_name} {last_name}".parse().unwrap(),
+            })
+        }
+
+        fn record(fields: &[(&str, &str)]) -> BTreeMap<String, Value> {
+            fields
+                .iter()
+                .map(|(name, value)| (name.to_string(), Value::from(*value)))
+                .collect()
+        }
+
+        #[test]
+        fn split_schema() {
+            let mut schema = strings(&[("name", "Jane Doe")]);
+
+            split_name().transform_schema(&mut schema).unwrap();
+
+            assert_eq!(
+                schema,
+                strings(&[("first_name", "Jane"), ("last_name", "Doe")])
+            );
+        }
+
+        #[test]
+        fn split_then_merge_schema() {
+            let mut schema = strings(&[("name", "Jane Doe")]);
+
+            let lens = split_name();
+            lens.transform_schema(&mut schema).unwrap();
+            lens.reversed().transform_schema(&mut schema).unwrap();
+
+            assert_eq!(schema, strings(&[("name", "Jane Doe")]));
+        }
+
+        #[test]
+        fn split_record() {
+            let mut record = record(&[("name", "Ada Lovelace")]);
+
+            split_name().transform_record(&mut record).unwrap();
+
+            assert_eq!(
+                record,
+                self::record(&[("first_name", "Ada"), ("last_name", "Lovelace")])
+            );
+        }
+
+        #[test]
+        fn merge_record() {
+            let mut record = record(&[("first_name", "Ada"), ("last_name", "Lovelace")]);
+
+            split_name()
+                .reversed()
+                .transform_record(&mut record)
+                .unwrap();
+
+            assert_eq!(record, self::record(&[("name", "Ada Lovelace")]));
+        }
+
+        #[test]
+        fn merge_partial_record() {
+            let mut record = record(&[("first_name", "Ada")]);
+
+            split_name()
+                .reversed()
+                .transform_record(&mut record)
+                .unwrap();
+
+            assert_eq!(record, BTreeMap::new());
+        }
+
+        #[test]
+        fn split_missing_field() {
+            assert_eq!(
+                split_name()
+                    .transform_schema(&mut strings(&[("title", "")]))
+                    .unwrap_err(),
+                Error::MissingFieldOnSplit("name".into())
+            );
+        }
+
+        #[test]
+        fn split_conflict() {
+            assert_eq!(
+                split_name()
+                    .transform_schema(&mut strings(&[("name", ""), ("last_name", "")]))
+                    .unwrap_err(),
+                Error::ConflictingFieldOnAdd("last_name".into())
+            );
+        }
+
+        #[test]
+        fn merge_needs_strings() {
+            let mut schema = strings(&[("first_name", "")]);
+            schema.insert(
+                "last_name".into(),
+                Field {
+                    type_: Type::Int,
+                    default: 0.into(),
+                    constraints: Vec::new(),
+                },
+            );
+
+            assert_eq!(
+                split_name()
+                    .reversed()
+                    .transform_schema(&mut schema)
+                    .unwrap_err(),
+                Error::NotAString("last_name".into())
+            );
+        }
+
+        #[test]
+        fn merge_sub_fields() {
+            let location = |fields: &[&str]| {
+                Type::Object(
+                    fields
+                        .iter()
+                        .map(|field| (field.to_string(), Type::String))
+                        .collect(),
+                )
+            };
+            let mut schema = Schema::from([(
+                "place",
+                Field {
+                    type_: location(&["lat", "lng"]),
+                    default: Value::Object(BTreeMap::from([
+                        ("lat".to_string(), Value::from("0")),
+                        ("lng".to_string(), Value::from("0")),
+                    ])),
+                    constraints: Vec::new(),
+                },
+            )]);
+
+            Lens::Merge(SplitMerge {
+                name: "place.coordinates".into(),
+                template: "{lat},{lng}".parse().unwrap(),
+            })
+            .transform_schema(&mut schema)
+            .unwrap();
+
+            let field = schema.get("place").unwrap();
+            assert_eq!(field.type_, location(&["coordinates"]));
+            assert_eq!(
+                field.default,
+                Value::Object(BTreeMap::from([(
+                    "coordinates".to_string(),
+                    Value::from("0,0"),
+                )]))
+            );
+        }
+
+        #[test]
+        fn deserialize_separator() {
+            let lens: Lens = serde_json::from_value(json!({
+                "split": {"name": "name", "fields": ["first_name", "last_name"], "separator": " "},
+            }))
+            .unwrap();
+
+            assert_eq!(lens, split_name());
+        }
+
+        #[test]
+        fn deserialize_template() {
+            let lens: Lens = serde_json::from_value(json!({
+                "split": {"name": "name", "template": "{first_name} {last_name}"},
+            }))
+            .unwrap();
+
+            assert_eq!(lens, split_name());
+            assert_eq!(
+                serde_json::to_value(&lens).unwrap(),
+                json!({"split": {"name": "name", "template": "{first_name} {last_name}"}})
+            );
+        }
+
+        #[test]
+        fn deserialize_rejects_uninvertible_template() {
+            let err = serde_json::from_value::<Lens>(json!({
+                "merge": {"name": "name", "template": "{first_name}{last_name}"},
+            }))
+            .unwrap_err();
+
+            assert!(
+                err.to_string().contains("need some text between them"),
+                "unexpected error: {err}"
+            );
+        }
+
+        #[test]
+        fn deserialize_rejects_template_and_separator() {
+            assert!(
+                serde_json::from_value::<Lens>(json!({
+                    "split": {"name": "name", "template": "{a} {b}", "separator": " "},
+                }))
+                .is_err()
+            );
+        }
+    }
+
+    mod set_default {
+        use super::*;
+        use crate::Field;
+        use pretty_assertions::assert_eq;
+
+        fn priority(default: Value) -> Schema {
+            Schema::from([(
+                "priority",
+                Field {
+                    type_: Type::Nullable(Box::new(Type::Int)),
+                    default,
+                    constraints: Vec::new(),
+                },
+            )])
+        }
+
+        fn set_default() -> Lens {
+            Lens::SetDefault {
+                name: "priority".into(),
+                from: Value::Null,
+                to: 3.into(),
+            }
+        }
+
+        #[test]
+        fn set_default_in_schema() {
+            let mut schema = priority(Value::Null);
+
+            set_default().transform_schema(&mut schema).unwrap();
+
+            assert_eq!(schema, priority(3.into()));
+        }
+
+        #[test]
+        fn set_then_restore_default() {
+            let mut schema = priority(Value::Null);
+
+            let lens = set_default();
+            lens.transform_schema(&mut schema).unwrap();
+            lens.reversed().transform_schema(&mut schema).unwrap();
+
+            assert_eq!(schema, priority(Value::Null));
+        }
+
+        #[test]
+        fn set_default_mismatch() {
+            assert_eq!(
+                set_default()
+                    .transform_schema(&mut priority(1.into()))
+                    .unwrap_err(),
+                Error::DefaultMismatch {
+                    name: "priority".into(),
+                    expected: Value::Null,
+                    got: 1.into(),
+                }
+            );
+        }
+
+        #[test]
+        fn set_default_checks_type() {
+            let lens = Lens::SetDefault {
+                name: "priority".into(),
+                from: Value::Null,
+                to: "high".into(),
+            };
+
+            assert!(matches!(
+                lens.transform_schema(&mut priority(Value::Null)),
+                Err(Error::InvalidDefault(_))
+            ));
+        }
+
+        #[test]
+        fn set_default_missing_field() {
+            assert_eq!(
+                set_default()
+                    .transform_schema(&mut Schema::default())
+                    .unwrap_err(),
+                Error::MissingFieldOnSetDefault("priority".into())
+            );
+        }
+
+        #[test]
+        fn set_default_in_defaults() {
+            let mut defaults = BTreeMap::from([("priority".to_string(), Value::Null)]);
+
+            set_default().transform_defaults(&mut defaults).unwrap();
+
+            assert_eq!(
+                defaults,
+                BTreeMap::from([("priority".to_string(), 3.into())])
+            );
+        }
+
+        #[test]
+        fn set_default_leaves_records_alone() {
+            let mut record = BTreeMap::from([("priority".to_string(), Value::Null)]);
+
+            set_default().transform_record(&mut record).unwrap();
+
+            assert_eq!(
+                record,
+                BTreeMap::from([("priority".to_string(), Value::Null)])
+            );
+        }
+
+        #[test]
+        fn set_bytes_default() {
+            let thumb = |default: Vec<u8>| {
+                Schema::from([(
+                    "thumb",
+                    Field {
+                        type_: Type::Bytes,
+                        default: Value::Bytes(default),
+                        constraints: Vec::new(),
+                    },
+                )])
+            };
+            let lens: Lens = serde_json::from_value(json!({
+                "set_default": {"name": "thumb", "from": "AQID", "to": "BAUG"},
+            }))
+            .unwrap();
+
+            let mut schema = thumb(vec![1, 2, 3]);
+            lens.transform_schema(&mut schema).unwrap();
+            assert_eq!(schema, thumb(vec![4, 5, 6]));
+
+            lens.reversed().transform_schema(&mut schema).unwrap();
+            assert_eq!(schema, thumb(vec![1, 2, 3]));
+        }
+
+        #[test]
+        fn set_sub_field_default() {
+            let mut schema = Schema::from([(
+                "address",
+                Field {
+                    type_: Type::Object(BTreeMap::from([("street".to_string(), Type::String)])),
+                    default: Value::Object(BTreeMap::from([(
+                        "street".to_string(),
+                        Value::from(""),
+                    )])),
+                    constraints: Vec::new(),
+                },
+            )]);
+
+            Lens::SetDefault {
+                name: "address.street".into(),
+                from: "".into(),
+                to: "Main".into(),
+            }
+            .transform_schema(&mut schema)
+            .unwrap();
+
+            assert_eq!(
+                schema.get("address").unwrap().default,
+                Value::Object(BTreeMap::from([(
+                    "street".to_string(),
+                    Value::from("Main"),
+                )]))
+            );
+        }
+
+        #[test]
+        fn deserialize() {
+            let lens: Lens = serde_json::from_value(json!({
+                "set_default": {"name": "priority", "from": null, "to": 3},
+            }))
+            .unwrap();
+
+            assert_eq!(lens, set_default());
+        }
+    }
+
+    mod nullability {
+        use super::*;
+        use crate::Field;
+        use pretty_assertions::assert_eq;
+
+        fn title(type_: Type, default: Value) -> Schema {
+            Schema::from([(
+                "title",
+                Field {
+                    type_,
+                    default,
+                    constraints: Vec::new(),
+                },
+            )])
+        }
+
+        fn nullable_title() -> Lens {
+            Lens::MakeNullable(Nullability {
+                name: "title".into(),
+                default: "untitled".into(),
+            })
+        }
+
+        #[test]
+        fn make_nullable() {
+            let mut schema = title(Type::String, "".into());
+
+            nullable_title().transform_schema(&mut schema).unwrap();
+
+            assert_eq!(
+                schema,
+                title(Type::Nullable(Box::new(Type::String)), "".into())
+            );
+        }
+
+        #[test]
+        fn make_nullable_updates_jtd() {
+            let mut schema = title(Type::String, "".into());
+
+            nullable_title().transform_schema(&mut schema).unwrap();
+
+            let jtd::Schema::Properties { properties, .. } = jtd::Schema::from(schema) else {
+                panic!("expected a properties schema");
+            };
+            assert_eq!(
+                properties["title"],
+                (&Type::Nullable(Box::new(Type::String))).into()
+            );
+        }
+
+        #[test]
+        fn make_nullable_twice() {
+            assert_eq!(
+                nullable_title()
+                    .transform_schema(&mut title(
+                        Type::Nullable(Box::new(Type::String)),
+                        Value::Null
+                    ))
+                    .unwrap_err(),
+                Error::AlreadyNullable("title".into())
+            );
+        }
+
+        #[test]
+        fn make_nullable_checks_default() {
+            let lens = Lens::MakeNullable(Nullability {
+                name: "title".into(),
+                default: Value::Null,
+            });
+
+            assert!(matches!(
+                lens.transform_schema(&mut title(Type::String, "".into())),
+                Err(Error::InvalidDefault(_))
+            ));
+        }
+
+        #[test]
+        fn make_required_replaces_null_default() {
+            let mut schema = title(Type::Nullable(Box::new(Type::String)), Value::Null);
+
+            nullable_title()
+                .reversed()
+                .transform_schema(&mut schema)
+                .unwrap();
+
+            assert_eq!(schema, title(Type::String, "untitled".into()));
+        }
+
+        #[test]
+        fn make_required_not_nullable() {
+            assert_eq!(
+                nullable_title()
+                    .reversed()
+                    .transform_schema(&mut title(Type::String, "".into()))
+                    .unwrap_err(),
+                Error::NotNullable("title".into())
+            );
+        }
+
+        #[test]
+        fn make_required_missing_field() {
+            assert_eq!(
+                nullable_title()
+                    .reversed()
+                    .transform_schema(&mut Schema::default())
+                    .unwrap_err(),
+                Error::MissingFieldOnNullability("title".into())
+            );
+        }
+
+        #[test]
+        fn records_from_newer_versions_lose_nulls() {
+            let lens = nullable_title();
+
+            let mut record = BTreeMap::from([("title".to_string(), Value::Null)]);
+            lens.transform_record(&mut record).unwrap();
+            assert_eq!(record, BTreeMap::from([("title".to_string(), Value::Null)]));
+
+            lens.reversed().transform_record(&mut record).unwrap();
+            assert_eq!(
+                record,
+                BTreeMap::from([("title".to_string(), "untitled".into())])
+            );
+
+            let mut record = BTreeMap::from([("title".to_string(), "kept".into())]);
+            lens.reversed().transform_record(&mut record).unwrap();
+            assert_eq!(
+                record,
+                BTreeMap::from([("title".to_string(), "kept".into())])
+            );
+        }
+
+        #[test]
+        fn nullability_decodes_bytes() {
+            let thumb = |type_: Type, default: Value| {
+                Schema::from([(
+                    "thumb",
+                    Field {
+                        type_,
+                        default,
+                        constraints: Vec::new(),
+                    },
+                )])
+            };
+            let nullable: Lens = serde_json::from_value(json!({
+                "make_nullable": {"name": "thumb", "default": "AQID"},
+            }))
+            .unwrap();
+            let required: Lens = serde_json::from_value(json!({
+                "make_required": {"name": "thumb", "default": "AQID"},
+            }))
+            .unwrap();
+
+            let mut schema = thumb(Type::Bytes, Value::Bytes(Vec::new()));
+            nullable.transform_schema(&mut schema).unwrap();
+            assert_eq!(
+                schema,
+                thumb(
+                    Type::Nullable(Box::new(Type::Bytes)),
+                    Value::Bytes(Vec::new())
+                )
+            );
+
+            let mut schema = thumb(Type::Nullable(Box::new(Type::Bytes)), Value::Null);
+            let decoded = required.decoded(&schema).unwrap();
+            required.transform_schema(&mut schema).unwrap();
+            assert_eq!(schema, thumb(Type::Bytes, Value::Bytes(vec![1, 2, 3])));
+
+            let mut record = BTreeMap::from([("thumb".to_string(), Value::Null)]);
+            decoded.transform_record(&mut record).unwrap();
+            assert_eq!(
+                record,
+                BTreeMap::from([("thumb".to_string(), Value::Bytes(vec![1, 2, 3]))])
+            );
+        }
+
+        #[test]
+        fn make_sub_field_required() {
+            let address = |zip: Type| {
+                Type::Object(BTreeMap::from([
+                    ("street".to_string(), Type::String),
+                    ("zip".to_string(), zip),
+                ]))
+            };
+            let mut schema = Schema::from([(
+                "address",
+                Field {
+                    type_: address(Type::Nullable(Box::new(Type::String))),
+                    default: Value::Object(BTreeMap::from([(
+                        "street".to_string(),
+                        Value::from(""),
+                    )])),
+                    constraints: Vec::new(),
+                },
+            )]);
+            let mut record = BTreeMap::from([(
+                "address".to_string(),
+                Value::Object(BTreeMap::from([(
+                    "street".to_string(),
+                    Value::from("Main"),
+                )])),
+            )]);
+
+            let lens = Lens::MakeRequired(Nullability {
+                name: "address.zip".into(),
+                default: "00000".into(),
+            });
+            lens.transform_schema(&mut schema).unwrap();
+            lens.transform_record(&mut record).unwrap();
+
+            let field = schema.get("address").unwrap();
+            assert_eq!(field.type_, address(Type::String));
+            field.type_.validate(&record["address"]).unwrap();
+            assert_eq!(
+                record["address"],
+                Value::Object(BTreeMap::from([
+                    ("street".to_string(), Value::from("Main")),
+                    ("zip".to_string(), Value::from("00000")),
+                ]))
+            );
+        }
+
+        #[test]
+        fn deserialize() {
+            let lens: Lens = serde_json::from_value(json!({
+                "make_nullable": {"name": "title", "default": "untitled"},
+            }))
+            .unwrap();
+
+            assert_eq!(lens, nullable_title());
+        }
+    }
+
+    mod constraints {
+        use super::*;
+        use crate::{Field, Pattern};
+        use pretty_assertions::assert_eq;
+
+        fn title(default: &str, constraints: Vec<Constraint>) -> Schema {
+            Schema::from([(
+                "title",
+                Field {
+                    type_: Type::String,
+                    default: default.into(),
+                    constraints,
+                },
+            )])
+        }
+
+        fn constrain(constraint: Constraint) -> Lens {
+            Lens::AddConstraint(FieldConstraint {
+                name: "title".into(),
+                constraint,
+            })
+        }
+
+        #[test]
+        fn add_constraint() {
+            let mut schema = title("", Vec::new());
+
+            constrain(Constraint::MaxLength(10))
+                .transform_schema(&mut schema)
+                .unwrap();
+
+            assert_eq!(schema, title("", vec![Constraint::MaxLength(10)]));
+        }
+
+        #[test]
+        fn add_then_remove_constraint() {
+            let mut schema = title("", Vec::new());
+
+            let lens = constrain(Constraint::MaxLength(10));
+            lens.transform_schema(&mut schema).unwrap();
+            lens.reversed().transform_schema(&mut schema).unwrap();
+
+            assert_eq!(schema, title("", Vec::new()));
+        }
+
+        #[test]
+        fn add_constraint_conflict() {
+            assert_eq!(
+                constrain(Constraint::NonEmpty)
+                    .transform_schema(&mut title("a", vec![Constraint::NonEmpty]))
+                    .unwrap_err(),
+                Error::ConflictingConstraint {
+                    name: "title".into(),
+                    constraint: Constraint::NonEmpty,
+                }
+            );
+        }
 
-    mod transform_defaults {
-        use super::*;
-        use pretty_assertions::assert_eq;
+        #[test]
+        fn add_constraint_missing_field() {
+            assert_eq!(
+                Lens::AddConstraint(FieldConstraint {
+                    name: "body".into(),
+                    constraint: Constraint::NonEmpty,
+                })
+                .transform_schema(&mut title("a", Vec::new()))
+                .unwrap_err(),
+                Error::MissingFieldOnConstraint("body".into())
+            );
+        }
 
         #[test]
-        fn add_field_success() {
-            let mut defaults = BTreeMap::new();
+        fn add_constraint_checks_type() {
+            assert_eq!(
+                constrain(Constraint::Min(0.0))
+                    .transform_schema(&mut title("a", Vec::new()))
+                    .unwrap_err(),
+                Error::ConstraintNotApplicable {
+                    name: "title".into(),
+                    constraint: Constraint::Min(0.0),
+                }
+            );
+        }
 
-            let lens = Lens::Add(AddRemoveField {
-                name: "test".into(),
-                type_: Type::String,
-                default: "default_value".into(),
-            });
+        #[test]
+        fn add_constraint_checks_default() {
+            assert_eq!(
+                constrain(Constraint::NonEmpty)
+                    .transform_schema(&mut title("", Vec::new()))
+                    .unwrap_err(),
+                Error::InvalidDefault(type_::Error::Constraint {
+                    constraint: Constraint::NonEmpty,
+                    got: "".into(),
+                })
+            );
+        }
 
-            lens.transform_defaults(&mut defaults).unwrap();
+        #[test]
+        fn remove_missing_constraint() {
+            let pattern = Constraint::Pattern(Pattern::new("^a").unwrap());
 
-            let mut expected = BTreeMap::new();
-            expected.insert("test".to_string(), "default_value".into());
-            assert_eq!(defaults, expected);
+            assert_eq!(
+                Lens::RemoveConstraint(FieldConstraint {
+                    name: "title".into(),
+                    constraint: pattern.clone(),
+                })
+                .transform_schema(&mut title("a", Vec::new()))
+                .unwrap_err(),
+                Error::MissingConstraint {
+                    name: "title".into(),
+                    constraint: pattern,
+                }
+            );
         }
 
         #[test]
-        fn add_field_conflict() {
-            let mut defaults = BTreeMap::new();
-            defaults.insert("test".to_string(), "existing_value".into());
+        fn rename_keeps_constraints() {
+            let mut schema = title("a", vec![Constraint::NonEmpty]);
 
-            let lens = Lens::Add(AddRemoveField {
-                name: "test".into(),
-                type_: Type::String,
-                default: "default_value".into(),
-            });
+            Lens::Rename {
+                from: "title".into(),
+                to: "name".into(),
+            }
+            .transform_schema(&mut schema)
+            .unwrap();
 
             assert_eq!(
-                lens.transform_defaults(&mut defaults).unwrap_err(),
-                Error::ConflictingFieldOnAdd("test".to_string())
+                schema.get("name").unwrap().constraints,
+                vec![Constraint::NonEmpty]
             );
         }
 
         #[test]
-        fn remove_field_success() {
-            let mut defaults = BTreeMap::new();
-            defaults.insert("test".to_string(), "existing_value".into());
+        fn remove_drops_constraints() {
+            let mut schema = title("a", vec![Constraint::NonEmpty]);
 
-            let lens = Lens::Remove(AddRemoveField {
-                name: "test".into(),
+            let remove = Lens::Remove(AddRemoveField {
+                name: "title".into(),
                 type_: Type::String,
-                default: "unused".into(),
+                default: "a".into(),
             });
+            remove.transform_schema(&mut schema).unwrap();
+            remove.reversed().transform_schema(&mut schema).unwrap();
 
-            lens.transform_defaults(&mut defaults).unwrap();
-
-            assert_eq!(defaults, BTreeMap::new());
+            assert_eq!(schema, title("a", Vec::new()));
         }
 
         #[test]
-        fn remove_field_missing() {
-            let mut defaults = BTreeMap::new();
+        fn convert_checks_constraints_still_apply() {
+            let mut schema = title("1", vec![Constraint::MaxLength(3)]);
 
-            let lens = Lens::Remove(AddRemoveField {
-                name: "test".into(),
-                type_: Type::String,
-                default: "unused".into(),
-            });
+            let err = Lens::Convert(Convert {
+                name: "title".into(),
+                from: Type::String,
+                to: Type::Int,
+                forward: Mapping::Cast,
+                backward: Mapping::Cast,
+                forward_fallback: None,
+                backward_fallback: None,
+            })
+            .transform_schema(&mut schema)
+            .unwrap_err();
 
             assert_eq!(
-                lens.transform_defaults(&mut defaults).unwrap_err(),
-                Error::MissingFieldOnRemove("test".to_string())
+                err,
+                Error::ConstraintNotApplicable {
+                    name: "title".into(),
+                    constraint: Constraint::MaxLength(3),
+                }
             );
         }
 
         #[test]
-        fn rename_field_success() {
-            let mut defaults = BTreeMap::new();
-            defaults.insert("test".to_string(), "value".into());
+        fn wrap_constrained_field() {
+            assert_eq!(
+                Lens::Wrap(WrapHead {
+                    name: "title".into(),
+                    default: "".into(),
+                })
+                .transform_schema(&mut title("a", vec![Constraint::MaxLength(3)]))
+                .unwrap_err(),
+                Error::ConstrainedField("title".into())
+            );
+        }
 
-            let lens = Lens::Rename {
-                from: "test".into(),
-                to: "new".into(),
-            };
+        #[test]
+        fn constraints_leave_records_alone() {
+            let mut record = BTreeMap::from([("title".to_string(), Value::from(""))]);
 
-            lens.transform_defaults(&mut defaults).unwrap();
+            constrain(Constraint::NonEmpty)
+                .transform_record(&mut record)
+                .unwrap();
 
-            let mut expected = BTreeMap::new();
-            expected.insert("new".to_string(), "value".into());
-            assert_eq!(defaults, expected);
+            assert_eq!(record, BTreeMap::from([("title".to_string(), "".into())]));
         }
 
         #[test]
-        fn rename_field_missing() {
-            let mut defaults = BTreeMap::new();
-
-            let lens = Lens::Rename {
-                from: "test".into(),
-                to: "new".into(),
-            };
+        fn deserialize() {
+            let lens: Lens = serde_json::from_value(json!({
+                "add_constraint": {"name": "title", "constraint": {"max_length": 10}},
+            }))
+            .unwrap();
 
-            assert_eq!(
-                lens.transform_defaults(&mut defaults).unwrap_err(),
-                Error::MissingFieldOnRename("test".to_string())
-            );
+            assert_eq!(lens, constrain(Constraint::MaxLength(10)));
         }
     }
 
-    mod transform_schema {
-        use crate::Field;
-
+    mod cross_table {
         use super::*;
+        use crate::Field;
         use pretty_assertions::assert_eq;
 
-        #[test]
-        fn add_field_success() {
-            let mut base = Schema::default();
-
-            let lens = Lens::Add(AddRemoveField {
-                name: "test".into(),
-                type_: Type::String,
-                default: "default".into(),
-            });
-
-            lens.transform_schema(&mut base).unwrap();
+        fn avatar() -> CrossTable {
+            CrossTable {
+                field: AddRemoveField {
+                    name: "avatar".into(),
+                    type_: Type::Nullable(Box::new(Type::String)),
+                    default: Value::Null,
+                },
+                from: "user".into(),
+                to: "profile".into(),
+                reference: "user_id".into(),
+            }
+        }
 
-            assert_eq!(
-                base,
-                Schema::from([(
-                    "test",
+        fn with_avatar() -> Schema {
+            Schema::from([
+                (
+                    "name",
                     Field {
                         type_: Type::String,
-                        default: "default".into()
-                    }
-                )])
-            );
+                        default: "".into(),
+                        constraints: Vec::new(),
+                    },
+                ),
+                (
+                    "avatar",
+                    Field {
+                        type_: Type::Nullable(Box::new(Type::String)),
+                        default: Value::Null,
+                        constraints: Vec::new(),
+                    },
+                ),
+            ])
+        }
+
+        fn without_avatar() -> Schema {
+            let mut schema = with_avatar();
+            schema.remove("avatar");
+            schema
         }
 
         #[test]
-        fn add_field_conflict() {
-            let mut base = Schema::from([(
-                "test",
-                Field {
-                    type_: Type::String,
-                    default: "default".into(),
-                },
-            )]);
+        fn move_out_and_back_in() {
+            let mut schema = with_avatar();
 
-            println!("{base:#?}");
+            let lens = Lens::MoveOut(avatar());
+            lens.transform_schema(&mut schema).unwrap();
+            assert_eq!(schema, without_avatar());
 
-            let lens = Lens::Add(AddRemoveField {
-                name: "test".into(),
-                type_: Type::String,
-                default: "unused".into(),
-            });
+            lens.reversed().transform_schema(&mut schema).unwrap();
+            assert_eq!(schema, with_avatar());
+        }
+
+        #[test]
+        fn move_out_checks_type() {
+            let mut lens = avatar();
+            lens.field.type_ = Type::String;
+            lens.field.default = "".into();
 
             assert_eq!(
-                lens.transform_schema(&mut base).unwrap_err(),
-                Error::ConflictingFieldOnAdd("test".to_string())
+                Lens::MoveOut(lens)
+                    .transform_schema(&mut with_avatar())
+                    .unwrap_err(),
+                Error::TypeMismatchOnMove {
+                    name: "avatar".into(),
+                    expected: Type::String,
+                    got: Type::Nullable(Box::new(Type::String)),
+                }
             );
         }
 
         #[test]
-        fn remove_field_success() {
-            let mut base = Schema::from([(
-                "test",
-                Field {
-                    type_: Type::String,
-                    default: "default".into(),
-                },
-            )]);
-
-            let lens = Lens::Remove(AddRemoveField {
-                name: "test".into(),
-                type_: Type::String,
-                default: "unused".into(),
-            });
-
-            lens.transform_schema(&mut base).unwrap();
-
-            assert_eq!(base, Schema::default());
+        fn move_in_conflict() {
+            assert_eq!(
+                Lens::MoveIn(avatar())
+                    .transform_schema(&mut with_avatar())
+                    .unwrap_err(),
+                Error::ConflictingFieldOnMove("avatar".into())
+            );
         }
 
         #[test]
-        fn remove_field_missing() {
-            let mut base = Schema::default();
-
-            let lens = Lens::Remove(AddRemoveField {
-                name: "test".into(),
-                type_: Type::String,
-                default: "unused".into(),
-            });
+        fn nested_moves_are_rejected() {
+            let mut lens = avatar();
+            lens.field.name = "images.avatar".into();
 
             assert_eq!(
-                lens.transform_schema(&mut base).unwrap_err(),
-                Error::MissingFieldOnRemove("test".to_string())
+                Lens::MoveIn(lens)
+                    .transform_schema(&mut without_avatar())
+                    .unwrap_err(),
+                Error::NestedMove("images.avatar".into())
             );
         }
 
         #[test]
-        fn rename_field_success() {
-            let mut base = Schema::from([(
-                "test",
-                Field {
-                    type_: Type::String,
-                    default: "default".into(),
-                },
-            )]);
-
-            let lens = Lens::Rename {
-                from: "test".into(),
-                to: "new".into(),
-            };
+        fn records_lose_moved_out_values() {
+            let mut record = BTreeMap::from([
+                ("name".to_string(), Value::from("Ada")),
+                ("avatar".to_string(), Value::from("ada.png")),
+            ]);
 
-            lens.transform_schema(&mut base).unwrap();
+            Lens::MoveOut(avatar())
+                .transform_record(&mut record)
+                .unwrap();
+            assert_eq!(
+                record,
+                BTreeMap::from([("name".to_string(), Value::from("Ada"))])
+            );
 
+            // Moving back in doesn't make up a value; that comes from the
+            // other table.
+            Lens::MoveIn(avatar())
+                .transform_record(&mut record)
+                .unwrap();
             assert_eq!(
-                base,
-                Schema::from([(
-                    "new",
-                    Field {
-                        type_: Type::String,
-                        default: "default".into(),
-                    },
-                )])
+                record,
+                BTreeMap::from([("name".to_string(), Value::from("Ada"))])
             );
         }
 
         #[test]
-        fn rename_field_missing() {
-            let mut base = Schema::default();
-
-            let lens = Lens::Rename {
-                from: "test".into(),
-                to: "new".into(),
-            };
+        fn deserialize() {
+            let lens: Lens = serde_json::from_value(json!({
+                "move_out": {
+                    "name": "avatar",
+                    "type": "string",
+                    "nullable": true,
+                    "from": "user",
+                    "to": "profile",
+                    "reference": "user_id",
+                },
+            }))
+            .unwrap();
 
-            assert_eq!(
-                lens.transform_schema(&mut base).unwrap_err(),
-                Error::MissingFieldOnRename("test".to_string())
-            );
+            assert_eq!(lens, Lens::MoveOut(avatar()));
         }
     }
 
@@ -448,5 +4142,102 @@ mod tests {
 
             assert_eq!(Value::Null, deserialized.default);
         }
+
+        #[test]
+        fn bytes_default_is_base64() {
+            let json = json!({
+                "name": "thumbnail",
+                "type": "bytes",
+                "nullable": false,
+                "default": "AQID",
+            });
+
+            let deserialized = serde_json::from_value::<AddRemoveField>(json.clone()).unwrap();
+            assert_eq!(Value::Bytes(vec![1, 2, 3]), deserialized.default);
+
+            assert_eq!(json, serde_json::to_value(&deserialized).unwrap());
+        }
+
+        #[test]
+        fn bytes_default_checks_base64() {
+            let err = serde_json::from_value::<AddRemoveField>(json!({
+                "name": "thumbnail",
+                "type": "bytes",
+                "default": "not base64!",
+            }))
+            .unwrap_err();
+
+            assert_eq!("Invalid value for type bytes: not base64!", err.to_string());
+        }
+
+        #[test]
+        fn convert_deserializes_successfully() {
+            let deserialized = serde_json::from_value::<Lens>(json!({
+                "convert": {
+                    "name": "status",
+                    "from": "bool",
+                    "to": "string",
+                    "forward": {"table": [[true, "done"], [false, "todo"]]},
+                    "backward": {"table": [["done", true], ["todo", false]]},
+                }
+            }))
+            .unwrap();
+
+            assert_eq!(Lens::Convert(bool_to_status()), deserialized);
+        }
+
+        #[test]
+        fn convert_with_fallbacks_round_trips() {
+            let json = json!({
+                "convert": {
+                    "name": "status",
+                    "from": "bool",
+                    "to": "string",
+                    "forward": {"table": [[true, "done"], [false, "todo"]]},
+                    "backward": {"table": [["done", true], ["todo", false]]},
+                    "forward_fallback": "todo",
+                    "backward_fallback": false,
+                }
+            });
+
+            let deserialized = serde_json::from_value::<Lens>(json.clone()).unwrap();
+            assert_eq!(
+                Lens::Convert(Convert {
+                    forward_fallback: Some("todo".into()),
+                    backward_fallback: Some(false.into()),
+                    ..bool_to_status()
+                }),
+                deserialized
+            );
+            assert_eq!(json, serde_json::to_value(&deserialized).unwrap());
+        }
+
+        #[test]
+        fn convert_checks_cast() {
+            let err = serde_json::from_value::<Convert>(json!({
+                "name": "test_field",
+                "from": "float",
+                "to": "bool",
+                "forward": "cast",
+                "backward": "cast",
+            }))
+            .unwrap_err();
+
+            assert_eq!("Can't cast float to bool.", err.to_string());
+        }
+
+        #[test]
+        fn convert_checks_table() {
+            let err = serde_json::from_value::<Convert>(json!({
+                "name": "test_field",
+                "from": "bool",
+                "to": "string",
+                "forward": {"table": [[true, 1]]},
+                "backward": "cast",
+            }))
+            .unwrap_err();
+
+            assert_eq!("Invalid value for type string: 1", err.to_string());
+        }
     }
 }
@@ -1,13 +1,22 @@
-mod lens;
+mod constraint;
+pub mod lens;
 mod migration;
 pub mod migrator;
 mod schema;
+pub mod template;
 pub mod type_;
 mod value;
+mod version;
 
-pub use lens::{AddRemoveField, Lens};
-pub use migration::Migration;
+pub use constraint::{Constraint, Pattern};
+pub use lens::{
+    AddRemoveField, Convert, CrossTable, EnumVariant, FieldConstraint, HoistPlunge, Lens,
+    MapVariants, Mapping, Nullability, SplitMerge, WrapHead,
+};
+pub use migration::{DeletePolicy, Migration};
 pub use migrator::Migrator;
 pub use schema::{Field, Schema};
+pub use template::Template;
 pub use type_::Type;
 pub use value::Value;
+pub use version::Version;
@@ -1,8 +1,45 @@
-use crate::Lens;
+use crate::{Lens, Version};
 
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct Migration {
     pub schema: String,
-    pub version: usize,
+    pub version: Version,
+    /// The versions this migration builds on. Leave it empty to build on the
+    /// number before, if `version` is a number. More than one parent merges
+    /// branches together.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub parents: Vec<Version>,
+    /// How the table settles a delete racing an update. Every replica has
+    /// to agree on this, so only a table's first migration can set it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delete_policy: Option<DeletePolicy>,
     pub ops: Vec<Lens>,
 }
+
+impl Migration {
+    /// The versions this migration builds on, filling in the default. Empty
+    /// if there's no default to fill in.
+    pub fn parents(&self) -> Vec<Version> {
+        if !self.parents.is_empty() {
+            return self.parents.clone();
+        }
+
+        self.version
+            .number()
+            .and_then(|number| number.checked_sub(1))
+            .map(Version::from)
+            .into_iter()
+            .collect()
+    }
+}
+
+/// What to do when a row is deleted on one replica and updated on another.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DeletePolicy {
+    /// Any field write after the delete brings the row back.
+    #[default]
+    AddWins,
+    /// The row stays deleted until someone explicitly undeletes it.
+    RemoveWins,
+}
@@ -1,102 +1,701 @@
-use crate::{Lens, Migration, Schema, lens};
-use std::collections::BTreeMap;
+use crate::{CrossTable, DeletePolicy, Lens, Migration, Schema, Type, Value, Version, lens};
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct Migrator {
-    paths: BTreeMap<String, BTreeMap<(usize, usize), Vec<Lens>>>,
+    /// The lenses for each step between a version and one of its parents,
+    /// in both directions: `paths[table][from][to]`.
+    paths: BTreeMap<String, BTreeMap<Version, BTreeMap<Version, Vec<Lens>>>>,
+    migrations: BTreeMap<String, BTreeMap<Version, Migration>>,
+    links: Vec<Link>,
+}
+
+/// A field moved from one table to another by a matching `MoveOut` and
+/// `MoveIn`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Link {
+    pub lens: CrossTable,
+    /// The version of `lens.from` that moves the field out.
+    pub from_version: Version,
+    /// The version of `lens.from` that `from_version` builds on, which is
+    /// the last one to have the field.
+    pub before: Version,
+    /// The version of `lens.to` that moves it in.
+    pub to_version: Version,
 }
 
 impl Migrator {
-    pub fn add_migration(&mut self, migration: Migration) {
-        let Migration {
-            schema: table,
-            version,
-            ops,
-        } = migration;
+    /// Register a migration. Its parents have to be registered first, since
+    /// we check it against the schema they produce.
+    pub fn add_migration(&mut self, migration: Migration) -> Result<(), Error> {
+        self.add_migrations(vec![migration])
+    }
 
-        let table_entry = self.paths.entry(table).or_default();
+    /// Register several migrations at once. They can come in any order, as
+    /// long as each one's parents are registered already or in the set.
+    /// Moving a field between tables takes a migration for each of them, so
+    /// those have to be added together: either the whole set goes in or
+    /// none of it does.
+    pub fn add_migrations(&mut self, migrations: Vec<Migration>) -> Result<(), Error> {
+        let links = find_links(&migrations)?;
 
-        if version == 0 {
-            todo!("Raise an error if version is 0; that's reserved for the blank schema");
+        // Everything we add, so we can take it out again if something fails.
+        let mut added = Vec::new();
+        let result = self.add_all(migrations, &links, &mut added);
+
+        match result {
+            Ok(()) => {
+                for link in links {
+                    if !self.links.contains(&link) {
+                        self.links.push(link);
+                    }
+                }
+            }
+            Err(_) => self.remove_all(added),
         }
 
-        table_entry.insert(
-            (version, version - 1),
-            ops.iter().rev().map(|op| op.reversed()).collect(),
-        );
-        table_entry.insert((version - 1, version), ops);
+        result
     }
 
-    pub fn migration_path(&self, schema: &str, from: usize, to: usize) -> Option<Vec<&Lens>> {
-        let mut out = Vec::new();
+    fn add_all(
+        &mut self,
+        mut migrations: Vec<Migration>,
+        links: &[Link],
+        added: &mut Vec<(String, Version)>,
+    ) -> Result<(), Error> {
+        while !migrations.is_empty() {
+            // If nothing is ready, adding the first one anyway says why.
+            let ready = migrations
+                .iter()
+                .position(|migration| self.has_parents(migration))
+                .unwrap_or(0);
+            let migration = migrations.remove(ready);
 
-        if from == to {
-            return None;
+            let key = (migration.schema.clone(), migration.version.clone());
+            if self.add_one(migration)? {
+                added.push(key);
+            }
         }
 
-        let direction = if from < to {
-            Direction::Up
-        } else {
-            Direction::Down
-        };
+        for link in links {
+            self.check_link(link)?;
+        }
+
+        Ok(())
+    }
 
-        let mut current = from;
+    /// Undo `add_all`, newest first so every version goes before its
+    /// parents.
+    fn remove_all(&mut self, added: Vec<(String, Version)>) {
+        for (table, version) in added.into_iter().rev() {
+            let Some(migrations) = self.migrations.get_mut(&table) else {
+                continue;
+            };
+            let Some(migration) = migrations.remove(&version) else {
+                continue;
+            };
+            if migrations.is_empty() {
+                self.migrations.remove(&table);
+            }
 
-        if let Some(paths) = self.paths.get(schema) {
-            while current != to {
-                match paths.get(&(current, direction.tick(current))) {
-                    Some(path) => {
-                        out.extend(path);
-                        current = direction.tick(current);
+            if let Some(paths) = self.paths.get_mut(&table) {
+                paths.remove(&version);
+                for parent in migration.parents() {
+                    if let Some(steps) = paths.get_mut(&parent) {
+                        steps.remove(&version);
+                        if steps.is_empty() {
+                            paths.remove(&parent);
+                        }
                     }
-                    // TODO: say why. This is an error case.
-                    None => return None,
                 }
+                if paths.is_empty() {
+                    self.paths.remove(&table);
+                }
+            }
+        }
+    }
+
+    /// Add a single migration, whose parents are all registered. Returns
+    /// whether it was new.
+    fn add_one(&mut self, migration: Migration) -> Result<bool, Error> {
+        let table = migration.schema.clone();
+        let version = migration.version.clone();
+
+        if version.is_root() {
+            return Err(Error::ReservedVersion(table));
+        }
+
+        if let Some(existing) = self
+            .migrations
+            .get(&table)
+            .and_then(|migrations| migrations.get(&version))
+        {
+            return if existing.parents() == migration.parents()
+                && existing.delete_policy == migration.delete_policy
+                && existing.ops == migration.ops
+            {
+                Ok(false)
+            } else {
+                Err(Error::DuplicateVersion(table, version))
+            };
+        }
+
+        let parents = migration.parents();
+        if parents.is_empty() {
+            return Err(Error::MissingParents(table, version));
+        }
+        if !self.has_parents(&migration) {
+            return Err(Error::MissingPreviousVersion(table, version));
+        }
+
+        if parents == [Version::root()] {
+            if self.roots(&table).any(|root| {
+                root.delete_policy.unwrap_or_default()
+                    != migration.delete_policy.unwrap_or_default()
+            }) {
+                return Err(Error::ConflictingDeletePolicy(table));
+            }
+        } else if migration.delete_policy.is_some() {
+            return Err(Error::MisplacedDeletePolicy(table, version));
+        }
+
+        let (mut schema, steps) = match &parents[..] {
+            [parent] => (self.schema(&table, parent)?, vec![Vec::new()]),
+            _ => self.merge(&table, &version, &parents)?,
+        };
+        // Paths keep the decoded ops, so that their values match what
+        // records hold.
+        let mut ops = Vec::new();
+        for op in &migration.ops {
+            let op = op
+                .decoded(&schema)
+                .and_then(|op| op.transform_schema(&mut schema).map(|()| op))
+                .map_err(|err| Error::InvalidOps(table.clone(), version.clone(), Box::new(err)))?;
+            ops.push(op);
+        }
+
+        let table_paths = self.paths.entry(table.clone()).or_default();
+        for (parent, mut lenses) in parents.into_iter().zip(steps) {
+            lenses.extend(ops.iter().cloned());
+
+            table_paths.entry(version.clone()).or_default().insert(
+                parent.clone(),
+                lenses.iter().rev().map(Lens::reversed).collect(),
+            );
+            table_paths
+                .entry(parent)
+                .or_default()
+                .insert(version.clone(), lenses);
+        }
+        self.migrations
+            .entry(table)
+            .or_default()
+            .insert(version, migration);
+
+        Ok(true)
+    }
+
+    /// Work out what merging `parents` looks like. Each parent's branch (the
+    /// steps from where the branches split) gets replayed on top of the
+    /// others, and that has to come out the same whichever parent we start
+    /// from. Returns the merged schema, plus the lenses that bring each
+    /// parent up to it.
+    fn merge(
+        &self,
+        table: &str,
+        version: &Version,
+        parents: &[Version],
+    ) -> Result<(Schema, Vec<Vec<Lens>>), Error> {
+        let base = self.merge_base(table, version, parents)?;
+
+        let mut branches = Vec::new();
+        for parent in parents {
+            branches.push(if *parent == base {
+                Vec::new()
+            } else {
+                self.migration_path(table, &base, parent)?
+            });
+        }
+
+        let mut merged = None;
+        let mut steps = Vec::new();
+        for (index, parent) in parents.iter().enumerate() {
+            let lenses: Vec<Lens> = branches
+                .iter()
+                .enumerate()
+                .filter(|(other, _)| *other != index)
+                .flat_map(|(_, branch)| branch.iter().map(|lens| (*lens).clone()))
+                .collect();
+
+            let mut schema = self.schema(table, parent)?;
+            for lens in &lenses {
+                lens.transform_schema(&mut schema).map_err(|err| {
+                    Error::ConflictingBranches(table.to_string(), version.clone(), Box::new(err))
+                })?;
+            }
+
+            match &merged {
+                None => merged = Some(schema),
+                Some(merged) if *merged != schema => {
+                    return Err(Error::DivergentBranches(table.to_string(), version.clone()));
+                }
+                Some(_) => {}
+            }
+            steps.push(lenses);
+        }
+
+        Ok((merged.unwrap_or_default(), steps))
+    }
+
+    /// The closest version that all of `parents` build on.
+    fn merge_base(
+        &self,
+        table: &str,
+        version: &Version,
+        parents: &[Version],
+    ) -> Result<Version, Error> {
+        let mut common = self.ancestors(table, &parents[0]);
+        for parent in &parents[1..] {
+            let ancestors = self.ancestors(table, parent);
+            common.retain(|candidate| ancestors.contains(candidate));
+        }
+
+        // Anything another common ancestor builds on is further away.
+        let mut closest = common.iter().filter(|candidate| {
+            !common.iter().any(|other| {
+                other != *candidate && self.ancestors(table, other).contains(*candidate)
+            })
+        });
+
+        match (closest.next(), closest.next()) {
+            (Some(base), None) => Ok(base.clone()),
+            _ => Err(Error::AmbiguousMergeBase(
+                table.to_string(),
+                version.clone(),
+            )),
+        }
+    }
+
+    /// `version` and every version it builds on, all the way back to the
+    /// empty schema.
+    fn ancestors(&self, table: &str, version: &Version) -> BTreeSet<Version> {
+        let mut out = BTreeSet::new();
+        let mut stack = vec![version.clone()];
+
+        while let Some(current) = stack.pop() {
+            if let Some(migration) = self
+                .migrations
+                .get(table)
+                .and_then(|migrations| migrations.get(&current))
+                && !out.contains(&current)
+            {
+                stack.extend(migration.parents());
+            }
+            out.insert(current);
+        }
+
+        out
+    }
+
+    /// The migrations that start `table` off from the empty schema.
+    fn roots(&self, table: &str) -> impl Iterator<Item = &Migration> {
+        self.migrations
+            .get(table)
+            .into_iter()
+            .flat_map(|migrations| migrations.values())
+            .filter(|migration| migration.parents() == [Version::root()])
+    }
+
+    /// How `table` settles a delete racing an update, as its first
+    /// migration says.
+    pub fn delete_policy(&self, table: &str) -> DeletePolicy {
+        self.roots(table)
+            .find_map(|root| root.delete_policy)
+            .unwrap_or_default()
+    }
+
+    /// Whether `version` builds on `ancestor`, directly or through other
+    /// versions. Every version builds on itself.
+    pub fn descends(
+        &self,
+        table: &str,
+        version: impl Into<Version>,
+        ancestor: impl Into<Version>,
+    ) -> bool {
+        self.ancestors(table, &version.into())
+            .contains(&ancestor.into())
+    }
+
+    fn has_version(&self, table: &str, version: &Version) -> bool {
+        version.is_root()
+            || self
+                .migrations
+                .get(table)
+                .is_some_and(|migrations| migrations.contains_key(version))
+    }
+
+    fn has_parents(&self, migration: &Migration) -> bool {
+        migration
+            .parents()
+            .iter()
+            .all(|parent| self.has_version(&migration.schema, parent))
+    }
+
+    /// Both ends of a move need a schema to exist, and the reference has to
+    /// be able to hold a row ID.
+    fn check_link(&self, link: &Link) -> Result<(), Error> {
+        let lens = &link.lens;
+        let schema = self.schema(&lens.to, &link.to_version)?;
+
+        let reference = schema.get(&lens.reference).map(|field| match &field.type_ {
+            Type::Nullable(inner) => inner.as_ref(),
+            type_ => type_,
+        });
+
+        match reference {
+            Some(Type::String | Type::Uuid) => Ok(()),
+            _ => Err(Error::InvalidReference(
+                lens.to.clone(),
+                link.to_version.clone(),
+                lens.reference.clone(),
+            )),
+        }
+    }
+
+    /// Whether `link`'s field lives in `table` at `version`, and lived in
+    /// the other table at some other version.
+    pub fn lives_in(&self, link: &Link, table: &str, version: impl Into<Version>) -> bool {
+        let version = version.into();
+
+        (table == link.lens.to && self.descends(table, &version, &link.to_version))
+            || (table == link.lens.from && !self.descends(table, &version, &link.from_version))
+    }
+
+    /// Moves that matter when reading `table` at `version`: ones for fields
+    /// that live in `table` now, but were in another table at some point.
+    pub fn links(&self, table: &str, version: impl Into<Version>) -> impl Iterator<Item = &Link> {
+        let version = version.into();
+
+        self.links
+            .iter()
+            .filter(move |link| self.lives_in(link, table, &version))
+    }
+
+    /// Check that the versions we're going to use for each table agree about
+    /// where moved fields live. Otherwise values would go missing: written to
+    /// one table, but read from the other.
+    pub fn check_versions(&self, versions: &BTreeMap<String, Version>) -> Result<(), Error> {
+        for link in &self.links {
+            let lens = &link.lens;
+            let (Some(from), Some(to)) = (versions.get(&lens.from), versions.get(&lens.to)) else {
+                continue;
+            };
+
+            if self.descends(&lens.from, from, &link.from_version)
+                != self.descends(&lens.to, to, &link.to_version)
+            {
+                return Err(Error::MismatchedVersions {
+                    name: lens.field.name.clone(),
+                    from: format!("{}.{from}", lens.from),
+                    to: format!("{}.{to}", lens.to),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Get the value of a moved field from a record written to the other
+    /// table at version `written`, shaped for reading `table` at `version`.
+    /// Returns `None` if the field wasn't in that table when the record was
+    /// written.
+    pub fn transform_linked(
+        &self,
+        link: &Link,
+        table: &str,
+        version: impl Into<Version>,
+        written: impl Into<Version>,
+        mut record: BTreeMap<String, Value>,
+    ) -> Result<Option<Value>, Error> {
+        let (version, written) = (version.into(), written.into());
+        let lens = &link.lens;
+        let name = &lens.field.name;
+
+        // Get the record to the version right before (or right after) the
+        // move on its side, then the value from the matching version on ours.
+        let (other, other_version, here_version) = if !self.lives_in(link, table, &version) {
+            return Ok(None);
+        } else if *table == lens.to {
+            if self.descends(&lens.from, &written, &link.from_version) {
+                return Ok(None);
             }
+            (&lens.from, &link.before, &link.to_version)
         } else {
-            // TODO: say why. This is an error case.
-            return None;
+            if !self.descends(&lens.to, &written, &link.to_version) {
+                return Ok(None);
+            }
+            (&lens.to, &link.to_version, &link.before)
+        };
+
+        if written != *other_version {
+            for lens in self.migration_path(other, &written, other_version)? {
+                lens.transform_record(&mut record)
+                    .map_err(Error::CouldNotApply)?;
+            }
+        }
+
+        let Some(value) = record.remove(name) else {
+            return Ok(None);
         };
+        let mut moved = BTreeMap::from([(name.clone(), value)]);
+
+        if *here_version != version {
+            for lens in self.migration_path(table, here_version, &version)? {
+                lens.transform_record(&mut moved)
+                    .map_err(Error::CouldNotApply)?;
+            }
+        }
 
-        Some(out)
+        Ok(moved.remove(name))
     }
 
-    pub fn schema(&self, schema: &str, version: usize) -> Result<Schema, Error> {
-        let mut out = Schema::default();
+    /// The lenses that take a record from `from` to `to` in the fewest
+    /// steps. Once versions branch, that can mean going back down one branch
+    /// and up another.
+    pub fn migration_path(
+        &self,
+        schema: &str,
+        from: impl Into<Version>,
+        to: impl Into<Version>,
+    ) -> Result<Vec<&Lens>, Error> {
+        let (from, to) = (from.into(), to.into());
+
+        if from == to {
+            return Err(Error::SameVersion(schema.to_string(), from));
+        }
+
+        let paths = self
+            .paths
+            .get(schema)
+            .ok_or_else(|| Error::UnknownSchema(schema.to_string()))?;
 
-        for lens in self
-            .migration_path(schema, 0, version)
-            .ok_or_else(|| Error::MigrationPathNotFound(schema.to_string(), version))?
+        if !self.has_version(schema, &from) {
+            return Err(self.missing(schema, &from, false));
+        }
+        if !self.has_version(schema, &to) {
+            return Err(self.missing(schema, &to, true));
+        }
+
+        // Breadth-first, remembering where we came from at each version.
+        let mut previous = BTreeMap::from([(&from, &from)]);
+        let mut queue = VecDeque::from([&from]);
+        while let Some(current) = queue.pop_front()
+            && *current != to
         {
+            for next in paths.get(current).into_iter().flat_map(BTreeMap::keys) {
+                if let Entry::Vacant(entry) = previous.entry(next) {
+                    entry.insert(current);
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        let mut steps = Vec::new();
+        let mut current = &to;
+        while *current != from {
+            let Some(before) = previous.get(current) else {
+                return Err(Error::MissingStep(schema.to_string(), from, to));
+            };
+            steps.push(&paths[*before][current]);
+            current = before;
+        }
+
+        Ok(steps.into_iter().rev().flatten().collect())
+    }
+
+    /// Explain why there's no path to or from `version`, which isn't
+    /// registered. For numbered versions, that's the first step missing from
+    /// the numbers leading up to it.
+    fn missing(&self, table: &str, version: &Version, up: bool) -> Error {
+        let Some(number) = version.number() else {
+            return Error::UnknownVersion(table.to_string(), version.clone());
+        };
+
+        let known = (0..number)
+            .rev()
+            .find(|known| self.has_version(table, &Version::from(*known)))
+            .unwrap_or_default();
+        let (below, above) = (Version::from(known), Version::from(known + 1));
+
+        if up {
+            Error::MissingStep(table.to_string(), below, above)
+        } else {
+            Error::MissingStep(table.to_string(), above, below)
+        }
+    }
+
+    pub fn schema(&self, schema: &str, version: impl Into<Version>) -> Result<Schema, Error> {
+        let version = version.into();
+        let mut out = Schema::default();
+
+        if version.is_root() {
+            return Ok(out);
+        }
+
+        for lens in self.migration_path(schema, Version::root(), version)? {
             lens.transform_schema(&mut out)
                 .map_err(Error::CouldNotApply)?;
         }
 
         Ok(out)
     }
+
+    /// Move a record written against version `from` of a schema to version
+    /// `to`, filling in defaults for any fields the record doesn't have.
+    pub fn transform(
+        &self,
+        schema: &str,
+        from: impl Into<Version>,
+        to: impl Into<Version>,
+        mut record: BTreeMap<String, Value>,
+    ) -> Result<BTreeMap<String, Value>, Error> {
+        let (from, to) = (from.into(), to.into());
+
+        if from != to {
+            for lens in self.migration_path(schema, &from, &to)? {
+                lens.transform_record(&mut record)
+                    .map_err(Error::CouldNotApply)?;
+            }
+        }
+
+        for (name, field) in self.schema(schema, to)? {
+            let mut value = field.default;
+            if let Some(written) = record.remove(&name) {
+                value.overlay(written);
+            }
+            record.insert(name, value);
+        }
+
+        Ok(record)
+    }
 }
 
 #[derive(Debug, thiserror::Error, PartialEq)]
 pub enum Error {
-    #[error("could not find path to migration ({0}.{1})")]
-    MigrationPathNotFound(String, usize),
+    #[error("no migrations registered for schema `{0}`")]
+    UnknownSchema(String),
+    #[error("no migration registered for {0}.{1}")]
+    UnknownVersion(String, Version),
+    #[error("missing migration step from {0}.{1} to {0}.{2}")]
+    MissingStep(String, Version, Version),
+    #[error("already at {0}.{1}; there's no path to take")]
+    SameVersion(String, Version),
     #[error("could not apply operation: {0}")]
     CouldNotApply(lens::Error),
+    #[error("version 0 is reserved for the empty schema ({0}.0)")]
+    ReservedVersion(String),
+    #[error("a different migration is already registered for {0}.{1}")]
+    DuplicateVersion(String, Version),
+    #[error("migration {0}.{1} needs `parents`, since its version isn't a number")]
+    MissingParents(String, Version),
+    #[error("migration {0}.{1} was added before the versions leading up to it")]
+    MissingPreviousVersion(String, Version),
+    #[error("migration {0}.{1} sets a delete policy, but only a table's first migration can")]
+    MisplacedDeletePolicy(String, Version),
+    #[error("the first migrations for `{0}` disagree about its delete policy")]
+    ConflictingDeletePolicy(String),
+    #[error("migration {0}.{1} does not apply to the previous version: {2}")]
+    InvalidOps(String, Version, Box<lens::Error>),
+    #[error("migration {0}.{1} merges branches that conflict: {2}")]
+    ConflictingBranches(String, Version, Box<lens::Error>),
+    #[error(
+        "migration {0}.{1} merges branches that give different schemas depending on which goes first"
+    )]
+    DivergentBranches(String, Version),
+    #[error("migration {0}.{1} merges branches that don't have a single closest version in common")]
+    AmbiguousMergeBase(String, Version),
+    #[error(
+        "migration {0}.{1} moves `{2}` between tables, but the other side of the move is missing"
+    )]
+    UnmatchedMove(String, Version, String),
+    #[error(
+        "migration {0}.{1} moves `{2}`, but belongs to neither the table it moves from nor the one it moves to"
+    )]
+    MisplacedMove(String, Version, String),
+    #[error("migration {0}.{1} moves `{2}` out of its table, so it can't also merge branches")]
+    MergedMove(String, Version, String),
+    #[error("`{2}` in {0}.{1} has to be a string or UUID field to refer to rows")]
+    InvalidReference(String, Version, String),
+    #[error("{from} and {to} disagree about which table `{name}` is in")]
+    MismatchedVersions {
+        name: String,
+        from: String,
+        to: String,
+    },
 }
 
-#[derive(Debug)]
-enum Direction {
-    Up,
-    Down,
-}
+/// Pair up the `MoveOut` and `MoveIn` halves of cross-table moves.
+fn find_links(migrations: &[Migration]) -> Result<Vec<Link>, Error> {
+    let mut outs = Vec::new();
+    let mut ins = Vec::new();
 
-impl Direction {
-    fn tick(&self, n: usize) -> usize {
-        match self {
-            Direction::Up => n.saturating_add(1),
-            Direction::Down => n.saturating_sub(1),
+    for migration in migrations {
+        for op in &migration.ops {
+            let (lens, expected, halves) = match op {
+                Lens::MoveOut(lens) => (lens, &lens.from, &mut outs),
+                Lens::MoveIn(lens) => (lens, &lens.to, &mut ins),
+                _ => continue,
+            };
+
+            if migration.schema != *expected || lens.from == lens.to {
+                return Err(Error::MisplacedMove(
+                    migration.schema.clone(),
+                    migration.version.clone(),
+                    lens.field.name.clone(),
+                ));
+            }
+            halves.push((migration, lens));
         }
     }
+
+    let mut links = Vec::new();
+    for (migration, lens) in outs {
+        // Reading across the move needs the one version that still had the
+        // field, so moving out can't happen in a merge.
+        let [before] = &migration.parents()[..] else {
+            return Err(Error::MergedMove(
+                lens.from.clone(),
+                migration.version.clone(),
+                lens.field.name.clone(),
+            ));
+        };
+
+        let Some(index) = ins.iter().position(|(_, other)| *other == lens) else {
+            return Err(Error::UnmatchedMove(
+                lens.from.clone(),
+                migration.version.clone(),
+                lens.field.name.clone(),
+            ));
+        };
+        let (to, _) = ins.remove(index);
+
+        links.push(Link {
+            lens: lens.clone(),
+            from_version: migration.version.clone(),
+            before: before.clone(),
+            to_version: to.version.clone(),
+        });
+    }
+
+    match ins.first() {
+        Some((to, lens)) => Err(Error::UnmatchedMove(
+            lens.to.clone(),
+            to.version.clone(),
+            lens.field.name.clone(),
+        )),
+        None => Ok(links),
+    }
 }
 
 #[cfg(test)]
@@ -123,10 +722,12 @@ mod tests {
         }});
         let migration_a = Migration {
             schema: "test".into(),
-            version: 1,
+            version: 1.into(),
+            parents: Vec::new(),
+            delete_policy: None,
             ops: vec![lens_a.clone()],
         };
-        migrator.add_migration(migration_a.clone());
+        migrator.add_migration(migration_a.clone()).unwrap();
 
         let lens_b = lens!({"rename": {
             "from": "a",
@@ -134,10 +735,12 @@ mod tests {
         }});
         let migration_b = Migration {
             schema: "test".into(),
-            version: 2,
+            version: 2.into(),
+            parents: Vec::new(),
+            delete_policy: None,
             ops: vec![lens_b.clone()],
         };
-        migrator.add_migration(migration_b.clone());
+        migrator.add_migration(migration_b.clone()).unwrap();
 
         let lens_c = lens!({"rename": {
             "from": "b",
@@ -145,36 +748,181 @@ mod tests {
         }});
         let migration_c = Migration {
             schema: "test".into(),
-            version: 3,
+            version: 3.into(),
+            parents: Vec::new(),
+            delete_policy: None,
             ops: vec![lens_c.clone()],
         };
-        migrator.add_migration(migration_c.clone());
+        migrator.add_migration(migration_c.clone()).unwrap();
 
         assert_eq!(
-            Some(vec![&lens_a, &lens_b, &lens_c]),
+            Ok(vec![&lens_a, &lens_b, &lens_c]),
             migrator.migration_path("test", 0, 3)
         );
 
         println!("========================");
 
         assert_eq!(
-            Some(vec![&lens_c.reversed(), &lens_b.reversed()]),
+            Ok(vec![&lens_c.reversed(), &lens_b.reversed()]),
             migrator.migration_path("test", 3, 1)
         );
     }
 
+    #[test]
+    fn migration_path_same_version() {
+        let migrator = Migrator::default();
+
+        assert_eq!(
+            migrator.migration_path("test", 1, 1),
+            Err(Error::SameVersion("test".into(), 1.into()))
+        );
+    }
+
+    #[test]
+    fn migration_path_missing_step() {
+        let mut migrator = Migrator::default();
+        migrator
+            .add_migration(Migration {
+                schema: "test".into(),
+                version: 1.into(),
+                parents: Vec::new(),
+                delete_policy: None,
+                ops: Vec::new(),
+            })
+            .unwrap();
+
+        assert_eq!(
+            migrator.migration_path("test", 0, 3),
+            Err(Error::MissingStep("test".into(), 1.into(), 2.into()))
+        );
+        assert_eq!(
+            migrator.migration_path("test", 2, 0),
+            Err(Error::MissingStep("test".into(), 2.into(), 1.into()))
+        );
+    }
+
     #[test]
     fn schema_missing() {
         let migrator = Migrator::default();
 
         assert_eq!(
             migrator.schema("nope", 1),
-            Err(Error::MigrationPathNotFound("nope".into(), 1))
+            Err(Error::UnknownSchema("nope".into()))
         )
     }
 
     #[test]
-    fn schema_conflict() {
+    fn schema_zero_is_empty() {
+        let migrator = Migrator::default();
+
+        assert_eq!(migrator.schema("anything", 0), Ok(Schema::default()));
+    }
+
+    #[test]
+    fn add_migration_version_zero() {
+        let mut migrator = Migrator::default();
+
+        assert_eq!(
+            migrator.add_migration(Migration {
+                schema: "test".into(),
+                version: 0.into(),
+                parents: Vec::new(),
+                delete_policy: None,
+                ops: Vec::new(),
+            }),
+            Err(Error::ReservedVersion("test".into()))
+        );
+    }
+
+    #[test]
+    fn add_migration_duplicate() {
+        let mut migrator = Migrator::default();
+
+        let migration = Migration {
+            schema: "test".into(),
+            version: 1.into(),
+            parents: Vec::new(),
+            delete_policy: None,
+            ops: vec![lens!({"add": {
+                "name": "a",
+                "type": "string",
+                "nullable": true,
+            }})],
+        };
+        migrator.add_migration(migration.clone()).unwrap();
+
+        // Adding the same migration again is harmless...
+        assert_eq!(migrator.add_migration(migration), Ok(()));
+
+        // ...but a different one with the same version is not.
+        assert_eq!(
+            migrator.add_migration(Migration {
+                schema: "test".into(),
+                version: 1.into(),
+                parents: Vec::new(),
+                delete_policy: None,
+                ops: Vec::new(),
+            }),
+            Err(Error::DuplicateVersion("test".into(), 1.into()))
+        );
+    }
+
+    #[test]
+    fn add_migration_out_of_order() {
+        let mut migrator = Migrator::default();
+
+        assert_eq!(
+            migrator.add_migration(Migration {
+                schema: "test".into(),
+                version: 2.into(),
+                parents: Vec::new(),
+                delete_policy: None,
+                ops: Vec::new(),
+            }),
+            Err(Error::MissingPreviousVersion("test".into(), 2.into()))
+        );
+    }
+
+    #[test]
+    fn delete_policy_comes_from_first_migration() {
+        let mut migrator = Migrator::default();
+        migrator
+            .add_migration(Migration {
+                schema: "test".into(),
+                version: 1.into(),
+                parents: Vec::new(),
+                delete_policy: Some(DeletePolicy::RemoveWins),
+                ops: Vec::new(),
+            })
+            .unwrap();
+
+        assert_eq!(migrator.delete_policy("test"), DeletePolicy::RemoveWins);
+        assert_eq!(migrator.delete_policy("other"), DeletePolicy::AddWins);
+
+        assert_eq!(
+            migrator.add_migration(Migration {
+                schema: "test".into(),
+                version: 2.into(),
+                parents: Vec::new(),
+                delete_policy: Some(DeletePolicy::AddWins),
+                ops: Vec::new(),
+            }),
+            Err(Error::MisplacedDeletePolicy("test".into(), 2.into()))
+        );
+        assert_eq!(
+            migrator.add_migration(Migration {
+                schema: "test".into(),
+                version: 3.into(),
+                parents: vec![0.into()],
+                delete_policy: None,
+                ops: Vec::new(),
+            }),
+            Err(Error::ConflictingDeletePolicy("test".into()))
+        );
+    }
+
+    #[test]
+    fn add_migration_conflict() {
         let mut migrator = Migrator::default();
 
         let same_lens = lens!({"add": {
@@ -183,37 +931,52 @@ mod tests {
             "nullable": true,
         }});
 
-        migrator.add_migration(Migration {
-            schema: "test".into(),
-            version: 1,
-            ops: vec![same_lens.clone()],
-        });
-        migrator.add_migration(Migration {
-            schema: "test".into(),
-            version: 2,
-            ops: vec![same_lens],
-        });
-
-        let err = migrator.schema("test", 2).unwrap_err();
+        migrator
+            .add_migration(Migration {
+                schema: "test".into(),
+                version: 1.into(),
+                parents: Vec::new(),
+                delete_policy: None,
+                ops: vec![same_lens.clone()],
+            })
+            .unwrap();
 
-        assert!(
-            matches!(err, Error::CouldNotApply(..)),
-            "Expected CouldNotApply, got {err:?}"
+        assert_eq!(
+            migrator.add_migration(Migration {
+                schema: "test".into(),
+                version: 2.into(),
+                parents: Vec::new(),
+                delete_policy: None,
+                ops: vec![same_lens],
+            }),
+            Err(Error::InvalidOps(
+                "test".into(),
+                2.into(),
+                Box::new(lens::Error::ConflictingFieldOnAdd("a".into()))
+            ))
+        );
+        assert_eq!(
+            migrator.schema("test", 2),
+            Err(Error::MissingStep("test".into(), 1.into(), 2.into()))
         );
     }
 
     #[test]
     fn schema_success() {
         let mut migrator = Migrator::default();
-        migrator.add_migration(Migration {
-            schema: "test".into(),
-            version: 1,
-            ops: vec![lens!({"add": {
-                "name": "a",
-                "type": "string",
-                "nullable": true,
-            }})],
-        });
+        migrator
+            .add_migration(Migration {
+                schema: "test".into(),
+                version: 1.into(),
+                parents: Vec::new(),
+                delete_policy: None,
+                ops: vec![lens!({"add": {
+                    "name": "a",
+                    "type": "string",
+                    "nullable": true,
+                }})],
+            })
+            .unwrap();
 
         assert_eq!(
             migrator.schema("test", 1),
@@ -222,8 +985,699 @@ mod tests {
                 Field {
                     type_: Type::Nullable(Box::new(Type::String)),
                     default: Value::Null,
+                    constraints: Vec::new(),
                 }
             )]))
         )
     }
+
+    fn todo_migrator() -> Migrator {
+        let mut migrator = Migrator::default();
+        migrator
+            .add_migration(Migration {
+                schema: "todo".into(),
+                version: 1.into(),
+                parents: Vec::new(),
+                delete_policy: None,
+                ops: vec![
+                    lens!({"add": {"name": "title", "type": "string", "default": ""}}),
+                    lens!({"add": {"name": "done", "type": "bool", "default": false}}),
+                    lens!({"add": {"name": "notes", "type": "string", "nullable": true}}),
+                ],
+            })
+            .unwrap();
+        migrator
+            .add_migration(Migration {
+                schema: "todo".into(),
+                version: 2.into(),
+                parents: Vec::new(),
+                delete_policy: None,
+                ops: vec![
+                    lens!({"rename": {"from": "title", "to": "name"}}),
+                    lens!({"convert": {
+                        "name": "done",
+                        "from": "bool",
+                        "to": "string",
+                        "forward": {"table": [[true, "done"], [false, "todo"]]},
+                        "backward": {"table": [["done", true], ["todo", false]]},
+                    }}),
+                    lens!({"remove": {"name": "notes", "type": "string", "nullable": true}}),
+                    lens!({"add": {"name": "priority", "type": "int", "default": 3}}),
+                ],
+            })
+            .unwrap();
+
+        migrator
+    }
+
+    #[test]
+    fn transform_up() {
+        let record = BTreeMap::from([
+            ("title".to_string(), Value::from("Write tests")),
+            ("done".to_string(), Value::from(true)),
+            ("notes".to_string(), Value::from("soon")),
+        ]);
+
+        assert_eq!(
+            todo_migrator().transform("todo", 1, 2, record),
+            Ok(BTreeMap::from([
+                ("name".to_string(), Value::from("Write tests")),
+                ("done".to_string(), Value::from("done")),
+                ("priority".to_string(), Value::from(3)),
+            ]))
+        );
+    }
+
+    #[test]
+    fn transform_down() {
+        let record = BTreeMap::from([
+            ("name".to_string(), Value::from("Write tests")),
+            ("done".to_string(), Value::from("todo")),
+            ("priority".to_string(), Value::from(1)),
+        ]);
+
+        assert_eq!(
+            todo_migrator().transform("todo", 2, 1, record),
+            Ok(BTreeMap::from([
+                ("title".to_string(), Value::from("Write tests")),
+                ("done".to_string(), Value::from(false)),
+                ("notes".to_string(), Value::Null),
+            ]))
+        );
+    }
+
+    #[test]
+    fn transform_tightened_field() {
+        let mut migrator = todo_migrator();
+        migrator
+            .add_migration(Migration {
+                schema: "todo".into(),
+                version: 3.into(),
+                parents: Vec::new(),
+                delete_policy: None,
+                ops: vec![lens!({"make_nullable": {"name": "priority", "default": 3}})],
+            })
+            .unwrap();
+        migrator
+            .add_migration(Migration {
+                schema: "todo".into(),
+                version: 4.into(),
+                parents: Vec::new(),
+                delete_policy: None,
+                ops: vec![lens!({"make_required": {"name": "priority", "default": 5}})],
+            })
+            .unwrap();
+
+        let record = BTreeMap::from([("priority".to_string(), Value::Null)]);
+
+        assert_eq!(
+            migrator
+                .transform("todo", 3, 4, record)
+                .map(|record| record["priority"].clone()),
+            Ok(Value::from(5))
+        );
+    }
+
+    #[test]
+    fn transform_fills_defaults() {
+        assert_eq!(
+            todo_migrator().transform("todo", 1, 1, BTreeMap::new()),
+            Ok(BTreeMap::from([
+                ("title".to_string(), Value::from("")),
+                ("done".to_string(), Value::from(false)),
+                ("notes".to_string(), Value::Null),
+            ]))
+        );
+    }
+
+    #[test]
+    fn transform_reports_bad_values() {
+        let record = BTreeMap::from([("done".to_string(), Value::from("maybe"))]);
+
+        let err = todo_migrator().transform("todo", 2, 1, record).unwrap_err();
+
+        assert!(
+            matches!(
+                err,
+                Error::CouldNotApply(lens::Error::CouldNotConvert { .. })
+            ),
+            "Expected CouldNotConvert, got {err:?}"
+        );
+    }
+
+    #[test]
+    fn transform_missing_path() {
+        assert_eq!(
+            todo_migrator().transform("todo", 1, 3, BTreeMap::new()),
+            Err(Error::MissingStep("todo".into(), 2.into(), 3.into()))
+        );
+    }
+
+    #[test]
+    fn paths_decode_bytes() {
+        let mut migrator = Migrator::default();
+        for (version, ops) in [
+            (
+                1,
+                vec![lens!({"add": {"name": "thumb", "type": "bytes", "default": "AQID"}})],
+            ),
+            (
+                2,
+                vec![lens!({"wrap": {"name": "thumb", "default": "BAUG"}})],
+            ),
+        ] {
+            migrator
+                .add_migration(Migration {
+                    schema: "photo".into(),
+                    version: version.into(),
+                    parents: Vec::new(),
+                    delete_policy: None,
+                    ops,
+                })
+                .unwrap();
+        }
+
+        let record = BTreeMap::from([("thumb".to_string(), Value::Array(Vec::new()))]);
+        assert_eq!(
+            migrator.transform("photo", 2, 1, record),
+            Ok(BTreeMap::from([(
+                "thumb".to_string(),
+                Value::Bytes(vec![4, 5, 6])
+            )]))
+        );
+    }
+
+    mod branches {
+        use super::*;
+        use pretty_assertions::assert_eq;
+
+        fn migration(version: impl Into<Version>, parents: &[&str], ops: Vec<Lens>) -> Migration {
+            Migration {
+                schema: "todo".into(),
+                version: version.into(),
+                parents: parents.iter().copied().map(Version::from).collect(),
+                delete_policy: None,
+                ops,
+            }
+        }
+
+        /// Two teams each build on version 1 without knowing about the
+        /// other: one adds `priority`, the other adds `tags`.
+        fn branched() -> Migrator {
+            let mut migrator = Migrator::default();
+            migrator
+                .add_migrations(vec![
+                    migration(
+                        1,
+                        &[],
+                        vec![lens!({"add": {"name": "title", "type": "string", "default": ""}})],
+                    ),
+                    migration(
+                        "2-priority",
+                        &["1"],
+                        vec![lens!({"add": {"name": "priority", "type": "int", "default": 3}})],
+                    ),
+                    migration(
+                        "2-tags",
+                        &["1"],
+                        vec![lens!({"add": {"name": "tags", "type": "string", "default": ""}})],
+                    ),
+                ])
+                .unwrap();
+            migrator
+        }
+
+        fn merged() -> Migrator {
+            let mut migrator = branched();
+            migrator
+                .add_migration(migration(3, &["2-priority", "2-tags"], Vec::new()))
+                .unwrap();
+            migrator
+        }
+
+        #[test]
+        fn branches_have_their_own_schemas() {
+            let migrator = branched();
+
+            let fields = |version| {
+                migrator
+                    .schema("todo", version)
+                    .unwrap()
+                    .iter()
+                    .map(|(name, _)| name.clone())
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(fields("2-priority"), ["priority", "title"]);
+            assert_eq!(fields("2-tags"), ["tags", "title"]);
+        }
+
+        #[test]
+        fn path_between_branches() {
+            let record = BTreeMap::from([
+                ("title".to_string(), Value::from("Write tests")),
+                ("priority".to_string(), Value::from(1)),
+            ]);
+
+            assert_eq!(
+                branched().transform("todo", "2-priority", "2-tags", record),
+                Ok(BTreeMap::from([
+                    ("title".to_string(), Value::from("Write tests")),
+                    ("tags".to_string(), Value::from("")),
+                ]))
+            );
+        }
+
+        #[test]
+        fn merge_has_both_branches() {
+            let migrator = merged();
+
+            assert_eq!(
+                migrator.schema("todo", 3),
+                Ok(Schema::from([
+                    (
+                        "title",
+                        Field {
+                            type_: Type::String,
+                            default: Value::from(""),
+                            constraints: Vec::new(),
+                        }
+                    ),
+                    (
+                        "priority",
+                        Field {
+                            type_: Type::Int,
+                            default: Value::from(3),
+                            constraints: Vec::new(),
+                        }
+                    ),
+                    (
+                        "tags",
+                        Field {
+                            type_: Type::String,
+                            default: Value::from(""),
+                            constraints: Vec::new(),
+                        }
+                    ),
+                ]))
+            );
+
+            let record = BTreeMap::from([("tags".to_string(), Value::from("home"))]);
+            assert_eq!(
+                migrator.transform("todo", "2-tags", 3, record),
+                Ok(BTreeMap::from([
+                    ("title".to_string(), Value::from("")),
+                    ("priority".to_string(), Value::from(3)),
+                    ("tags".to_string(), Value::from("home")),
+                ]))
+            );
+        }
+
+        #[test]
+        fn merge_can_have_ops() {
+            let mut migrator = branched();
+            migrator
+                .add_migration(migration(
+                    3,
+                    &["2-priority", "2-tags"],
+                    vec![lens!({"rename": {"from": "tags", "to": "labels"}})],
+                ))
+                .unwrap();
+
+            let record = BTreeMap::from([("priority".to_string(), Value::from(1))]);
+            assert_eq!(
+                migrator.transform("todo", "2-priority", 3, record),
+                Ok(BTreeMap::from([
+                    ("title".to_string(), Value::from("")),
+                    ("priority".to_string(), Value::from(1)),
+                    ("labels".to_string(), Value::from("")),
+                ]))
+            );
+        }
+
+        #[test]
+        fn conflicting_branches() {
+            let mut migrator = branched();
+            migrator
+                .add_migration(migration(
+                    "2-urgent",
+                    &["1"],
+                    vec![lens!({"add": {"name": "priority", "type": "string", "default": ""}})],
+                ))
+                .unwrap();
+
+            assert_eq!(
+                migrator.add_migration(migration(3, &["2-priority", "2-urgent"], Vec::new())),
+                Err(Error::ConflictingBranches(
+                    "todo".into(),
+                    3.into(),
+                    Box::new(lens::Error::ConflictingFieldOnAdd("priority".into()))
+                ))
+            );
+        }
+
+        #[test]
+        fn ambiguous_merge_base() {
+            let mut migrator = merged();
+            migrator
+                .add_migration(migration("3-again", &["2-tags", "2-priority"], Vec::new()))
+                .unwrap();
+
+            assert_eq!(
+                migrator.add_migration(migration(4, &["3", "3-again"], Vec::new())),
+                Err(Error::AmbiguousMergeBase("todo".into(), 4.into()))
+            );
+        }
+
+        #[test]
+        fn parents_can_come_later_in_a_set() {
+            let mut migrator = Migrator::default();
+
+            assert_eq!(
+                migrator.add_migrations(vec![
+                    migration(3, &["1", "2"], Vec::new()),
+                    migration(2, &["1"], Vec::new()),
+                    migration(1, &[], Vec::new()),
+                ]),
+                Ok(())
+            );
+            assert!(migrator.descends("todo", 3, 1));
+        }
+
+        #[test]
+        fn missing_parent() {
+            assert_eq!(
+                branched().add_migration(migration(3, &["2-priority", "2-nope"], Vec::new())),
+                Err(Error::MissingPreviousVersion("todo".into(), 3.into()))
+            );
+        }
+
+        #[test]
+        fn names_need_parents() {
+            assert_eq!(
+                branched().add_migration(migration("2-nope", &[], Vec::new())),
+                Err(Error::MissingParents("todo".into(), "2-nope".into()))
+            );
+        }
+
+        #[test]
+        fn same_name_different_parents() {
+            assert_eq!(
+                branched().add_migration(migration("2-tags", &["2-priority"], Vec::new())),
+                Err(Error::DuplicateVersion("todo".into(), "2-tags".into()))
+            );
+        }
+
+        #[test]
+        fn unknown_name() {
+            assert_eq!(
+                branched().schema("todo", "2-nope"),
+                Err(Error::UnknownVersion("todo".into(), "2-nope".into()))
+            );
+        }
+
+        #[test]
+        fn descends() {
+            let migrator = merged();
+
+            assert!(migrator.descends("todo", 3, "2-priority"));
+            assert!(migrator.descends("todo", 3, "2-tags"));
+            assert!(migrator.descends("todo", "2-tags", "2-tags"));
+            assert!(!migrator.descends("todo", "2-tags", "2-priority"));
+            assert!(!migrator.descends("todo", "2-priority", 3));
+        }
+
+        #[test]
+        fn failed_set_leaves_nothing_behind() {
+            let mut migrator = branched();
+
+            assert_eq!(
+                migrator.add_migrations(vec![
+                    migration(
+                        "2-done",
+                        &["1"],
+                        vec![lens!({"add": {"name": "done", "type": "bool", "default": false}})],
+                    ),
+                    migration(3, &["2-done", "2-nope"], Vec::new()),
+                ]),
+                Err(Error::MissingPreviousVersion("todo".into(), 3.into()))
+            );
+            assert_eq!(
+                migrator.schema("todo", "2-done"),
+                Err(Error::UnknownVersion("todo".into(), "2-done".into()))
+            );
+            assert!(!migrator.paths["todo"][&1.into()].contains_key(&"2-done".into()));
+
+            // Its name is free to use, once it's right.
+            assert_eq!(
+                migrator.add_migration(migration("2-done", &["2-tags"], Vec::new())),
+                Ok(())
+            );
+        }
+    }
+
+    mod cross_table {
+        use super::*;
+        use pretty_assertions::assert_eq;
+
+        fn move_avatar(direction: &str) -> Lens {
+            lens!({direction: {
+                "name": "avatar",
+                "type": "string",
+                "nullable": true,
+                "from": "user",
+                "to": "profile",
+                "reference": "user_id",
+            }})
+        }
+
+        fn version_one() -> Vec<Migration> {
+            vec![
+                Migration {
+                    schema: "profile".into(),
+                    version: 1.into(),
+                    parents: Vec::new(),
+                    delete_policy: None,
+                    ops: vec![
+                        lens!({"add": {"name": "user_id", "type": "uuid", "nullable": true}}),
+                        lens!({"add": {"name": "bio", "type": "string", "default": ""}}),
+                    ],
+                },
+                Migration {
+                    schema: "user".into(),
+                    version: 1.into(),
+                    parents: Vec::new(),
+                    delete_policy: None,
+                    ops: vec![
+                        lens!({"add": {"name": "name", "type": "string", "default": ""}}),
+                        lens!({"add": {"name": "avatar", "type": "string", "nullable": true}}),
+                    ],
+                },
+            ]
+        }
+
+        fn version_two() -> Vec<Migration> {
+            vec![
+                Migration {
+                    schema: "profile".into(),
+                    version: 2.into(),
+                    parents: Vec::new(),
+                    delete_policy: None,
+                    ops: vec![move_avatar("move_in")],
+                },
+                Migration {
+                    schema: "user".into(),
+                    version: 2.into(),
+                    parents: Vec::new(),
+                    delete_policy: None,
+                    ops: vec![move_avatar("move_out")],
+                },
+            ]
+        }
+
+        fn migrator() -> Migrator {
+            let mut migrator = Migrator::default();
+            migrator.add_migrations(version_one()).unwrap();
+            migrator.add_migrations(version_two()).unwrap();
+            migrator
+        }
+
+        #[test]
+        fn schemas_move_the_field() {
+            let migrator = migrator();
+
+            assert!(migrator.schema("user", 1).unwrap().contains_key("avatar"));
+            assert!(!migrator.schema("user", 2).unwrap().contains_key("avatar"));
+            assert!(
+                !migrator
+                    .schema("profile", 1)
+                    .unwrap()
+                    .contains_key("avatar")
+            );
+            assert!(
+                migrator
+                    .schema("profile", 2)
+                    .unwrap()
+                    .contains_key("avatar")
+            );
+        }
+
+        #[test]
+        fn links() {
+            let migrator = migrator();
+
+            let linked = |table, version| migrator.links(table, version).count();
+            assert_eq!(linked("user", 1), 1);
+            assert_eq!(linked("user", 2), 0);
+            assert_eq!(linked("profile", 1), 0);
+            assert_eq!(linked("profile", 2), 1);
+        }
+
+        #[test]
+        fn adding_a_set_again_is_fine() {
+            let mut migrator = migrator();
+
+            assert_eq!(migrator.add_migrations(version_two()), Ok(()));
+            assert_eq!(migrator.links.len(), 1);
+        }
+
+        #[test]
+        fn half_a_move_is_rejected() {
+            let mut migrator = Migrator::default();
+            migrator.add_migrations(version_one()).unwrap();
+
+            let user = version_two().pop().unwrap();
+
+            assert_eq!(
+                migrator.add_migration(user),
+                Err(Error::UnmatchedMove(
+                    "user".into(),
+                    2.into(),
+                    "avatar".into()
+                ))
+            );
+            assert_eq!(
+                migrator.schema("user", 2),
+                Err(Error::MissingStep("user".into(), 1.into(), 2.into()))
+            );
+        }
+
+        #[test]
+        fn moves_belong_to_their_tables() {
+            let mut migrator = Migrator::default();
+            migrator.add_migrations(version_one()).unwrap();
+
+            let mut migrations = version_two();
+            migrations[0].schema = "user".into();
+            migrations[1].schema = "profile".into();
+
+            assert_eq!(
+                migrator.add_migrations(migrations),
+                Err(Error::MisplacedMove(
+                    "user".into(),
+                    2.into(),
+                    "avatar".into()
+                ))
+            );
+        }
+
+        #[test]
+        fn moves_out_cant_merge() {
+            let mut migrator = Migrator::default();
+            migrator.add_migrations(version_one()).unwrap();
+            migrator
+                .add_migration(Migration {
+                    schema: "user".into(),
+                    version: 3.into(),
+                    parents: vec![1.into()],
+                    delete_policy: None,
+                    ops: Vec::new(),
+                })
+                .unwrap();
+
+            let mut migrations = version_two();
+            migrations[1].parents = vec![1.into(), 3.into()];
+
+            assert_eq!(
+                migrator.add_migrations(migrations),
+                Err(Error::MergedMove("user".into(), 2.into(), "avatar".into()))
+            );
+        }
+
+        #[test]
+        fn reference_has_to_hold_ids() {
+            let mut migrator = Migrator::default();
+            let mut migrations = version_one();
+            migrations[0].ops[0] = lens!({"add": {"name": "user_id", "type": "int", "default": 0}});
+            migrator.add_migrations(migrations).unwrap();
+
+            assert_eq!(
+                migrator.add_migrations(version_two()),
+                Err(Error::InvalidReference(
+                    "profile".into(),
+                    2.into(),
+                    "user_id".into()
+                ))
+            );
+            // Nothing from the failed set sticks around.
+            assert_eq!(
+                migrator.schema("user", 2),
+                Err(Error::MissingStep("user".into(), 1.into(), 2.into()))
+            );
+        }
+
+        #[test]
+        fn check_versions() {
+            let migrator = migrator();
+            let versions = |user: usize, profile: usize| {
+                BTreeMap::from([
+                    ("user".to_string(), user.into()),
+                    ("profile".to_string(), profile.into()),
+                ])
+            };
+
+            assert_eq!(migrator.check_versions(&versions(1, 1)), Ok(()));
+            assert_eq!(migrator.check_versions(&versions(2, 2)), Ok(()));
+            assert_eq!(
+                migrator.check_versions(&versions(2, 1)),
+                Err(Error::MismatchedVersions {
+                    name: "avatar".into(),
+                    from: "user.2".into(),
+                    to: "profile.1".into(),
+                })
+            );
+        }
+
+        #[test]
+        fn transform_linked() {
+            let migrator = migrator();
+            let link = migrator.links("profile", 2).next().unwrap();
+
+            // Written to a user before the move; read from a profile after.
+            let user = BTreeMap::from([
+                ("name".to_string(), Value::from("Ada")),
+                ("avatar".to_string(), Value::from("ada.png")),
+            ]);
+            assert_eq!(
+                migrator.transform_linked(link, "profile", 2, 1, user.clone()),
+                Ok(Some(Value::from("ada.png")))
+            );
+
+            // Written to a profile after the move; read from a user before.
+            let profile = BTreeMap::from([("avatar".to_string(), Value::from("ada.png"))]);
+            assert_eq!(
+                migrator.transform_linked(link, "user", 1, 2, profile.clone()),
+                Ok(Some(Value::from("ada.png")))
+            );
+
+            // Written on the same side as we're reading: nothing to take.
+            assert_eq!(
+                migrator.transform_linked(link, "profile", 2, 2, profile),
+                Ok(None)
+            );
+            assert_eq!(
+                migrator.transform_linked(link, "user", 2, 1, user),
+                Ok(None)
+            );
+        }
+    }
 }
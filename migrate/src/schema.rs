@@ -1,13 +1,37 @@
-use crate::{Type, Value};
+use crate::{Constraint, Type, Value, type_};
 use std::collections::BTreeMap;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Field {
     pub type_: Type,
     pub default: Value,
+    pub constraints: Vec<Constraint>,
 }
 
-#[derive(Debug, Default, PartialEq)]
+impl Field {
+    /// Check a value against the field's type and then its constraints.
+    /// Nulls in nullable fields skip the constraints.
+    pub fn validate(&self, value: &Value) -> Result<(), type_::Error> {
+        self.type_.validate(value)?;
+
+        if *value == Value::Null {
+            return Ok(());
+        }
+
+        self.constraints
+            .iter()
+            .try_for_each(|constraint| constraint.check(value))
+    }
+}
+
+/// A schema taken apart by `Schema::into_parts`.
+pub(crate) type Parts = (
+    BTreeMap<String, Type>,
+    BTreeMap<String, Value>,
+    BTreeMap<String, Vec<Constraint>>,
+);
+
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct Schema(BTreeMap<String, Field>);
 
 impl Schema {
@@ -26,6 +50,53 @@ impl Schema {
     pub fn get<S: AsRef<str>>(&self, name: S) -> Option<&Field> {
         self.0.get(name.as_ref())
     }
+
+    /// Split into field types, defaults, and constraints, so lenses can
+    /// work on each separately. Fields without constraints are left out of
+    /// the constraints map.
+    pub(crate) fn into_parts(self) -> Parts {
+        let mut types = BTreeMap::new();
+        let mut defaults = BTreeMap::new();
+        let mut constraints = BTreeMap::new();
+
+        for (name, field) in self.0 {
+            if !field.constraints.is_empty() {
+                constraints.insert(name.clone(), field.constraints);
+            }
+            defaults.insert(name.clone(), field.default);
+            types.insert(name, field.type_);
+        }
+
+        (types, defaults, constraints)
+    }
+
+    pub(crate) fn from_parts(
+        types: BTreeMap<String, Type>,
+        mut defaults: BTreeMap<String, Value>,
+        mut constraints: BTreeMap<String, Vec<Constraint>>,
+    ) -> Self {
+        Schema(
+            types
+                .into_iter()
+                .map(|(name, type_)| {
+                    let default = defaults.remove(&name).unwrap_or(Value::Null);
+                    let constraints = constraints.remove(&name).unwrap_or_default();
+                    (
+                        name,
+                        Field {
+                            type_,
+                            default,
+                            constraints,
+                        },
+                    )
+                })
+                .collect(),
+        )
+    }
+
+    pub fn iter(&self) -> std::collections::btree_map::Iter<'_, String, Field> {
+        self.0.iter()
+    }
 }
 
 impl IntoIterator for Schema {
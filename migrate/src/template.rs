@@ -0,0 +1,260 @@
+use std::fmt::Display;
+use std::str::FromStr;
+
+/// How a string is built from several parts, like `"{first} {last}"`.
+/// Placeholders name the parts, and everything else is literal text. Use
+/// `{{` and `}}` for literal braces.
+///
+/// To be able to take a string apart again, there has to be some literal
+/// text between every two placeholders. Parsing checks that, so any
+/// `Template` can go both ways.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    /// The text before, between, and after the placeholders. Always one
+    /// longer than `fields`.
+    literals: Vec<String>,
+    fields: Vec<String>,
+}
+
+impl Template {
+    /// Put parts separated by `separator`, like `"{first} {last}"`.
+    pub fn separated(fields: Vec<String>, separator: &str) -> Result<Self, Error> {
+        let mut literals = vec![String::new()];
+        literals.extend(fields.iter().skip(1).map(|_| separator.to_string()));
+        literals.push(String::new());
+
+        Self::new(literals, fields)
+    }
+
+    fn new(literals: Vec<String>, fields: Vec<String>) -> Result<Self, Error> {
+        if fields.is_empty() {
+            return Err(Error::NoFields);
+        }
+
+        for (index, field) in fields.iter().enumerate() {
+            if field.is_empty() || field.contains('.') {
+                return Err(Error::InvalidField(field.clone()));
+            }
+            if fields[..index].contains(field) {
+                return Err(Error::DuplicateField(field.clone()));
+            }
+        }
+
+        if let Some(index) = literals[1..fields.len()].iter().position(String::is_empty) {
+            return Err(Error::AdjacentFields(
+                fields[index].clone(),
+                fields[index + 1].clone(),
+            ));
+        }
+
+        Ok(Self { literals, fields })
+    }
+
+    /// The names of the parts, in order.
+    pub fn fields(&self) -> &[String] {
+        &self.fields
+    }
+
+    /// Fill in the template. `parts` line up with `fields()`.
+    pub fn render(&self, parts: &[&str]) -> String {
+        let mut out = self.literals[0].clone();
+
+        for (part, literal) in parts.iter().zip(&self.literals[1..]) {
+            out.push_str(part);
+            out.push_str(literal);
+        }
+
+        out
+    }
+
+    /// Take a string apart, lining up with `fields()`. Strings that don't
+    /// match the template still get split as well as possible: whatever we
+    /// can't place ends up in the last part we could find, and the rest are
+    /// empty.
+    pub fn split(&self, value: &str) -> Vec<String> {
+        let mut rest = value.strip_prefix(&self.literals[0]).unwrap_or(value);
+        let mut parts = Vec::with_capacity(self.fields.len());
+
+        for literal in &self.literals[1..self.fields.len()] {
+            match rest.split_once(literal.as_str()) {
+                Some((part, after)) => {
+                    parts.push(part.to_string());
+                    rest = after;
+                }
+                None => break,
+            }
+        }
+
+        let suffix = &self.literals[self.fields.len()];
+        parts.push(
+            rest.strip_suffix(suffix.as_str())
+                .unwrap_or(rest)
+                .to_string(),
+        );
+        parts.resize(self.fields.len(), String::new());
+
+        parts
+    }
+}
+
+impl FromStr for Template {
+    type Err = Error;
+
+    fn from_str(template: &str) -> Result<Self, Self::Err> {
+        let mut literals = vec![String::new()];
+        let mut fields = Vec::new();
+        let mut chars = template.chars();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.as_str().starts_with('{') => {
+                    chars.next();
+                    literals.last_mut().unwrap().push('{');
+                }
+                '}' if chars.as_str().starts_with('}') => {
+                    chars.next();
+                    literals.last_mut().unwrap().push('}');
+                }
+                '{' => {
+                    let (field, after) = chars
+                        .as_str()
+                        .split_once('}')
+                        .ok_or(Error::UnclosedPlaceholder)?;
+                    if field.contains('{') {
+                        return Err(Error::UnclosedPlaceholder);
+                    }
+
+                    fields.push(field.to_string());
+                    literals.push(String::new());
+                    chars = after.chars();
+                }
+                '}' => return Err(Error::UnopenedPlaceholder),
+                c => literals.last_mut().unwrap().push(c),
+            }
+        }
+
+        Self::new(literals, fields)
+    }
+}
+
+impl Display for Template {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let escape = |literal: &str| literal.replace('{', "{{").replace('}', "}}");
+
+        write!(f, "{}", escape(&self.literals[0]))?;
+        for (field, literal) in self.fields.iter().zip(&self.literals[1..]) {
+            write!(f, "{{{field}}}{}", escape(literal))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl serde::Serialize for Template {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Template {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Debug, thiserror::Error, PartialEq)]
+pub enum Error {
+    #[error("Templates need at least one `{{field}}` placeholder.")]
+    NoFields,
+    #[error("`{0}` is not a valid field name for a template.")]
+    InvalidField(String),
+    #[error("`{0}` appears more than once in the template.")]
+    DuplicateField(String),
+    #[error("`{0}` and `{1}` need some text between them to tell them apart.")]
+    AdjacentFields(String, String),
+    #[error("A `{{` in the template is never closed. Use `{{{{` for a literal brace.")]
+    UnclosedPlaceholder,
+    #[error("A `}}` in the template was never opened. Use `}}}}` for a literal brace.")]
+    UnopenedPlaceholder,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn name() -> Template {
+        "{first} {last}".parse().unwrap()
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(name().fields(), ["first", "last"]);
+        assert_eq!(
+            Template::separated(vec!["first".into(), "last".into()], " "),
+            Ok(name())
+        );
+    }
+
+    #[test]
+    fn parse_escapes() {
+        let template: Template = "{{{key}}}: {value}".parse().unwrap();
+
+        assert_eq!(template.fields(), ["key", "value"]);
+        assert_eq!(template.render(&["a", "b"]), "{a}: b");
+        assert_eq!(template.to_string(), "{{{key}}}: {value}");
+    }
+
+    #[test]
+    fn parse_errors() {
+        let parse = |template: &str| template.parse::<Template>().unwrap_err();
+
+        assert_eq!(parse("no fields"), Error::NoFields);
+        assert_eq!(
+            parse("{first}{last}"),
+            Error::AdjacentFields("first".into(), "last".into())
+        );
+        assert_eq!(parse("{a}-{a}"), Error::DuplicateField("a".into()));
+        assert_eq!(parse("{a.b}"), Error::InvalidField("a.b".into()));
+        assert_eq!(parse("{}"), Error::InvalidField("".into()));
+        assert_eq!(parse("{a"), Error::UnclosedPlaceholder);
+        assert_eq!(parse("{a {b}"), Error::UnclosedPlaceholder);
+        assert_eq!(parse("a}"), Error::UnopenedPlaceholder);
+    }
+
+    #[test]
+    fn render() {
+        assert_eq!(name().render(&["Ada", "Lovelace"]), "Ada Lovelace");
+    }
+
+    #[test]
+    fn split() {
+        assert_eq!(name().split("Ada Lovelace"), ["Ada", "Lovelace"]);
+        assert_eq!(name().split("Mary Ann Evans"), ["Mary", "Ann Evans"]);
+    }
+
+    #[test]
+    fn split_mismatch() {
+        assert_eq!(name().split("Cher"), ["Cher", ""]);
+
+        let point: Template = "({x}, {y})".parse().unwrap();
+        assert_eq!(point.split("(1, 2)"), ["1", "2"]);
+        assert_eq!(point.split("1, 2"), ["1", "2"]);
+        assert_eq!(point.split("nowhere"), ["nowhere", ""]);
+    }
+
+    #[test]
+    fn display_round_trips() {
+        for template in ["{first} {last}", "({x}, {y})", "{{{a}}}"] {
+            assert_eq!(template.parse::<Template>().unwrap().to_string(), template);
+        }
+    }
+}
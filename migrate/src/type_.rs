@@ -1,14 +1,33 @@
-use crate::Value;
+use crate::{Value, constraint::Constraint};
+use base64::{Engine, prelude::BASE64_STANDARD};
 use jtd::Schema;
 use std::{collections::BTreeMap, fmt::Display};
 
-#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize, PartialEq)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum SerdeType {
     String,
     Int,
     Float,
     Bool,
+    Timestamp,
+    Date,
+    Uuid,
+    Bytes,
+    Object(BTreeMap<String, SerdeSubField>),
+    Array(Box<SerdeSubField>),
+    Enum(Vec<String>),
+}
+
+/// A field inside an object type, or the items of an array. Neither carries
+/// its own default; that comes from the default for the object or array as a
+/// whole.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
+pub struct SerdeSubField {
+    #[serde(rename = "type")]
+    pub type_: SerdeType,
+    #[serde(default)]
+    pub nullable: bool,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -17,6 +36,18 @@ pub enum Type {
     Int,
     Float,
     Bool,
+    /// An RFC 3339 date and time, stored as a string.
+    Timestamp,
+    /// A `YYYY-MM-DD` calendar date, stored as a string.
+    Date,
+    /// A hyphenated UUID, stored as a string.
+    Uuid,
+    /// Binary data. JSON has no way to represent this, so it's written as
+    /// base64 there.
+    Bytes,
+    Object(BTreeMap<String, Type>),
+    Array(Box<Type>),
+    Enum(Vec<String>),
     Nullable(Box<Type>),
 }
 
@@ -35,6 +66,29 @@ impl Type {
             Type::Int => SerdeType::Int,
             Type::Float => SerdeType::Float,
             Type::Bool => SerdeType::Bool,
+            Type::Timestamp => SerdeType::Timestamp,
+            Type::Date => SerdeType::Date,
+            Type::Uuid => SerdeType::Uuid,
+            Type::Bytes => SerdeType::Bytes,
+            Type::Object(fields) => SerdeType::Object(
+                fields
+                    .iter()
+                    .map(|(name, type_)| {
+                        (
+                            name.clone(),
+                            SerdeSubField {
+                                type_: type_.to_serde(),
+                                nullable: type_.is_nullable(),
+                            },
+                        )
+                    })
+                    .collect(),
+            ),
+            Type::Array(items) => SerdeType::Array(Box::new(SerdeSubField {
+                type_: items.to_serde(),
+                nullable: items.is_nullable(),
+            })),
+            Type::Enum(variants) => SerdeType::Enum(variants.clone()),
             Type::Nullable(inner) => inner.to_serde(),
         }
     }
@@ -49,12 +103,50 @@ impl Type {
             (Type::Int, Value::Int(_)) => Ok(()),
             (Type::Float, Value::Float(_)) => Ok(()),
             (Type::Bool, Value::Bool(_)) => Ok(()),
+            (Type::Timestamp, Value::String(s))
+                if chrono::DateTime::parse_from_rfc3339(s).is_ok() =>
+            {
+                Ok(())
+            }
+            (Type::Date, Value::String(s))
+                if chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d").is_ok() =>
+            {
+                Ok(())
+            }
+            (Type::Uuid, Value::String(s)) if uuid::Uuid::try_parse(s).is_ok() => Ok(()),
+            (Type::Bytes, Value::Bytes(_)) => Ok(()),
+            // Nullable sub-fields may be left out entirely, but anything
+            // that isn't part of the type is an error.
+            (Type::Object(fields), Value::Object(values))
+                if values.keys().all(|name| fields.contains_key(name)) =>
+            {
+                for (name, type_) in fields {
+                    type_
+                        .validate(values.get(name).unwrap_or(&Value::Null))
+                        .map_err(|_| Error::InvalidValue {
+                            expected: self.clone(),
+                            got: value.clone(),
+                        })?;
+                }
+                Ok(())
+            }
+            (Type::Array(items), Value::Array(values)) => {
+                for item in values {
+                    items.validate(item).map_err(|_| Error::InvalidValue {
+                        expected: self.clone(),
+                        got: value.clone(),
+                    })?;
+                }
+                Ok(())
+            }
+            (Type::Enum(variants), Value::String(variant)) if variants.contains(variant) => Ok(()),
             (Type::Nullable(_), Value::Null) => Ok(()),
             (Type::Nullable(inner), _) => inner.validate(value).map_err(|err| match err {
                 Error::InvalidValue { got, .. } => Error::InvalidValue {
                     expected: self.clone(),
                     got,
                 },
+                err => err,
             }),
             _ => Err(Error::InvalidValue {
                 expected: self.clone(),
@@ -62,6 +154,85 @@ impl Type {
             }),
         }
     }
+
+    /// Turn base64 strings into bytes wherever this type expects bytes. For
+    /// values that came from JSON, where there's no other way to write them.
+    pub(crate) fn bytes_from_json(&self, value: Value) -> Result<Value, Error> {
+        match (self, value) {
+            (Type::Bytes, Value::String(encoded)) => match BASE64_STANDARD.decode(&encoded) {
+                Ok(bytes) => Ok(Value::Bytes(bytes)),
+                Err(_) => Err(Error::InvalidValue {
+                    expected: self.clone(),
+                    got: Value::String(encoded),
+                }),
+            },
+            (Type::Nullable(inner), value) => inner.bytes_from_json(value),
+            (Type::Array(items), Value::Array(values)) => values
+                .into_iter()
+                .map(|value| items.bytes_from_json(value))
+                .collect::<Result<_, _>>()
+                .map(Value::Array),
+            (Type::Object(fields), Value::Object(values)) => values
+                .into_iter()
+                .map(|(name, value)| match fields.get(&name) {
+                    Some(type_) => Ok((name, type_.bytes_from_json(value)?)),
+                    None => Ok((name, value)),
+                })
+                .collect::<Result<_, _>>()
+                .map(Value::Object),
+            (_, value) => Ok(value),
+        }
+    }
+
+    /// The reverse of [`Type::bytes_from_json`].
+    pub(crate) fn bytes_to_json(&self, value: Value) -> Value {
+        match (self, value) {
+            (Type::Bytes, Value::Bytes(bytes)) => Value::String(BASE64_STANDARD.encode(bytes)),
+            (Type::Nullable(inner), value) => inner.bytes_to_json(value),
+            (Type::Array(items), Value::Array(values)) => Value::Array(
+                values
+                    .into_iter()
+                    .map(|value| items.bytes_to_json(value))
+                    .collect(),
+            ),
+            (Type::Object(fields), Value::Object(values)) => Value::Object(
+                values
+                    .into_iter()
+                    .map(|(name, value)| match fields.get(&name) {
+                        Some(type_) => (name, type_.bytes_to_json(value)),
+                        None => (name, value),
+                    })
+                    .collect(),
+            ),
+            (_, value) => value,
+        }
+    }
+
+    /// Turn ints back into floats wherever this type expects floats.
+    /// JavaScript has one kind of number, so whole-number floats that go
+    /// through it (or through IndexedDB) come back looking like ints.
+    pub fn coerce(&self, value: Value) -> Value {
+        match (self, value) {
+            (Type::Float, Value::Int(int)) => Value::Float(int as f64),
+            (Type::Nullable(inner), value) => inner.coerce(value),
+            (Type::Array(items), Value::Array(values)) => Value::Array(
+                values
+                    .into_iter()
+                    .map(|value| items.coerce(value))
+                    .collect(),
+            ),
+            (Type::Object(fields), Value::Object(values)) => Value::Object(
+                values
+                    .into_iter()
+                    .map(|(name, value)| match fields.get(&name) {
+                        Some(type_) => (name, type_.coerce(value)),
+                        None => (name, value),
+                    })
+                    .collect(),
+            ),
+            (_, value) => value,
+        }
+    }
 }
 
 impl From<SerdeType> for Type {
@@ -71,37 +242,86 @@ impl From<SerdeType> for Type {
             SerdeType::Int => Self::Int,
             SerdeType::Float => Self::Float,
             SerdeType::Bool => Self::Bool,
-        }
-    }
-}
-
-impl From<&Type> for jtd::Type {
-    fn from(type_: &Type) -> Self {
-        match type_ {
-            Type::String => jtd::Type::String,
-            Type::Int => jtd::Type::Int32,
-            Type::Float => jtd::Type::Float64,
-            Type::Bool => jtd::Type::Boolean,
-            Type::Nullable(inner) => inner.as_ref().into(),
+            SerdeType::Timestamp => Self::Timestamp,
+            SerdeType::Date => Self::Date,
+            SerdeType::Uuid => Self::Uuid,
+            SerdeType::Bytes => Self::Bytes,
+            SerdeType::Object(fields) => Self::Object(
+                fields
+                    .into_iter()
+                    .map(|(name, field)| (name, Type::from_serde(field.type_, field.nullable)))
+                    .collect(),
+            ),
+            SerdeType::Array(items) => {
+                Self::Array(Box::new(Type::from_serde(items.type_, items.nullable)))
+            }
+            SerdeType::Enum(variants) => Self::Enum(variants),
         }
     }
 }
 
 impl From<&Type> for Schema {
     fn from(type_: &Type) -> Self {
+        let scalar = |type_| Schema::Type {
+            definitions: BTreeMap::new(),
+            metadata: BTreeMap::new(),
+            nullable: false,
+            type_,
+        };
+
         match type_ {
-            Type::String | Type::Int | Type::Float | Type::Bool => Schema::Type {
+            Type::String => scalar(jtd::Type::String),
+            Type::Int => scalar(jtd::Type::Int32),
+            Type::Float => scalar(jtd::Type::Float64),
+            Type::Bool => scalar(jtd::Type::Boolean),
+            Type::Timestamp => scalar(jtd::Type::Timestamp),
+            // JTD has no types of its own for these. Bytes are base64.
+            Type::Date | Type::Uuid | Type::Bytes => scalar(jtd::Type::String),
+            // Nullable sub-fields may be left out, so they're optional.
+            Type::Object(fields) => {
+                let (optional, required): (Vec<_>, Vec<_>) = fields
+                    .iter()
+                    .partition(|(_, type_)| matches!(type_, Type::Nullable(_)));
+
+                Schema::Properties {
+                    definitions: BTreeMap::new(),
+                    metadata: BTreeMap::new(),
+                    nullable: false,
+                    properties: required
+                        .into_iter()
+                        .map(|(name, type_)| (name.clone(), type_.into()))
+                        .collect(),
+                    optional_properties: optional
+                        .into_iter()
+                        .map(|(name, type_)| (name.clone(), type_.into()))
+                        .collect(),
+                    properties_is_present: true,
+                    additional_properties: false,
+                }
+            }
+            Type::Array(items) => Schema::Elements {
                 definitions: BTreeMap::new(),
                 metadata: BTreeMap::new(),
                 nullable: false,
-                type_: type_.into(),
+                elements: Box::new(items.as_ref().into()),
             },
-            Type::Nullable(inner) => Schema::Type {
+            Type::Enum(variants) => Schema::Enum {
                 definitions: BTreeMap::new(),
                 metadata: BTreeMap::new(),
-                nullable: true,
-                type_: inner.as_ref().into(),
+                nullable: false,
+                enum_: variants.iter().cloned().collect(),
             },
+            Type::Nullable(inner) => {
+                let mut schema = Schema::from(inner.as_ref());
+                if let Schema::Type { nullable, .. }
+                | Schema::Properties { nullable, .. }
+                | Schema::Elements { nullable, .. }
+                | Schema::Enum { nullable, .. } = &mut schema
+                {
+                    *nullable = true;
+                }
+                schema
+            }
         }
     }
 }
@@ -113,6 +333,20 @@ impl Display for Type {
             Type::Int => write!(f, "int"),
             Type::Float => write!(f, "float"),
             Type::Bool => write!(f, "bool"),
+            Type::Timestamp => write!(f, "timestamp"),
+            Type::Date => write!(f, "date"),
+            Type::Uuid => write!(f, "uuid"),
+            Type::Bytes => write!(f, "bytes"),
+            Type::Object(fields) => {
+                write!(f, "object {{")?;
+                for (i, (name, type_)) in fields.iter().enumerate() {
+                    let sep = if i == 0 { " " } else { ", " };
+                    write!(f, "{sep}{name}: {type_}")?;
+                }
+                write!(f, " }}")
+            }
+            Type::Array(items) => write!(f, "array of {items}"),
+            Type::Enum(variants) => write!(f, "one of {}", variants.join(" | ")),
             Type::Nullable(inner) => write!(f, "nullable {inner}"),
         }
     }
@@ -122,6 +356,8 @@ impl Display for Type {
 pub enum Error {
     #[error("Invalid value for type {expected}: {got}")]
     InvalidValue { expected: Type, got: Value },
+    #[error("Value does not meet constraint {constraint}: {got}")]
+    Constraint { constraint: Constraint, got: Value },
 }
 
 #[cfg(test)]
@@ -215,4 +451,269 @@ mod tests {
         assert!(nullable_bool.validate(&Value::Bool(true)).is_ok());
         assert!(nullable_bool.validate(&Value::Float(1.23)).is_err());
     }
+
+    fn address() -> Type {
+        Type::Object(BTreeMap::from([
+            ("street".to_string(), Type::String),
+            ("zip".to_string(), Type::Nullable(Box::new(Type::String))),
+        ]))
+    }
+
+    #[test]
+    fn validate_object() {
+        let address = address();
+
+        assert!(
+            address
+                .validate(&Value::Object(BTreeMap::from([
+                    ("street".to_string(), "Main St".into()),
+                    ("zip".to_string(), "12345".into()),
+                ])))
+                .is_ok()
+        );
+        // Nullable sub-fields can be left out...
+        assert!(
+            address
+                .validate(&Value::Object(BTreeMap::from([(
+                    "street".to_string(),
+                    "Main St".into()
+                )])))
+                .is_ok()
+        );
+        // ...but required ones can't.
+        assert!(address.validate(&Value::Object(BTreeMap::new())).is_err());
+        assert!(
+            address
+                .validate(&Value::Object(BTreeMap::from([
+                    ("street".to_string(), "Main St".into()),
+                    ("extra".to_string(), 1.into()),
+                ])))
+                .is_err()
+        );
+        assert!(
+            address
+                .validate(&Value::Object(BTreeMap::from([(
+                    "street".to_string(),
+                    1.into()
+                )])))
+                .is_err()
+        );
+        assert!(address.validate(&Value::String("hello".into())).is_err());
+        assert!(address.validate(&Value::Null).is_err());
+    }
+
+    #[test]
+    fn object_to_jtd() {
+        let schema: Schema = (&Type::Nullable(Box::new(address()))).into();
+
+        assert_eq!(
+            serde_json::to_value(schema.into_serde_schema()).unwrap(),
+            serde_json::json!({
+                "nullable": true,
+                "properties": {
+                    "street": {"type": "string"},
+                },
+                "optionalProperties": {
+                    "zip": {"type": "string", "nullable": true},
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn object_serde_round_trip() {
+        let serde: SerdeType = serde_json::from_value(serde_json::json!({
+            "object": {
+                "street": {"type": "string"},
+                "zip": {"type": "string", "nullable": true},
+            }
+        }))
+        .unwrap();
+
+        let type_ = Type::from_serde(serde.clone(), false);
+        assert_eq!(type_, address());
+        assert_eq!(type_.to_serde(), serde);
+    }
+
+    #[test]
+    fn validate_array() {
+        let tags = Type::Array(Box::new(Type::String));
+
+        assert!(tags.validate(&Value::Array(Vec::new())).is_ok());
+        assert!(
+            tags.validate(&Value::Array(vec!["a".into(), "b".into()]))
+                .is_ok()
+        );
+        assert!(
+            tags.validate(&Value::Array(vec!["a".into(), 1.into()]))
+                .is_err()
+        );
+        assert!(tags.validate(&Value::Array(vec![Value::Null])).is_err());
+        assert!(tags.validate(&Value::String("a".into())).is_err());
+        assert!(tags.validate(&Value::Null).is_err());
+    }
+
+    #[test]
+    fn array_to_jtd() {
+        let schema: Schema = (&Type::Array(Box::new(Type::Nullable(Box::new(Type::Int))))).into();
+
+        assert_eq!(
+            serde_json::to_value(schema.into_serde_schema()).unwrap(),
+            serde_json::json!({
+                "elements": {"type": "int32", "nullable": true},
+            })
+        );
+    }
+
+    #[test]
+    fn array_serde_round_trip() {
+        let serde: SerdeType = serde_json::from_value(serde_json::json!({
+            "array": {"type": "string"}
+        }))
+        .unwrap();
+
+        let type_ = Type::from_serde(serde.clone(), false);
+        assert_eq!(type_, Type::Array(Box::new(Type::String)));
+        assert_eq!(type_.to_serde(), serde);
+    }
+
+    #[test]
+    fn validate_enum() {
+        let status = Type::Enum(vec!["draft".into(), "published".into()]);
+
+        assert!(status.validate(&Value::from("draft")).is_ok());
+        assert!(status.validate(&Value::from("published")).is_ok());
+        assert!(status.validate(&Value::from("deleted")).is_err());
+        assert!(status.validate(&Value::from(1)).is_err());
+        assert!(status.validate(&Value::Null).is_err());
+    }
+
+    #[test]
+    fn enum_to_jtd() {
+        let schema: Schema = (&Type::Enum(vec!["draft".into(), "published".into()])).into();
+
+        assert_eq!(
+            serde_json::to_value(schema.into_serde_schema()).unwrap(),
+            serde_json::json!({"enum": ["draft", "published"]})
+        );
+    }
+
+    #[test]
+    fn validate_timestamp() {
+        let timestamp = Type::Timestamp;
+        assert!(timestamp.validate(&"2025-07-01T12:30:00Z".into()).is_ok());
+        assert!(
+            timestamp
+                .validate(&"2025-07-01T12:30:00.123+02:00".into())
+                .is_ok()
+        );
+        assert!(timestamp.validate(&"2025-07-01".into()).is_err());
+        assert!(timestamp.validate(&"yesterday".into()).is_err());
+        assert!(timestamp.validate(&Value::Int(1751373000)).is_err());
+        assert!(timestamp.validate(&Value::Null).is_err());
+    }
+
+    #[test]
+    fn validate_date() {
+        let date = Type::Date;
+        assert!(date.validate(&"2025-07-01".into()).is_ok());
+        assert!(date.validate(&"2025-02-30".into()).is_err());
+        assert!(date.validate(&"2025-07-01T12:30:00Z".into()).is_err());
+        assert!(date.validate(&Value::Null).is_err());
+    }
+
+    #[test]
+    fn validate_uuid() {
+        let uuid = Type::Uuid;
+        assert!(
+            uuid.validate(&"67e55044-10b1-426f-9247-bb680e5fe0c8".into())
+                .is_ok()
+        );
+        assert!(uuid.validate(&"67e55044".into()).is_err());
+        assert!(uuid.validate(&Value::Int(1)).is_err());
+        assert!(uuid.validate(&Value::Null).is_err());
+    }
+
+    #[test]
+    fn timestamp_to_jtd() {
+        let schema: Schema = (&Type::Nullable(Box::new(Type::Timestamp))).into();
+
+        assert_eq!(
+            serde_json::to_value(schema.into_serde_schema()).unwrap(),
+            serde_json::json!({"type": "timestamp", "nullable": true})
+        );
+    }
+
+    #[test]
+    fn validate_bytes() {
+        let bytes = Type::Bytes;
+        assert!(bytes.validate(&Value::Bytes(vec![1, 2, 3])).is_ok());
+        assert!(bytes.validate(&Value::Bytes(Vec::new())).is_ok());
+        assert!(bytes.validate(&"AQID".into()).is_err());
+        assert!(bytes.validate(&Value::Null).is_err());
+    }
+
+    #[test]
+    fn bytes_through_json() {
+        let type_ = Type::Object(BTreeMap::from([
+            (
+                "thumbnail".to_string(),
+                Type::Nullable(Box::new(Type::Bytes)),
+            ),
+            ("name".to_string(), Type::String),
+        ]));
+        let value = Value::Object(BTreeMap::from([
+            ("thumbnail".to_string(), Value::Bytes(vec![1, 2, 3])),
+            ("name".to_string(), "AQID".into()),
+        ]));
+
+        let json = type_.bytes_to_json(value.clone());
+        assert_eq!(
+            json,
+            Value::Object(BTreeMap::from([
+                ("thumbnail".to_string(), "AQID".into()),
+                ("name".to_string(), "AQID".into()),
+            ]))
+        );
+        assert_eq!(type_.bytes_from_json(json), Ok(value));
+
+        assert!(Type::Bytes.bytes_from_json("not base64!".into()).is_err());
+    }
+
+    #[test]
+    fn coerce_whole_floats() {
+        let type_ = Type::Object(BTreeMap::from([
+            ("score".to_string(), Type::Nullable(Box::new(Type::Float))),
+            ("count".to_string(), Type::Int),
+            ("readings".to_string(), Type::Array(Box::new(Type::Float))),
+        ]));
+        let value = Value::Object(BTreeMap::from([
+            ("score".to_string(), Value::Int(0)),
+            ("count".to_string(), Value::Int(3)),
+            (
+                "readings".to_string(),
+                Value::Array(vec![Value::Int(1), Value::Float(1.5)]),
+            ),
+        ]));
+
+        let coerced = type_.coerce(value);
+        assert_eq!(
+            coerced,
+            Value::Object(BTreeMap::from([
+                ("score".to_string(), Value::Float(0.0)),
+                ("count".to_string(), Value::Int(3)),
+                (
+                    "readings".to_string(),
+                    Value::Array(vec![Value::Float(1.0), Value::Float(1.5)]),
+                ),
+            ]))
+        );
+        assert!(type_.validate(&coerced).is_ok());
+
+        assert_eq!(Type::String.coerce(Value::Int(1)), Value::Int(1));
+        assert_eq!(
+            Type::Nullable(Box::new(Type::Float)).coerce(Value::Null),
+            Value::Null
+        );
+    }
 }
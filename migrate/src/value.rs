@@ -1,14 +1,31 @@
+use base64::{Engine, prelude::BASE64_STANDARD};
+use std::collections::BTreeMap;
 use std::fmt::Display;
 
 #[derive(Debug, Clone, serde::Serialize, PartialEq)]
+#[serde(untagged)]
 pub enum Value {
     String(String),
     Int(i64),
     Float(f64),
     Bool(bool),
+    Object(BTreeMap<String, Value>),
+    Array(Vec<Value>),
+    #[serde(serialize_with = "serialize_bytes")]
+    Bytes(Vec<u8>),
     Null,
 }
 
+/// Serde's default for `Vec<u8>` is a sequence of numbers, but we want
+/// formats that know about binary data (like `serde_wasm_bindgen`, which
+/// makes a `Uint8Array`) to use it.
+fn serialize_bytes<S>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    serializer.serialize_bytes(bytes)
+}
+
 impl Display for Value {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
@@ -16,6 +33,23 @@ impl Display for Value {
             Value::Int(i) => write!(f, "{i}"),
             Value::Float(n) => write!(f, "{n}"),
             Value::Bool(b) => write!(f, "{b}"),
+            Value::Object(fields) => {
+                write!(f, "{{")?;
+                for (i, (name, value)) in fields.iter().enumerate() {
+                    let sep = if i == 0 { " " } else { ", " };
+                    write!(f, "{sep}{name}: {value}")?;
+                }
+                write!(f, " }}")
+            }
+            Value::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    let sep = if i == 0 { "" } else { ", " };
+                    write!(f, "{sep}{item}")?;
+                }
+                write!(f, "]")
+            }
+            Value::Bytes(bytes) => write!(f, "{}", BASE64_STANDARD.encode(bytes)),
             Value::Null => write!(f, "null"),
         }
     }
@@ -25,6 +59,25 @@ impl Value {
     pub fn null() -> Value {
         Value::Null
     }
+
+    /// Lay `other` over this value. A partial record may only have some of
+    /// an object's sub-fields, so objects are merged one sub-field at a
+    /// time; anything else is replaced.
+    pub fn overlay(&mut self, other: Value) {
+        match (self, other) {
+            (Value::Object(fields), Value::Object(others)) => {
+                for (name, other) in others {
+                    match fields.get_mut(&name) {
+                        Some(field) => field.overlay(other),
+                        None => {
+                            fields.insert(name, other);
+                        }
+                    }
+                }
+            }
+            (this, other) => *this = other,
+        }
+    }
 }
 
 impl<'de> serde::Deserialize<'de> for Value {
@@ -42,7 +95,7 @@ impl<'de> serde::de::Visitor<'de> for ValueVisitor {
     type Value = Value;
 
     fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
-        formatter.write_str("a string, integer, float, boolean, or null")
+        formatter.write_str("a string, integer, float, boolean, object, array, bytes, or null")
     }
 
     fn visit_bool<E>(self, v: bool) -> Result<Self::Value, E>
@@ -91,6 +144,20 @@ impl<'de> serde::de::Visitor<'de> for ValueVisitor {
         Ok(Value::String(v))
     }
 
+    fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        Ok(Value::Bytes(v.to_vec()))
+    }
+
+    fn visit_byte_buf<E>(self, v: Vec<u8>) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        Ok(Value::Bytes(v))
+    }
+
     fn visit_unit<E>(self) -> Result<Self::Value, E>
     where
         E: serde::de::Error,
@@ -105,6 +172,30 @@ impl<'de> serde::de::Visitor<'de> for ValueVisitor {
         Ok(Value::Null)
     }
 
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: serde::de::MapAccess<'de>,
+    {
+        let mut fields = BTreeMap::new();
+        while let Some((name, value)) = map.next_entry()? {
+            fields.insert(name, value);
+        }
+        Ok(Value::Object(fields))
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: serde::de::SeqAccess<'de>,
+    {
+        // The hint comes from the input, so don't let it decide how much we
+        // allocate up front.
+        let mut items = Vec::with_capacity(seq.size_hint().unwrap_or_default().min(4096));
+        while let Some(item) = seq.next_element()? {
+            items.push(item);
+        }
+        Ok(Value::Array(items))
+    }
+
     fn visit_some<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
     where
         D: serde::Deserializer<'de>,
@@ -131,6 +222,12 @@ impl From<i64> for Value {
     }
 }
 
+impl From<Vec<u8>> for Value {
+    fn from(v: Vec<u8>) -> Self {
+        Self::Bytes(v)
+    }
+}
+
 impl From<String> for Value {
     fn from(v: String) -> Self {
         Self::String(v)
@@ -142,3 +239,93 @@ impl From<&str> for Value {
         Self::String(v.to_string())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    #[test]
+    fn round_trips_through_json() {
+        for (value, expected) in [
+            (Value::from("hello"), json!("hello")),
+            (Value::from(42), json!(42)),
+            (Value::from(1.5), json!(1.5)),
+            (Value::from(true), json!(true)),
+            (Value::Null, json!(null)),
+            (
+                Value::Object(BTreeMap::from([
+                    ("street".to_string(), Value::from("Main St")),
+                    ("zip".to_string(), Value::Null),
+                ])),
+                json!({"street": "Main St", "zip": null}),
+            ),
+            (
+                Value::Array(vec![Value::from(1), Value::from("two")]),
+                json!([1, "two"]),
+            ),
+        ] {
+            let serialized = serde_json::to_value(&value).unwrap();
+            assert_eq!(serialized, expected);
+
+            let deserialized: Value = serde_json::from_value(serialized).unwrap();
+            assert_eq!(deserialized, value);
+        }
+    }
+
+    #[test]
+    fn overlay_merges_objects() {
+        let mut value = Value::Object(BTreeMap::from([
+            ("avatar".to_string(), Value::from("me.png")),
+            (
+                "bio".to_string(),
+                Value::Object(BTreeMap::from([
+                    ("text".to_string(), Value::from("Hi")),
+                    ("lang".to_string(), Value::from("en")),
+                ])),
+            ),
+        ]));
+
+        value.overlay(Value::Object(BTreeMap::from([(
+            "bio".to_string(),
+            Value::Object(BTreeMap::from([(
+                "text".to_string(),
+                Value::from("Hello!"),
+            )])),
+        )])));
+
+        assert_eq!(
+            value,
+            Value::Object(BTreeMap::from([
+                ("avatar".to_string(), Value::from("me.png")),
+                (
+                    "bio".to_string(),
+                    Value::Object(BTreeMap::from([
+                        ("text".to_string(), Value::from("Hello!")),
+                        ("lang".to_string(), Value::from("en")),
+                    ])),
+                ),
+            ]))
+        );
+
+        value.overlay(Value::Null);
+        assert_eq!(value, Value::Null);
+    }
+
+    #[test]
+    fn bytes_use_native_binary() {
+        use serde::Deserialize;
+        use serde::de::value::{BytesDeserializer, Error};
+
+        let deserialized = Value::deserialize(BytesDeserializer::<Error>::new(&[1, 2, 3])).unwrap();
+        assert_eq!(deserialized, Value::Bytes(vec![1, 2, 3]));
+
+        // JSON has no binary type, so serde_json writes the bytes out one by
+        // one. Base64 conversion for JSON happens where we know the type.
+        assert_eq!(
+            serde_json::to_value(Value::Bytes(vec![1, 2, 3])).unwrap(),
+            json!([1, 2, 3])
+        );
+    }
+}
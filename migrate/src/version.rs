@@ -0,0 +1,176 @@
+use std::convert::Infallible;
+use std::fmt::Display;
+use std::str::FromStr;
+
+/// Names a migration, and the schema you get once it's applied. Names are
+/// unique within a table, but that's all: two branches that both build on
+/// `3` can call themselves `4-tags` and `4-priority`, and a later migration
+/// can merge them by naming both as parents.
+///
+/// Plain numbers are still the usual thing for a straight line of
+/// migrations, so they read and write as JSON numbers. `0` is the empty
+/// schema every table starts from.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Version(String);
+
+impl Version {
+    pub fn root() -> Self {
+        Self::from(0)
+    }
+
+    pub fn is_root(&self) -> bool {
+        *self == Self::root()
+    }
+
+    /// The number this version is named with, if it's just a number.
+    pub fn number(&self) -> Option<usize> {
+        self.0
+            .parse::<usize>()
+            .ok()
+            .filter(|number| number.to_string() == self.0)
+    }
+}
+
+impl From<usize> for Version {
+    fn from(number: usize) -> Self {
+        Self(number.to_string())
+    }
+}
+
+impl From<&Version> for Version {
+    fn from(version: &Version) -> Self {
+        version.clone()
+    }
+}
+
+impl From<&str> for Version {
+    fn from(name: &str) -> Self {
+        Self(name.to_string())
+    }
+}
+
+impl From<String> for Version {
+    fn from(name: String) -> Self {
+        Self(name)
+    }
+}
+
+impl FromStr for Version {
+    type Err = Infallible;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Ok(Self::from(name))
+    }
+}
+
+impl Display for Version {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl serde::Serialize for Version {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match self.number() {
+            Some(number) => serializer.serialize_u64(number as u64),
+            None => serializer.serialize_str(&self.0),
+        }
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Version {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_any(VersionVisitor)
+    }
+}
+
+struct VersionVisitor;
+
+impl serde::de::Visitor<'_> for VersionVisitor {
+    type Value = Version;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("a version number or name")
+    }
+
+    fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        Ok(Version(v.to_string()))
+    }
+
+    fn visit_i64<E>(self, v: i64) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        u64::try_from(v)
+            .map_err(|_| E::invalid_value(serde::de::Unexpected::Signed(v), &self))
+            .and_then(|v| self.visit_u64(v))
+    }
+
+    // JavaScript numbers come through as floats.
+    fn visit_f64<E>(self, v: f64) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        if v >= 0.0 && v.fract() == 0.0 && v <= u64::MAX as f64 {
+            self.visit_u64(v as u64)
+        } else {
+            Err(E::invalid_value(serde::de::Unexpected::Float(v), &self))
+        }
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        if v.is_empty() {
+            Err(E::invalid_value(serde::de::Unexpected::Str(v), &self))
+        } else {
+            Ok(Version::from(v))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    #[test]
+    fn numbers() {
+        assert_eq!(Version::from(4).number(), Some(4));
+        assert_eq!(Version::from("4-tags").number(), None);
+        assert_eq!(Version::from("04").number(), None);
+        assert!(Version::from("0").is_root());
+    }
+
+    #[test]
+    fn serde_round_trips() {
+        for (version, serialized) in [
+            (Version::from(4), json!(4)),
+            (Version::from("4-tags"), json!("4-tags")),
+        ] {
+            assert_eq!(serde_json::to_value(&version).unwrap(), serialized);
+            assert_eq!(
+                serde_json::from_value::<Version>(serialized).unwrap(),
+                version
+            );
+        }
+    }
+
+    #[test]
+    fn deserialize_rejects_nonsense() {
+        assert!(serde_json::from_value::<Version>(json!(-1)).is_err());
+        assert!(serde_json::from_value::<Version>(json!(1.5)).is_err());
+        assert!(serde_json::from_value::<Version>(json!("")).is_err());
+    }
+}
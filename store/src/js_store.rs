@@ -1,23 +1,39 @@
 use crate::storage::idb::{self, IDBStorage};
 use crate::store::{self, Store as GenericStore};
-use migrate::{Migration, Migrator};
+use migrate::{Migration, Migrator, Version};
+use serde::Serialize;
+use serde_wasm_bindgen::Serializer;
 use std::collections::BTreeMap;
 use tokio::sync::RwLock;
+use uuid::Uuid;
 use wasm_bindgen::prelude::*;
-use wasm_bindgen_futures::js_sys::JsString;
+use wasm_bindgen_futures::js_sys::{Function, JsString};
 
 #[wasm_bindgen(typescript_custom_section)]
 const TS_APPEND_CONTENT: &'static str = r#"
 
 export type TypeMap = Record<string, any>;
 
-export function store<T extends TypeMap>(schemas: Record<keyof T, number>, migrations: any[]): Promise<Store<T>>;
+/** What a replica has seen: the highest counter for each node ID. */
+export type VersionVector = Record<string, number>;
+
+/** Ops to send to another replica. Treat this as opaque. */
+export type Changes = { rows: unknown[]; fields: unknown[] };
+
+export function store<T extends TypeMap>(schemas: Record<keyof T, number | string>, migrations: any[]): Promise<Store<T>>;
 
 export class Store<T extends TypeMap> {
   insert<K extends keyof T>(table: K, data: T[K]): Promise<void>;
-  list<K extends keyof T>(table: K): Promise<T[K][]>;
-  // get<K extends keyof T>(table: K, id: string): Promise<T[K]>;
-  // update<K extends keyof T>(table: K, id: String, updater: (current: T[K]) => void): void;
+  list<K extends keyof T>(table: K): Promise<{ id: string; data: T[K] }[]>;
+  summary(): Promise<VersionVector>;
+  changes(since: VersionVector): Promise<Changes>;
+  apply(changes: Changes): Promise<void>;
+  nodeId(): Promise<string>;
+  rotateNodeId(): Promise<string>;
+  get<K extends keyof T>(table: K, id: string): Promise<T[K]>;
+  update<K extends keyof T>(table: K, id: string, updater: (current: T[K]) => void): Promise<void>;
+  delete<K extends keyof T>(table: K, id: string): Promise<void>;
+  undelete<K extends keyof T>(table: K, id: string): Promise<void>;
 }
 "#;
 
@@ -37,25 +53,36 @@ pub async fn store(schemas: JsValue, migrations_raw: JsValue) -> Result<Store, E
     let mut migrator = Migrator::default();
     let migrations: Vec<Migration> =
         serde_wasm_bindgen::from_value(migrations_raw).map_err(Error::Migration)?;
-    for migration in migrations {
-        migrator.add_migration(migration);
-    }
+    migrator
+        .add_migrations(migrations)
+        .map_err(Error::InvalidMigration)?;
 
-    Ok(Store::new(
+    Store::new(
         migrator,
         serde_wasm_bindgen::from_value(schemas).map_err(Error::SchemaMapping)?,
         IDBStorage::init().await?,
-    ))
+    )
+    .await
 }
 
 impl Store {
-    pub fn new(migrator: Migrator, schemas: BTreeMap<String, usize>, storage: IDBStorage) -> Self {
-        Store {
-            store: RwLock::new(GenericStore::new(migrator, schemas, storage)),
-        }
+    pub async fn new(
+        migrator: Migrator,
+        schemas: BTreeMap<String, Version>,
+        storage: IDBStorage,
+    ) -> Result<Self, Error> {
+        Ok(Store {
+            store: RwLock::new(GenericStore::new(migrator, schemas, storage).await?),
+        })
     }
 }
 
+/// How we hand values back to JavaScript: plain objects rather than `Map`s,
+/// but binary data as a `Uint8Array` rather than an array of numbers.
+fn serializer() -> Serializer {
+    Serializer::json_compatible().serialize_bytes_as_arrays(false)
+}
+
 #[wasm_bindgen]
 impl Store {
     // Implementation note: all the fields here should borrow self immutably and
@@ -79,13 +106,124 @@ impl Store {
     }
 
     #[wasm_bindgen]
-    pub async fn list(&self, _table_js: JsString) -> Result<JsValue, Error> {
-        // let table: String = table_js.into();
-        // let rows = self.storage.get_rows(&table).await?;
+    pub async fn summary(&self) -> Result<JsValue, Error> {
+        self.store
+            .read()
+            .await
+            .summary()
+            .await?
+            .serialize(&serializer())
+            .map_err(Error::Value)
+    }
+
+    #[wasm_bindgen]
+    pub async fn changes(&self, since_js: JsValue) -> Result<JsValue, Error> {
+        let since = serde_wasm_bindgen::from_value(since_js).map_err(Error::Sync)?;
+
+        self.store
+            .read()
+            .await
+            .changes_since(&since)
+            .await?
+            .serialize(&serializer())
+            .map_err(Error::Value)
+    }
+
+    #[wasm_bindgen]
+    pub async fn apply(&self, changes_js: JsValue) -> Result<(), Error> {
+        let changes = serde_wasm_bindgen::from_value(changes_js).map_err(Error::Sync)?;
+
+        Ok(self.store.write().await.apply(changes).await?)
+    }
 
-        // Ok(serde_wasm_bindgen::to_value(&rows).unwrap())
-        let empty: Vec<()> = Vec::new();
-        serde_wasm_bindgen::to_value(&empty).map_err(Error::Value)
+    #[wasm_bindgen(js_name = nodeId)]
+    pub async fn node_id(&self) -> JsString {
+        self.store.read().await.node_id().to_string().into()
+    }
+
+    #[wasm_bindgen(js_name = rotateNodeId)]
+    pub async fn rotate_node_id(&self) -> Result<JsString, Error> {
+        Ok(self
+            .store
+            .write()
+            .await
+            .rotate_node_id()
+            .await?
+            .to_string()
+            .into())
+    }
+
+    #[wasm_bindgen]
+    pub async fn list(&self, table_js: JsString) -> Result<JsValue, Error> {
+        let table: String = table_js.into();
+        let records = self.store.read().await.list(&table).await?;
+
+        records.serialize(&serializer()).map_err(Error::Value)
+    }
+
+    #[wasm_bindgen]
+    pub async fn get(&self, table_js: JsString, id_js: JsString) -> Result<JsValue, Error> {
+        let table: String = table_js.into();
+        let id = Uuid::parse_str(&String::from(id_js)).map_err(Error::Id)?;
+
+        let record = self.store.read().await.get(&table, id).await?;
+
+        record.serialize(&serializer()).map_err(Error::Value)
+    }
+
+    /// Calls `updater` with the current record for the caller to modify in
+    /// place, then writes whatever changed. We hold the write lock for the
+    /// whole time so nothing can sneak in between the read and the write.
+    #[wasm_bindgen]
+    pub async fn update(
+        &self,
+        table_js: JsString,
+        id_js: JsString,
+        updater: Function,
+    ) -> Result<(), Error> {
+        let table: String = table_js.into();
+        let id = Uuid::parse_str(&String::from(id_js)).map_err(Error::Id)?;
+
+        let mut store = self.store.write().await;
+
+        let current = store
+            .get(&table, id)
+            .await?
+            .serialize(&serializer())
+            .map_err(Error::Value)?;
+
+        updater
+            .call1(&JsValue::NULL, &current)
+            .map_err(Error::Updater)?;
+
+        store
+            .update(
+                table,
+                id,
+                serde_wasm_bindgen::from_value(current).map_err(Error::Value)?,
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    #[wasm_bindgen]
+    pub async fn delete(&self, table_js: JsString, id_js: JsString) -> Result<(), Error> {
+        let id = Uuid::parse_str(&String::from(id_js)).map_err(Error::Id)?;
+
+        Ok(self.store.write().await.delete(table_js.into(), id).await?)
+    }
+
+    #[wasm_bindgen]
+    pub async fn undelete(&self, table_js: JsString, id_js: JsString) -> Result<(), Error> {
+        let id = Uuid::parse_str(&String::from(id_js)).map_err(Error::Id)?;
+
+        Ok(self
+            .store
+            .write()
+            .await
+            .undelete(table_js.into(), id)
+            .await?)
     }
 }
 
@@ -93,10 +231,18 @@ impl Store {
 pub enum Error {
     #[error("Invalid migration. Details: {0}")]
     Migration(serde_wasm_bindgen::Error),
+    #[error("Invalid migration. Details: {0}")]
+    InvalidMigration(migrate::migrator::Error),
     #[error("Invalid schema mapping. Details: {0}")]
     SchemaMapping(serde_wasm_bindgen::Error),
     #[error("Invalid value. Details: {0}")]
     Value(serde_wasm_bindgen::Error),
+    #[error("Invalid ID. Details: {0}")]
+    Id(uuid::Error),
+    #[error("Invalid sync message. Details: {0}")]
+    Sync(serde_wasm_bindgen::Error),
+    #[error("Updater threw an error: {0:?}")]
+    Updater(JsValue),
     #[error("IndexedDB error: {0}")]
     Idb(#[from] idb::Error),
     #[error("Store error: {0}")]
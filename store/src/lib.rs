@@ -2,7 +2,10 @@ mod js_store;
 mod op;
 mod storage;
 mod store;
+mod sync;
 mod timestamp;
 
+#[cfg(test)]
+mod simulation;
 #[cfg(test)]
 mod test_helpers;
@@ -1,9 +1,9 @@
 use crate::timestamp::Timestamp;
-use migrate::Value;
+use migrate::{DeletePolicy, Value, Version};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-#[derive(Debug, PartialEq, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct Row {
     pub table: String,
     pub id: Uuid,
@@ -11,12 +11,38 @@ pub struct Row {
     pub removed: Option<Timestamp>,
 }
 
-#[derive(Debug, PartialEq, Deserialize, Serialize)]
+impl Row {
+    /// Whether reads should hide this row. `last_write` is the timestamp of
+    /// the newest field op for the row, which counts as re-adding it under
+    /// `DeletePolicy::AddWins`.
+    pub fn is_removed(&self, policy: DeletePolicy, last_write: Option<Timestamp>) -> bool {
+        let Some(removed) = self.removed else {
+            return false;
+        };
+
+        let last_add = match policy {
+            DeletePolicy::AddWins => last_write.map_or(self.added, |write| write.max(self.added)),
+            DeletePolicy::RemoveWins => self.added,
+        };
+
+        removed > last_add
+    }
+
+    /// Combine two copies of the same row, keeping the latest add and the
+    /// latest removal. This is commutative and idempotent, so it doesn't
+    /// matter what order replicas see row ops in.
+    pub fn merge(&mut self, other: &Row) {
+        self.added = self.added.max(other.added);
+        self.removed = self.removed.max(other.removed);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct Field {
     pub table: String,
     pub row_id: Uuid,
     pub field_name: String,
     pub timestamp: Timestamp,
-    pub schema_version: usize,
+    pub schema_version: Version,
     pub value: Value,
 }
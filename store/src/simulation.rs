@@ -0,0 +1,300 @@
+//! Convergence testing: run several in-memory replicas through random
+//! interleavings of writes and syncs, then check they all agree.
+
+use crate::storage::memory::MemoryStorage;
+use crate::store::{Error, Record, Store};
+use migrate::{AddRemoveField, DeletePolicy, Lens, Migration, Migrator, Type, Value};
+use pretty_assertions::assert_eq;
+use proptest::prelude::*;
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+use uuid::Uuid;
+
+/// Schema versions the replicas run, one entry per replica. Mixing versions
+/// means every sync also exercises translation between them.
+const VERSIONS: [usize; 3] = [1, 2, 2];
+
+fn migrator(policy: DeletePolicy) -> Migrator {
+    let mut migrator = Migrator::default();
+    migrator
+        .add_migration(Migration {
+            schema: "todo".into(),
+            version: 1.into(),
+            parents: Vec::new(),
+            delete_policy: Some(policy),
+            ops: vec![Lens::Add(AddRemoveField {
+                name: "title".into(),
+                type_: Type::String,
+                default: "".into(),
+            })],
+        })
+        .unwrap();
+    migrator
+        .add_migration(Migration {
+            schema: "todo".into(),
+            version: 2.into(),
+            parents: Vec::new(),
+            delete_policy: None,
+            ops: vec![
+                Lens::Rename {
+                    from: "title".into(),
+                    to: "name".into(),
+                },
+                Lens::Add(AddRemoveField {
+                    name: "done".into(),
+                    type_: Type::Bool,
+                    default: false.into(),
+                }),
+            ],
+        })
+        .unwrap();
+
+    migrator
+}
+
+#[derive(Debug, Clone)]
+enum Action {
+    Insert {
+        replica: usize,
+        text: String,
+    },
+    Update {
+        replica: usize,
+        row: usize,
+        text: String,
+        done: bool,
+    },
+    Delete {
+        replica: usize,
+        row: usize,
+    },
+    Undelete {
+        replica: usize,
+        row: usize,
+    },
+    Sync {
+        from: usize,
+        to: usize,
+    },
+}
+
+fn action() -> impl Strategy<Value = Action> {
+    let replica = 0..VERSIONS.len();
+    let row = any::<usize>();
+    let text = "[a-z]{0,4}";
+
+    prop_oneof![
+        1 => (replica.clone(), text).prop_map(|(replica, text)| Action::Insert {
+            replica,
+            text: text.to_string()
+        }),
+        1 => (replica.clone(), row, text, any::<bool>()).prop_map(
+            |(replica, row, text, done)| Action::Update {
+                replica,
+                row,
+                text: text.to_string(),
+                done,
+            }
+        ),
+        1 => (replica.clone(), row).prop_map(|(replica, row)| Action::Delete { replica, row }),
+        1 => (replica.clone(), row).prop_map(|(replica, row)| Action::Undelete { replica, row }),
+        // Syncs are the interesting part, so make them more likely.
+        3 => (replica.clone(), replica).prop_map(|(from, to)| Action::Sync { from, to }),
+    ]
+}
+
+/// An action, after letting the shared clock run for some milliseconds. Zero
+/// means the clock stalled, so replicas have to bump their counters instead.
+fn step() -> impl Strategy<Value = (u64, Action)> {
+    (0..3u64, action())
+}
+
+struct Simulation {
+    policy: DeletePolicy,
+    /// Wall time as the replicas see it. We move it along ourselves so that
+    /// a failing run replays the same way every time.
+    time: Rc<Cell<u64>>,
+    replicas: Vec<Store<MemoryStorage>>,
+    /// Every row ID inserted anywhere, in insertion order.
+    ids: Vec<Uuid>,
+}
+
+impl Simulation {
+    async fn new(policy: DeletePolicy) -> Self {
+        let time = Rc::new(Cell::new(0));
+        let mut replicas = Vec::new();
+
+        for (i, version) in VERSIONS.into_iter().enumerate() {
+            let storage = MemoryStorage {
+                node_id: Some(Uuid::from_u128(i as u128 + 1)),
+                ..MemoryStorage::default()
+            };
+
+            replicas.push(Self::replica(policy, &time, version, storage).await);
+        }
+
+        Self {
+            policy,
+            time,
+            replicas,
+            ids: Vec::new(),
+        }
+    }
+
+    async fn replica(
+        policy: DeletePolicy,
+        time: &Rc<Cell<u64>>,
+        version: usize,
+        storage: MemoryStorage,
+    ) -> Store<MemoryStorage> {
+        let mut store = Store::new(
+            migrator(policy),
+            BTreeMap::from([("todo".into(), version.into())]),
+            storage,
+        )
+        .await
+        .unwrap();
+
+        let time = time.clone();
+        store.set_now(move || time.get());
+
+        store
+    }
+
+    fn data(replica: usize, text: String, done: bool) -> BTreeMap<String, Value> {
+        match VERSIONS[replica] {
+            1 => BTreeMap::from([("title".into(), text.into())]),
+            _ => BTreeMap::from([("name".into(), text.into()), ("done".into(), done.into())]),
+        }
+    }
+
+    fn pick(&self, row: usize) -> Option<Uuid> {
+        if self.ids.is_empty() {
+            None
+        } else {
+            Some(self.ids[row % self.ids.len()])
+        }
+    }
+
+    async fn run(&mut self, elapsed: u64, action: Action) {
+        self.time.set(self.time.get() + elapsed);
+
+        let result = match action {
+            Action::Insert { replica, text } => {
+                let id = self.replicas[replica]
+                    .insert("todo".into(), Self::data(replica, text, false))
+                    .await
+                    .unwrap();
+                self.ids.push(id);
+
+                Ok(())
+            }
+            Action::Update {
+                replica,
+                row,
+                text,
+                done,
+            } => match self.pick(row) {
+                Some(id) => {
+                    self.replicas[replica]
+                        .update("todo".into(), id, Self::data(replica, text, done))
+                        .await
+                }
+                None => Ok(()),
+            },
+            Action::Delete { replica, row } => match self.pick(row) {
+                Some(id) => self.replicas[replica].delete("todo".into(), id).await,
+                None => Ok(()),
+            },
+            Action::Undelete { replica, row } => match self.pick(row) {
+                Some(id) => self.replicas[replica].undelete("todo".into(), id).await,
+                None => Ok(()),
+            },
+            Action::Sync { from, to } => {
+                self.sync(from, to).await;
+
+                Ok(())
+            }
+        };
+
+        // Replicas act on rows they may not have heard of yet (or that
+        // they've removed), which is fine. Anything else is a bug.
+        match result {
+            Ok(()) | Err(Error::NotFound(..)) => {}
+            Err(err) => panic!("unexpected error: {err:?}"),
+        }
+    }
+
+    async fn sync(&mut self, from: usize, to: usize) {
+        if from == to {
+            return;
+        }
+
+        let summary = self.replicas[to].summary().await.unwrap();
+        let changes = self.replicas[from].changes_since(&summary).await.unwrap();
+        self.replicas[to].apply(changes).await.unwrap();
+    }
+
+    async fn sync_all(&mut self) {
+        for from in 0..self.replicas.len() {
+            for to in 0..self.replicas.len() {
+                self.sync(from, to).await;
+            }
+        }
+    }
+
+    /// What each replica's data looks like when read at `version`.
+    async fn read_all_at(&self, version: usize) -> Vec<Vec<Record>> {
+        let mut out = Vec::new();
+
+        for replica in &self.replicas {
+            let reader =
+                Self::replica(self.policy, &self.time, version, replica.storage().clone()).await;
+
+            let mut records = reader.list("todo").await.unwrap();
+            records.sort_by_key(|record| record.id);
+            out.push(records);
+        }
+
+        out
+    }
+}
+
+fn run(policy: DeletePolicy, steps: Vec<(u64, Action)>) {
+    tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap()
+        .block_on(async {
+            let mut simulation = Simulation::new(policy).await;
+
+            for (elapsed, action) in steps {
+                simulation.run(elapsed, action).await;
+            }
+
+            simulation.sync_all().await;
+
+            for version in [1, 2] {
+                let reads = simulation.read_all_at(version).await;
+
+                for (i, read) in reads.iter().enumerate().skip(1) {
+                    assert_eq!(
+                        &reads[0], read,
+                        "replica {i} disagrees with replica 0 at version {version}"
+                    );
+                }
+            }
+        });
+}
+
+proptest! {
+    #[test]
+    fn replicas_converge_with_add_wins(steps in prop::collection::vec(step(), 0..40)) {
+        run(DeletePolicy::AddWins, steps);
+    }
+
+    #[test]
+    fn replicas_converge_with_remove_wins(steps in prop::collection::vec(step(), 0..40)) {
+        run(DeletePolicy::RemoveWins, steps);
+    }
+}
@@ -1,22 +1,63 @@
 pub mod idb;
+#[cfg(test)]
 pub mod memory;
 
 use crate::op::{Field, Row};
+use uuid::Uuid;
 
 pub trait Storage {
     type Error: std::error::Error;
+    type ROTransaction<'a>: ROTransaction<Error = Self::Error>
+    where
+        Self: 'a;
     type RWTransaction<'a>: RWTransaction<Error = Self::Error>
     where
         Self: 'a;
 
+    async fn ro_transaction(&self) -> Result<Self::ROTransaction<'_>, Self::Error>;
     async fn rw_transaction(&mut self) -> Result<Self::RWTransaction<'_>, Self::Error>;
 }
 
+pub trait ROTransaction {
+    type Error: std::error::Error;
+
+    /// The highest clock counter we've persisted, or 0 if we've never written
+    /// anything.
+    async fn high_water_mark(&self) -> Result<u64, Self::Error>;
+
+    /// The ID this replica stamps on its ops, or `None` if we haven't opened
+    /// this storage before.
+    async fn node_id(&self) -> Result<Option<Uuid>, Self::Error>;
+
+    /// All the rows we know about in `table`, including removed ones.
+    async fn rows(&self, table: &str) -> Result<Vec<Row>, Self::Error>;
+
+    /// A single row by ID, whether or not it has been removed.
+    async fn row(&self, table: &str, id: Uuid) -> Result<Option<Row>, Self::Error>;
+
+    /// All the field ops we know about for a single row.
+    async fn fields(&self, table: &str, row_id: Uuid) -> Result<Vec<Field>, Self::Error>;
+
+    /// Every row we know about, in any table.
+    async fn all_rows(&self) -> Result<Vec<Row>, Self::Error>;
+
+    /// Every field op we know about, in any table.
+    async fn all_fields(&self) -> Result<Vec<Field>, Self::Error>;
+}
+
 pub trait RWTransaction {
     type Error: std::error::Error;
 
+    /// Store a row op, merging it with any copy of the row we already have
+    /// (see `Row::merge`.)
     async fn store_row(&mut self, row: Row) -> Result<(), Self::Error>;
+
+    /// Store a field op. If we already have an op for the same field with a
+    /// higher timestamp, the older one is dropped instead, so storing the same
+    /// op twice (or out of order) is harmless.
     async fn store_field(&mut self, field: Field) -> Result<(), Self::Error>;
+    async fn store_high_water_mark(&mut self, counter: u64) -> Result<(), Self::Error>;
+    async fn store_node_id(&mut self, node_id: Uuid) -> Result<(), Self::Error>;
 
     async fn commit(self) -> Result<(), Self::Error>;
     async fn abort(self) -> Result<(), Self::Error>;
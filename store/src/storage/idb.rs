@@ -1,9 +1,10 @@
-use super::{RWTransaction, Storage};
-use crate::op::Row;
+use super::{ROTransaction, RWTransaction, Storage};
+use crate::op::{Field, Row};
 use idb::{
-    CursorDirection, Database, KeyPath, Query, TransactionMode,
+    Database, KeyPath, Query, TransactionMode,
     builder::{DatabaseBuilder, IndexBuilder, ObjectStoreBuilder},
 };
+use uuid::Uuid;
 use wasm_bindgen::JsValue;
 
 pub struct IDBStorage {
@@ -13,6 +14,7 @@ pub struct IDBStorage {
 impl IDBStorage {
     pub async fn init() -> Result<Self, Error> {
         let database = DatabaseBuilder::new("ops")
+            .version(2)
             .add_object_store(
                 ObjectStoreBuilder::new("row")
                     .auto_increment(false)
@@ -33,64 +35,184 @@ impl IDBStorage {
                             .multi_entry(false),
                     ),
             )
+            .add_object_store(ObjectStoreBuilder::new("meta").auto_increment(false))
             .build()
             .await?;
 
         Ok(Self { database })
     }
-
-    pub async fn get_rows(&self, table: &str) -> Result<Vec<Row>, Error> {
-        let tx = self
-            .database
-            .transaction(&["row"], TransactionMode::ReadOnly)?;
-
-        let row_store = tx.object_store("row")?;
-        let by_table = row_store.index("by_table")?;
-
-        let cursor = by_table
-            .open_cursor(Some(Query::Key(table.into())), Some(CursorDirection::Next))?
-            .await?
-            .ok_or(Error::MissingCursor)?;
-
-        let mut out = Vec::new();
-        while let Some(raw_row) = cursor.next(None)?.await? {
-            out.push(serde_wasm_bindgen::from_value(raw_row.value()?)?);
-        }
-
-        Ok(out)
-    }
 }
 
 impl Storage for IDBStorage {
     type Error = Error;
+    type ROTransaction<'a>
+        = IDBROTransaction
+    where
+        Self: 'a;
     type RWTransaction<'a>
         = IDBRWTransaction
     where
         Self: 'a;
 
+    async fn ro_transaction(&self) -> Result<Self::ROTransaction<'_>, Self::Error> {
+        Ok(IDBROTransaction(self.database.transaction(
+            &["row", "field", "meta"],
+            TransactionMode::ReadOnly,
+        )?))
+    }
+
     async fn rw_transaction(&mut self) -> Result<Self::RWTransaction<'_>, Self::Error> {
         Ok(IDBRWTransaction(self.database.transaction(
-            &["row", "field"],
+            &["row", "field", "meta"],
             TransactionMode::ReadWrite,
         )?))
     }
 }
 
+/// Key in the `meta` store where we keep the clock's high-water mark.
+const CLOCK_KEY: &str = "clock";
+
+/// Key in the `meta` store where we keep this replica's node ID.
+const NODE_ID_KEY: &str = "node_id";
+
+pub struct IDBROTransaction(idb::Transaction);
+
+impl ROTransaction for IDBROTransaction {
+    type Error = Error;
+
+    async fn high_water_mark(&self) -> Result<u64, Self::Error> {
+        match self
+            .0
+            .object_store("meta")?
+            .get(JsValue::from_str(CLOCK_KEY))?
+            .await?
+        {
+            Some(raw) => Ok(serde_wasm_bindgen::from_value(raw)?),
+            None => Ok(0),
+        }
+    }
+
+    async fn node_id(&self) -> Result<Option<Uuid>, Self::Error> {
+        match self
+            .0
+            .object_store("meta")?
+            .get(JsValue::from_str(NODE_ID_KEY))?
+            .await?
+        {
+            Some(raw) => Ok(Some(serde_wasm_bindgen::from_value(raw)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn rows(&self, table: &str) -> Result<Vec<Row>, Self::Error> {
+        let by_table = self.0.object_store("row")?.index("by_table")?;
+
+        by_table
+            .get_all(Some(Query::Key(table.into())), None)?
+            .await?
+            .into_iter()
+            .map(|raw_row| Ok(serde_wasm_bindgen::from_value(raw_row)?))
+            .collect()
+    }
+
+    async fn row(&self, table: &str, id: Uuid) -> Result<Option<Row>, Self::Error> {
+        let key = serde_wasm_bindgen::to_value(&(table, id))?;
+
+        match self.0.object_store("row")?.get(key)?.await? {
+            Some(raw_row) => Ok(Some(serde_wasm_bindgen::from_value(raw_row)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn fields(&self, table: &str, row_id: Uuid) -> Result<Vec<Field>, Self::Error> {
+        let by_row_id = self.0.object_store("field")?.index("by_row_id")?;
+
+        let mut out = Vec::new();
+        for raw_field in by_row_id
+            .get_all(Some(Query::Key(row_id.to_string().into())), None)?
+            .await?
+        {
+            let field: Field = serde_wasm_bindgen::from_value(raw_field)?;
+            if field.table == table {
+                out.push(field);
+            }
+        }
+
+        Ok(out)
+    }
+
+    async fn all_rows(&self) -> Result<Vec<Row>, Self::Error> {
+        self.0
+            .object_store("row")?
+            .get_all(None, None)?
+            .await?
+            .into_iter()
+            .map(|raw_row| Ok(serde_wasm_bindgen::from_value(raw_row)?))
+            .collect()
+    }
+
+    async fn all_fields(&self) -> Result<Vec<Field>, Self::Error> {
+        self.0
+            .object_store("field")?
+            .get_all(None, None)?
+            .await?
+            .into_iter()
+            .map(|raw_field| Ok(serde_wasm_bindgen::from_value(raw_field)?))
+            .collect()
+    }
+}
+
 pub struct IDBRWTransaction(idb::Transaction);
 
 impl RWTransaction for IDBRWTransaction {
     type Error = Error;
 
-    async fn store_row(&mut self, row: Row) -> Result<(), Error> {
+    async fn store_row(&mut self, mut row: Row) -> Result<(), Error> {
         let row_store = self.0.object_store("row")?;
-        row_store.add(&serde_wasm_bindgen::to_value(&row)?, None)?;
+
+        let key = serde_wasm_bindgen::to_value(&(&row.table, row.id))?;
+        if let Some(raw_existing) = row_store.get(key)?.await? {
+            let existing: Row = serde_wasm_bindgen::from_value(raw_existing)?;
+            row.merge(&existing);
+        }
+
+        row_store.put(&serde_wasm_bindgen::to_value(&row)?, None)?;
 
         Ok(())
     }
 
-    async fn store_field(&mut self, field: crate::op::Field) -> Result<(), Self::Error> {
+    async fn store_field(&mut self, field: Field) -> Result<(), Self::Error> {
         let field_store = self.0.object_store("field")?;
-        field_store.add(&serde_wasm_bindgen::to_value(&field)?, None)?;
+
+        let key = serde_wasm_bindgen::to_value(&(&field.table, field.row_id, &field.field_name))?;
+        if let Some(raw_existing) = field_store.get(key)?.await? {
+            let existing: Field = serde_wasm_bindgen::from_value(raw_existing)?;
+            if existing.timestamp >= field.timestamp {
+                return Ok(());
+            }
+        }
+
+        field_store.put(&serde_wasm_bindgen::to_value(&field)?, None)?;
+
+        Ok(())
+    }
+
+    async fn store_high_water_mark(&mut self, counter: u64) -> Result<(), Self::Error> {
+        let meta_store = self.0.object_store("meta")?;
+        meta_store.put(
+            &serde_wasm_bindgen::to_value(&counter)?,
+            Some(&JsValue::from_str(CLOCK_KEY)),
+        )?;
+
+        Ok(())
+    }
+
+    async fn store_node_id(&mut self, node_id: Uuid) -> Result<(), Self::Error> {
+        let meta_store = self.0.object_store("meta")?;
+        meta_store.put(
+            &serde_wasm_bindgen::to_value(&node_id)?,
+            Some(&JsValue::from_str(NODE_ID_KEY)),
+        )?;
 
         Ok(())
     }
@@ -115,9 +237,6 @@ pub enum Error {
 
     #[error("Serde error: {0}")]
     Serde(#[from] serde_wasm_bindgen::Error),
-
-    #[error("Missing cursor while reading")]
-    MissingCursor,
 }
 
 impl From<Error> for JsValue {
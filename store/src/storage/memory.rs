@@ -1,35 +1,101 @@
-use super::{RWTransaction, Storage};
+use super::{ROTransaction, RWTransaction, Storage};
 use crate::op::{Field, Row};
+use uuid::Uuid;
 
-#[derive(Default)]
+#[derive(Clone, Default)]
 pub struct MemoryStorage {
     pub rows: Vec<Row>,
     pub fields: Vec<Field>,
+    pub high_water_mark: u64,
+    pub node_id: Option<Uuid>,
 }
 
 impl Storage for MemoryStorage {
     type Error = Error;
+    type ROTransaction<'a>
+        = MemoryROTransaction<'a>
+    where
+        Self: 'a;
     type RWTransaction<'a>
-        = MemoryTransaction<'a>
+        = MemoryRWTransaction<'a>
     where
         Self: 'a;
 
+    async fn ro_transaction(&self) -> Result<Self::ROTransaction<'_>, Self::Error> {
+        Ok(MemoryROTransaction(self))
+    }
+
     async fn rw_transaction(&mut self) -> Result<Self::RWTransaction<'_>, Self::Error> {
-        Ok(MemoryTransaction {
+        Ok(MemoryRWTransaction {
             storage: self,
             rows: Vec::new(),
             fields: Vec::new(),
+            high_water_mark: None,
+            node_id: None,
         })
     }
 }
 
-pub struct MemoryTransaction<'a> {
+pub struct MemoryROTransaction<'a>(&'a MemoryStorage);
+
+impl<'a> ROTransaction for MemoryROTransaction<'a> {
+    type Error = Error;
+
+    async fn high_water_mark(&self) -> Result<u64, Self::Error> {
+        Ok(self.0.high_water_mark)
+    }
+
+    async fn node_id(&self) -> Result<Option<Uuid>, Self::Error> {
+        Ok(self.0.node_id)
+    }
+
+    async fn rows(&self, table: &str) -> Result<Vec<Row>, Self::Error> {
+        Ok(self
+            .0
+            .rows
+            .iter()
+            .filter(|row| row.table == table)
+            .cloned()
+            .collect())
+    }
+
+    async fn row(&self, table: &str, id: Uuid) -> Result<Option<Row>, Self::Error> {
+        Ok(self
+            .0
+            .rows
+            .iter()
+            .find(|row| row.table == table && row.id == id)
+            .cloned())
+    }
+
+    async fn fields(&self, table: &str, row_id: Uuid) -> Result<Vec<Field>, Self::Error> {
+        Ok(self
+            .0
+            .fields
+            .iter()
+            .filter(|field| field.table == table && field.row_id == row_id)
+            .cloned()
+            .collect())
+    }
+
+    async fn all_rows(&self) -> Result<Vec<Row>, Self::Error> {
+        Ok(self.0.rows.clone())
+    }
+
+    async fn all_fields(&self) -> Result<Vec<Field>, Self::Error> {
+        Ok(self.0.fields.clone())
+    }
+}
+
+pub struct MemoryRWTransaction<'a> {
     storage: &'a mut MemoryStorage,
     rows: Vec<Row>,
     fields: Vec<Field>,
+    high_water_mark: Option<u64>,
+    node_id: Option<Uuid>,
 }
 
-impl<'a> RWTransaction for MemoryTransaction<'a> {
+impl<'a> RWTransaction for MemoryRWTransaction<'a> {
     type Error = Error;
 
     async fn store_row(&mut self, row: Row) -> Result<(), Error> {
@@ -42,9 +108,47 @@ impl<'a> RWTransaction for MemoryTransaction<'a> {
         Ok(())
     }
 
-    async fn commit(mut self) -> Result<(), Self::Error> {
-        self.storage.rows.append(&mut self.rows);
-        self.storage.fields.append(&mut self.fields);
+    async fn store_high_water_mark(&mut self, counter: u64) -> Result<(), Self::Error> {
+        self.high_water_mark = Some(counter);
+        Ok(())
+    }
+
+    async fn store_node_id(&mut self, node_id: Uuid) -> Result<(), Self::Error> {
+        self.node_id = Some(node_id);
+        Ok(())
+    }
+
+    async fn commit(self) -> Result<(), Self::Error> {
+        for row in self.rows {
+            match self
+                .storage
+                .rows
+                .iter_mut()
+                .find(|existing| existing.table == row.table && existing.id == row.id)
+            {
+                Some(existing) => existing.merge(&row),
+                None => self.storage.rows.push(row),
+            }
+        }
+
+        for field in self.fields {
+            match self.storage.fields.iter_mut().find(|existing| {
+                existing.table == field.table
+                    && existing.row_id == field.row_id
+                    && existing.field_name == field.field_name
+            }) {
+                Some(existing) if existing.timestamp < field.timestamp => *existing = field,
+                Some(_) => {}
+                None => self.storage.fields.push(field),
+            }
+        }
+
+        if let Some(counter) = self.high_water_mark {
+            self.storage.high_water_mark = counter;
+        }
+        if let Some(node_id) = self.node_id {
+            self.storage.node_id = Some(node_id);
+        }
 
         Ok(())
     }
@@ -54,5 +158,139 @@ impl<'a> RWTransaction for MemoryTransaction<'a> {
     }
 }
 
-#[derive(Debug, thiserror::Error)]
+#[derive(Debug, thiserror::Error, PartialEq)]
 pub enum Error {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timestamp::Timestamp;
+    use pretty_assertions::assert_eq;
+
+    fn row(table: &str, id: Uuid) -> Row {
+        Row {
+            table: table.into(),
+            id,
+            added: Timestamp::new(1, Uuid::nil()),
+            removed: None,
+        }
+    }
+
+    fn field(table: &str, row_id: Uuid, name: &str, counter: u64) -> Field {
+        Field {
+            table: table.into(),
+            row_id,
+            field_name: name.into(),
+            timestamp: Timestamp::new(counter, Uuid::nil()),
+            schema_version: 1.into(),
+            value: counter.to_string().into(),
+        }
+    }
+
+    async fn storage_with(rows: Vec<Row>, fields: Vec<Field>) -> MemoryStorage {
+        let mut storage = MemoryStorage::default();
+
+        let mut tx = storage.rw_transaction().await.unwrap();
+        for row in rows {
+            tx.store_row(row).await.unwrap();
+        }
+        for field in fields {
+            tx.store_field(field).await.unwrap();
+        }
+        tx.commit().await.unwrap();
+
+        storage
+    }
+
+    #[tokio::test]
+    async fn rows_filters_by_table() {
+        let a = Uuid::now_v7();
+        let b = Uuid::now_v7();
+        let storage = storage_with(vec![row("a", a), row("b", b)], Vec::new()).await;
+
+        let tx = storage.ro_transaction().await.unwrap();
+
+        assert_eq!(tx.rows("a").await, Ok(vec![row("a", a)]));
+        assert_eq!(tx.rows("c").await, Ok(Vec::new()));
+    }
+
+    #[tokio::test]
+    async fn row_gets_by_table_and_id() {
+        let id = Uuid::now_v7();
+        let storage = storage_with(vec![row("a", id)], Vec::new()).await;
+
+        let tx = storage.ro_transaction().await.unwrap();
+
+        assert_eq!(tx.row("a", id).await, Ok(Some(row("a", id))));
+        assert_eq!(tx.row("b", id).await, Ok(None));
+        assert_eq!(tx.row("a", Uuid::now_v7()).await, Ok(None));
+    }
+
+    #[tokio::test]
+    async fn fields_filters_by_row() {
+        let a = Uuid::now_v7();
+        let b = Uuid::now_v7();
+        let storage = storage_with(
+            vec![row("t", a), row("t", b)],
+            vec![field("t", a, "x", 1), field("t", b, "x", 2)],
+        )
+        .await;
+
+        let tx = storage.ro_transaction().await.unwrap();
+
+        assert_eq!(tx.fields("t", a).await, Ok(vec![field("t", a, "x", 1)]));
+        assert_eq!(tx.fields("other", a).await, Ok(Vec::new()));
+    }
+
+    #[tokio::test]
+    async fn fields_keeps_latest_op() {
+        let id = Uuid::now_v7();
+        let storage = storage_with(
+            vec![row("t", id)],
+            vec![
+                field("t", id, "x", 2),
+                field("t", id, "x", 1),
+                field("t", id, "x", 3),
+            ],
+        )
+        .await;
+
+        let tx = storage.ro_transaction().await.unwrap();
+
+        assert_eq!(tx.fields("t", id).await, Ok(vec![field("t", id, "x", 3)]));
+    }
+
+    #[tokio::test]
+    async fn meta_round_trips() {
+        let mut storage = MemoryStorage::default();
+        let node_id = Uuid::now_v7();
+
+        let tx = storage.ro_transaction().await.unwrap();
+        assert_eq!(tx.high_water_mark().await, Ok(0));
+        assert_eq!(tx.node_id().await, Ok(None));
+
+        let mut tx = storage.rw_transaction().await.unwrap();
+        tx.store_high_water_mark(42).await.unwrap();
+        tx.store_node_id(node_id).await.unwrap();
+        tx.commit().await.unwrap();
+
+        let tx = storage.ro_transaction().await.unwrap();
+        assert_eq!(tx.high_water_mark().await, Ok(42));
+        assert_eq!(tx.node_id().await, Ok(Some(node_id)));
+    }
+
+    #[tokio::test]
+    async fn abort_discards_writes() {
+        let mut storage = MemoryStorage::default();
+        let id = Uuid::now_v7();
+
+        let mut tx = storage.rw_transaction().await.unwrap();
+        tx.store_row(row("t", id)).await.unwrap();
+        tx.store_field(field("t", id, "x", 1)).await.unwrap();
+        tx.abort().await.unwrap();
+
+        let tx = storage.ro_transaction().await.unwrap();
+        assert_eq!(tx.rows("t").await, Ok(Vec::new()));
+        assert_eq!(tx.fields("t", id).await, Ok(Vec::new()));
+    }
+}
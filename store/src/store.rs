@@ -1,25 +1,134 @@
 use crate::op::{Field, Row};
-use crate::storage::{RWTransaction, Storage};
-use crate::timestamp::Timestamp;
-use migrate::{Migrator, Value, migrator, type_};
+use crate::storage::{ROTransaction, RWTransaction, Storage};
+use crate::sync::{self, Changes, VersionVector};
+use crate::timestamp::{self, Clock, Timestamp};
+use migrate::migrator::{self, Link};
+use migrate::{Migrator, Schema, Value, Version, type_};
+use serde::Serialize;
 use std::collections::BTreeMap;
+use std::collections::btree_map::Entry;
 use std::fmt::Display;
 use uuid::Uuid;
 use wasm_bindgen::JsValue;
 
 pub struct Store<S: Storage> {
     migrator: Migrator,
-    schema_to_version: BTreeMap<String, usize>,
+    schema_to_version: BTreeMap<String, Version>,
     storage: S,
+    clock: Clock,
+    /// Where the clock gets wall time from, in milliseconds since the Unix
+    /// epoch.
+    now: Box<dyn Fn() -> u64>,
 }
 
 impl<S: Storage> Store<S> {
-    pub fn new(migrator: Migrator, schema_to_version: BTreeMap<String, usize>, storage: S) -> Self {
-        Self {
+    pub async fn new(
+        migrator: Migrator,
+        schema_to_version: BTreeMap<String, Version>,
+        mut storage: S,
+    ) -> Result<Self, Error<S::Error>> {
+        migrator
+            .check_versions(&schema_to_version)
+            .map_err(Error::Versions)?;
+
+        let (high_water_mark, stored_node_id) = {
+            let tx = storage.ro_transaction().await?;
+            (tx.high_water_mark().await?, tx.node_id().await?)
+        };
+
+        let node_id = match stored_node_id {
+            Some(node_id) => node_id,
+            None => {
+                let node_id = Uuid::now_v7();
+
+                let mut tx = storage.rw_transaction().await?;
+                tx.store_node_id(node_id).await?;
+                tx.commit().await?;
+
+                node_id
+            }
+        };
+
+        Ok(Self {
             migrator,
             schema_to_version,
             storage,
+            clock: Clock::new(node_id, high_water_mark),
+            now: Box::new(timestamp::now),
+        })
+    }
+
+    #[cfg(test)]
+    pub fn storage(&self) -> &S {
+        &self.storage
+    }
+
+    /// Take wall time from somewhere other than the system clock, so that
+    /// timestamps are deterministic.
+    #[cfg(test)]
+    pub fn set_now(&mut self, now: impl Fn() -> u64 + 'static) {
+        self.now = Box::new(now);
+    }
+
+    /// The ID that identifies this replica in op timestamps.
+    pub fn node_id(&self) -> Uuid {
+        self.clock.node()
+    }
+
+    /// Give this replica a fresh node ID. Do this when a database has been
+    /// copied to another device; otherwise both copies would stamp their ops
+    /// with the same ID and we couldn't tell their writes apart.
+    pub async fn rotate_node_id(&mut self) -> Result<Uuid, Error<S::Error>> {
+        let node_id = Uuid::now_v7();
+
+        let mut tx = self.storage.rw_transaction().await?;
+        tx.store_node_id(node_id).await?;
+        tx.commit().await?;
+
+        self.clock.set_node(node_id);
+
+        Ok(node_id)
+    }
+
+    /// Get every live row in `table`, shaped according to the schema version
+    /// this store was configured with for that table.
+    pub async fn list(&self, table: &str) -> Result<Vec<Record>, Error<S::Error>> {
+        let mut out = Vec::new();
+
+        let tx = self.storage.ro_transaction().await?;
+        let read = self.read(&tx, table).await?;
+
+        for row in tx.rows(table).await? {
+            let Some(fields) = self.live_fields(&tx, &row).await? else {
+                continue;
+            };
+
+            out.push(Record {
+                id: row.id,
+                data: self.materialize(&tx, &read, row.id, fields).await?,
+            });
+        }
+
+        Ok(out)
+    }
+
+    /// Get a single live row from `table`, shaped according to the schema
+    /// version this store was configured with for that table.
+    pub async fn get(
+        &self,
+        table: &str,
+        id: Uuid,
+    ) -> Result<BTreeMap<String, Value>, Error<S::Error>> {
+        let tx = self.storage.ro_transaction().await?;
+        let read = self.read(&tx, table).await?;
+
+        let fields = match tx.row(table, id).await? {
+            Some(row) => self.live_fields(&tx, &row).await?,
+            None => None,
         }
+        .ok_or_else(|| Error::NotFound(table.to_string(), id))?;
+
+        self.materialize(&tx, &read, id, fields).await
     }
 
     pub async fn insert(
@@ -27,16 +136,15 @@ impl<S: Storage> Store<S> {
         table: String,
         mut data: BTreeMap<String, Value>,
     ) -> Result<Uuid, Error<S::Error>> {
-        let schema_version = self
-            .schema_to_version
-            .get(&table)
-            .ok_or_else(|| Error::TableNotFound(table.clone()))?;
+        let schema_version = self.schema_version(&table)?;
 
         let schema = self
             .migrator
-            .schema(&table, *schema_version)
+            .schema(&table, &schema_version)
             .map_err(Error::Schema)?;
 
+        let timestamp = self.clock.tick((self.now)()).map_err(Error::Clock)?;
+
         let mut tx = self
             .storage
             .rw_transaction()
@@ -47,7 +155,8 @@ impl<S: Storage> Store<S> {
 
         for (name, field) in schema {
             if let Some(value) = data.remove(&name) {
-                if let Err(err) = field.type_.validate(&value) {
+                let value = field.type_.coerce(value);
+                if let Err(err) = field.validate(&value) {
                     tx.abort().await.map_err(Error::Storage)?;
                     return Err(Error::Validation(name.clone(), err));
                 }
@@ -56,8 +165,8 @@ impl<S: Storage> Store<S> {
                     table: table.clone(),
                     row_id: id,
                     field_name: name,
-                    timestamp: Timestamp::new(0, Uuid::nil()), // TODO: Implement timestamp generation
-                    schema_version: *schema_version,
+                    timestamp,
+                    schema_version: schema_version.clone(),
                     value,
                 })
                 .await
@@ -68,16 +177,520 @@ impl<S: Storage> Store<S> {
         tx.store_row(Row {
             table,
             id,
-            added: Timestamp::new(0, Uuid::nil()), // TODO: Implement timestamp generation
+            added: timestamp,
             removed: None,
         })
         .await
         .map_err(Error::Storage)?;
 
+        tx.store_high_water_mark(self.clock.high_water_mark())
+            .await
+            .map_err(Error::Storage)?;
+
         tx.commit().await.map_err(Error::Storage)?;
 
         Ok(id)
     }
+
+    /// Change some fields of an existing row. Only values that differ from
+    /// what we currently have are written, each as a new field op, so
+    /// concurrent updates to different fields of the same row both survive.
+    pub async fn update(
+        &mut self,
+        table: String,
+        id: Uuid,
+        data: BTreeMap<String, Value>,
+    ) -> Result<(), Error<S::Error>> {
+        let schema_version = self.schema_version(&table)?;
+
+        let schema = self
+            .migrator
+            .schema(&table, &schema_version)
+            .map_err(Error::Schema)?;
+
+        let current = self.get(&table, id).await?;
+
+        let mut changed = Vec::new();
+        for (name, value) in data {
+            let field = schema
+                .get(&name)
+                .ok_or_else(|| Error::FieldNotFound(table.clone(), name.clone()))?;
+            let value = field.type_.coerce(value);
+
+            // Values we already have might predate a constraint, so only
+            // check the ones actually changing.
+            if current.get(&name) == Some(&value) {
+                continue;
+            }
+
+            if let Err(err) = field.validate(&value) {
+                return Err(Error::Validation(name, err));
+            }
+
+            changed.push((name, value));
+        }
+
+        if changed.is_empty() {
+            return Ok(());
+        }
+
+        let timestamp = self.clock.tick((self.now)()).map_err(Error::Clock)?;
+
+        let mut tx = self
+            .storage
+            .rw_transaction()
+            .await
+            .map_err(Error::Storage)?;
+
+        for (name, value) in changed {
+            tx.store_field(Field {
+                table: table.clone(),
+                row_id: id,
+                field_name: name,
+                timestamp,
+                schema_version: schema_version.clone(),
+                value,
+            })
+            .await
+            .map_err(Error::Storage)?;
+        }
+
+        tx.store_high_water_mark(self.clock.high_water_mark())
+            .await
+            .map_err(Error::Storage)?;
+
+        tx.commit().await.map_err(Error::Storage)?;
+
+        Ok(())
+    }
+
+    /// Remove a row. Whether a concurrent update on another replica brings it
+    /// back depends on the table's `DeletePolicy`.
+    pub async fn delete(&mut self, table: String, id: Uuid) -> Result<(), Error<S::Error>> {
+        let row = {
+            let tx = self.storage.ro_transaction().await?;
+
+            match tx.row(&table, id).await? {
+                Some(row) if self.live_fields(&tx, &row).await?.is_some() => row,
+                _ => return Err(Error::NotFound(table, id)),
+            }
+        };
+
+        let timestamp = self.clock.tick((self.now)()).map_err(Error::Clock)?;
+
+        self.store_row(Row {
+            removed: Some(timestamp),
+            ..row
+        })
+        .await
+    }
+
+    /// Restore a removed row, along with whatever field values it had when it
+    /// was removed.
+    pub async fn undelete(&mut self, table: String, id: Uuid) -> Result<(), Error<S::Error>> {
+        let row = {
+            let tx = self.storage.ro_transaction().await?;
+
+            match tx.row(&table, id).await? {
+                Some(row) if self.live_fields(&tx, &row).await?.is_some() => return Ok(()),
+                Some(row) => row,
+                None => return Err(Error::NotFound(table, id)),
+            }
+        };
+
+        let timestamp = self.clock.tick((self.now)()).map_err(Error::Clock)?;
+
+        self.store_row(Row {
+            added: timestamp,
+            ..row
+        })
+        .await
+    }
+
+    async fn store_row(&mut self, row: Row) -> Result<(), Error<S::Error>> {
+        let mut tx = self
+            .storage
+            .rw_transaction()
+            .await
+            .map_err(Error::Storage)?;
+
+        tx.store_row(row).await.map_err(Error::Storage)?;
+
+        tx.store_high_water_mark(self.clock.high_water_mark())
+            .await
+            .map_err(Error::Storage)?;
+
+        tx.commit().await.map_err(Error::Storage)?;
+
+        Ok(())
+    }
+
+    /// Get a row's field ops, or `None` if the row has been removed.
+    async fn live_fields(
+        &self,
+        tx: &S::ROTransaction<'_>,
+        row: &Row,
+    ) -> Result<Option<Vec<Field>>, Error<S::Error>> {
+        let fields = tx.fields(&row.table, row.id).await?;
+
+        let policy = self.migrator.delete_policy(&row.table);
+        let last_write = fields.iter().map(|field| field.timestamp).max();
+
+        if row.is_removed(policy, last_write) {
+            Ok(None)
+        } else {
+            Ok(Some(fields))
+        }
+    }
+
+    /// Summarize the ops we have, to send to a replica we want changes from.
+    pub async fn summary(&self) -> Result<VersionVector, Error<S::Error>> {
+        let tx = self.storage.ro_transaction().await?;
+
+        Ok(sync::summary(&tx).await?)
+    }
+
+    /// Get the ops a replica with the given summary is missing.
+    pub async fn changes_since(&self, remote: &VersionVector) -> Result<Changes, Error<S::Error>> {
+        let tx = self.storage.ro_transaction().await?;
+
+        Ok(sync::changes_since(&tx, remote).await?)
+    }
+
+    /// Apply ops we got from another replica. If any of them are too far in
+    /// the future for our clock to accept, we don't apply any of them, and
+    /// the clock stays where it was.
+    pub async fn apply(&mut self, changes: Changes) -> Result<(), Error<S::Error>> {
+        let Some(latest) = changes.timestamps().max() else {
+            return Ok(());
+        };
+
+        // The latest timestamp drifts the furthest, so if it's fine, they all
+        // are.
+        let now = (self.now)();
+        self.clock.check(latest, now).map_err(Error::Clock)?;
+
+        let mut tx = self
+            .storage
+            .rw_transaction()
+            .await
+            .map_err(Error::Storage)?;
+
+        sync::apply(&mut tx, changes)
+            .await
+            .map_err(Error::Storage)?;
+
+        tx.store_high_water_mark(self.clock.high_water_mark().max(latest.counter))
+            .await
+            .map_err(Error::Storage)?;
+
+        tx.commit().await.map_err(Error::Storage)?;
+
+        self.clock.observe(latest, now).map_err(Error::Clock)
+    }
+
+    fn schema_version(&self, table: &str) -> Result<Version, Error<S::Error>> {
+        self.schema_to_version
+            .get(table)
+            .cloned()
+            .ok_or_else(|| Error::TableNotFound(table.to_string()))
+    }
+
+    /// Build a complete record out of a row's field ops. Each op is
+    /// translated from the schema version it was written at to the one we're
+    /// reading at, then we keep the latest value for each field and fill in
+    /// anything that's still missing from the schema's defaults.
+    ///
+    /// Fields that were moved here from another table (or that we're reading
+    /// from before they moved away) can also have values in related rows
+    /// over there, so we look those up too.
+    async fn materialize(
+        &self,
+        tx: &S::ROTransaction<'_>,
+        read: &Read<'_>,
+        id: Uuid,
+        fields: Vec<Field>,
+    ) -> Result<BTreeMap<String, Value>, Error<S::Error>> {
+        let mut latest = self.latest(read.table, &read.schema_version, fields);
+
+        for (link, referrers) in &read.links {
+            for (timestamp, value) in self
+                .linked_values(tx, read, link, referrers, id, &latest)
+                .await?
+            {
+                keep_latest(&mut latest, link.lens.field.name.clone(), timestamp, value);
+            }
+        }
+
+        Ok(read
+            .schema
+            .iter()
+            .map(|(name, field)| {
+                // Values that went through JavaScript (or IndexedDB) may
+                // have lost track of being floats.
+                let mut value = field.default.clone();
+                if let Some((_, written)) = latest.remove(name) {
+                    value.overlay(field.type_.coerce(written));
+                }
+
+                (name.clone(), value)
+            })
+            .collect())
+    }
+
+    /// The latest value of each field in a row's own ops, as of
+    /// `schema_version`.
+    ///
+    /// Updates only write the fields that changed, but some lenses (like
+    /// merges) need several fields at once. So each write is laid over the
+    /// row's values as its writer saw them before being translated, and we
+    /// keep whatever it wrote or changed.
+    ///
+    /// A write we can't translate (one from a newer client whose migrations
+    /// we don't have yet, say, or a value with no conversion) is left out
+    /// rather than making the whole row unreadable. Older writes and
+    /// defaults fill in for it.
+    fn latest(&self, table: &str, schema_version: &Version, fields: Vec<Field>) -> Latest {
+        let writes = writes(fields);
+
+        // The row as seen from the version we're reading at, and from each
+        // version that wrote to it.
+        let mut seen: BTreeMap<Version, Latest> = BTreeMap::new();
+        seen.insert(schema_version.clone(), Latest::new());
+        for (_, version) in writes.keys() {
+            seen.entry(version.clone()).or_default();
+        }
+
+        for ((timestamp, version), record) in writes {
+            let base: BTreeMap<String, Value> = seen[&version]
+                .iter()
+                .map(|(name, (_, value))| (name.clone(), value.clone()))
+                .collect();
+            let mut full = base.clone();
+            for (name, value) in &record {
+                match full.get_mut(name) {
+                    Some(kept) => kept.overlay(value.clone()),
+                    None => {
+                        full.insert(name.clone(), value.clone());
+                    }
+                }
+            }
+
+            for (reader, latest) in &mut seen {
+                let written = self.translate(table, &version, reader, record.clone());
+                let Some(after) = self
+                    .translate(table, &version, reader, full.clone())
+                    .or_else(|| written.clone())
+                else {
+                    continue;
+                };
+                let written = written.unwrap_or_default();
+                let before = self
+                    .translate(table, &version, reader, base.clone())
+                    .unwrap_or_default();
+
+                for (name, value) in after {
+                    if written.contains_key(&name) || before.get(&name) != Some(&value) {
+                        keep_latest(latest, name, timestamp, value);
+                    }
+                }
+            }
+        }
+
+        seen.remove(schema_version).unwrap_or_default()
+    }
+
+    /// Translate a record written at `from` to how `to` sees it, or `None` if
+    /// there's no way to.
+    fn translate(
+        &self,
+        table: &str,
+        from: &Version,
+        to: &Version,
+        mut record: BTreeMap<String, Value>,
+    ) -> Option<BTreeMap<String, Value>> {
+        if from != to {
+            for lens in self.migrator.migration_path(table, from, to).ok()? {
+                lens.transform_record(&mut record).ok()?;
+            }
+        }
+
+        Some(record)
+    }
+
+    /// Work out what reading `table` needs before going through its rows.
+    async fn read<'a>(
+        &'a self,
+        tx: &S::ROTransaction<'_>,
+        table: &'a str,
+    ) -> Result<Read<'a>, Error<S::Error>> {
+        let schema_version = self.schema_version(table)?;
+
+        let schema = self
+            .migrator
+            .schema(table, &schema_version)
+            .map_err(Error::Schema)?;
+
+        Ok(Read {
+            table,
+            links: self.related(tx, table, &schema_version).await?,
+            schema_version,
+            schema,
+        })
+    }
+
+    /// The moves that matter when reading `table` at `schema_version`. Where
+    /// the other table holds the reference, we also gather its rows by the
+    /// ID they refer to, once per read rather than once per row.
+    async fn related(
+        &self,
+        tx: &S::ROTransaction<'_>,
+        table: &str,
+        schema_version: &Version,
+    ) -> Result<Vec<(&Link, Referrers)>, Error<S::Error>> {
+        let mut out = Vec::new();
+
+        for link in self.migrator.links(table, schema_version) {
+            let lens = &link.lens;
+            let mut referrers = Referrers::new();
+
+            if table != lens.to {
+                for row in tx.rows(&lens.to).await? {
+                    let Some(fields) = self.live_fields(tx, &row).await? else {
+                        continue;
+                    };
+
+                    let reference = self
+                        .latest(&lens.to, &link.to_version, fields.clone())
+                        .remove(&lens.reference)
+                        .and_then(|(_, reference)| match reference {
+                            Value::String(reference) => Uuid::parse_str(&reference).ok(),
+                            _ => None,
+                        });
+
+                    if let Some(reference) = reference {
+                        referrers.entry(reference).or_default().push(fields);
+                    }
+                }
+            }
+
+            out.push((link, referrers));
+        }
+
+        Ok(out)
+    }
+
+    /// Values for a moved field from the rows on the other side of `link`.
+    /// `latest` is what we have so far for the row we're reading.
+    async fn linked_values(
+        &self,
+        tx: &S::ROTransaction<'_>,
+        read: &Read<'_>,
+        link: &Link,
+        referrers: &Referrers,
+        id: Uuid,
+        latest: &Latest,
+    ) -> Result<Vec<(Timestamp, Value)>, Error<S::Error>> {
+        let lens = &link.lens;
+        let mut related = Vec::new();
+
+        if read.table == lens.to {
+            // We hold the reference, so there's at most one row to look at.
+            let reference = match latest.get(&lens.reference) {
+                Some((_, Value::String(reference))) => Uuid::parse_str(reference).ok(),
+                _ => None,
+            };
+
+            if let Some(reference) = reference
+                && let Some(row) = tx.row(&lens.from, reference).await?
+                && let Some(fields) = self.live_fields(tx, &row).await?
+            {
+                related.push(fields);
+            }
+        } else if let Some(fields) = referrers.get(&id) {
+            // They hold the reference, and `related` already found them.
+            related.extend(fields.iter().cloned());
+        }
+
+        let mut out = Vec::new();
+        for fields in related {
+            for ((timestamp, written), record) in writes(fields) {
+                // Same as in `latest`: a write we can't translate is left out.
+                let Ok(value) = self.migrator.transform_linked(
+                    link,
+                    read.table,
+                    &read.schema_version,
+                    written,
+                    record,
+                ) else {
+                    continue;
+                };
+
+                out.extend(value.map(|value| (timestamp, value)));
+            }
+        }
+
+        Ok(out)
+    }
+}
+
+/// Group field ops into the writes they came from. Fields written together
+/// by one insert or update share a timestamp, and we translate them
+/// together, since some lenses (like merges) need to see several fields at
+/// once.
+fn writes(fields: Vec<Field>) -> BTreeMap<(Timestamp, Version), BTreeMap<String, Value>> {
+    let mut writes: BTreeMap<(Timestamp, Version), BTreeMap<String, Value>> = BTreeMap::new();
+
+    for field in fields {
+        writes
+            .entry((field.timestamp, field.schema_version))
+            .or_default()
+            .insert(field.field_name, field.value);
+    }
+
+    writes
+}
+
+/// The value of each field from the most recent write to it.
+type Latest = BTreeMap<String, (Timestamp, Value)>;
+
+/// Rows' field ops, by the row ID their reference field points at.
+type Referrers = BTreeMap<Uuid, Vec<Vec<Field>>>;
+
+/// What reading a table needs, worked out once per `list` or `get` rather
+/// than once per row.
+struct Read<'a> {
+    table: &'a str,
+    schema_version: Version,
+    schema: Schema,
+    /// Moves that matter for this read. Where the other table holds the
+    /// reference, its rows come along too.
+    links: Vec<(&'a Link, Referrers)>,
+}
+
+/// Newer objects are laid over older ones rather than replacing them, since
+/// a write from before a field was moved into an object only carries that
+/// one sub-field.
+fn keep_latest(latest: &mut Latest, name: String, timestamp: Timestamp, value: Value) {
+    match latest.entry(name) {
+        Entry::Vacant(entry) => {
+            entry.insert((timestamp, value));
+        }
+        Entry::Occupied(mut entry) => {
+            let (kept_at, kept) = entry.get_mut();
+            if timestamp > *kept_at {
+                *kept_at = timestamp;
+                kept.overlay(value);
+            }
+        }
+    }
+}
+
+/// A row as seen by a particular schema version.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Record {
+    pub id: Uuid,
+    pub data: BTreeMap<String, Value>,
 }
 
 #[derive(Debug, thiserror::Error, PartialEq)]
@@ -88,11 +701,23 @@ pub enum Error<E: std::error::Error> {
     #[error("Schema not found for table {0}")]
     TableNotFound(String),
 
+    #[error("Row {1} not found in table {0}")]
+    NotFound(String, Uuid),
+
+    #[error("Field {1} not found in table {0}")]
+    FieldNotFound(String, String),
+
     #[error("Error retrieving schema")]
     Schema(migrator::Error),
 
     #[error("Problem validating data for field {0}: {1}")]
     Validation(String, type_::Error),
+
+    #[error("Clock error: {0}")]
+    Clock(timestamp::Error),
+
+    #[error("Schema versions don't line up: {0}")]
+    Versions(migrator::Error),
 }
 
 impl<E: std::error::Error + Display> From<Error<E>> for JsValue {
@@ -105,30 +730,102 @@ impl<E: std::error::Error + Display> From<Error<E>> for JsValue {
 mod tests {
     use super::*;
     use crate::storage::memory::MemoryStorage;
-    use migrate::{AddRemoveField, Lens, Type};
+    use migrate::{
+        AddRemoveField, Constraint, CrossTable, DeletePolicy, FieldConstraint, HoistPlunge, Lens,
+        SplitMerge, Type,
+    };
 
-    fn init() -> Store<MemoryStorage> {
-        let mut migrator = Migrator::default();
-        migrator.add_migration(migrate::Migration {
-            schema: "test".into(),
-            version: 1,
-            ops: vec![Lens::Add(AddRemoveField {
-                name: "test".into(),
-                type_: Type::String,
-                default: "default".into(),
-            })],
-        });
+    async fn init() -> Store<MemoryStorage> {
+        init_with(MemoryStorage::default()).await
+    }
+
+    async fn init_with(storage: MemoryStorage) -> Store<MemoryStorage> {
+        init_at(1, storage).await
+    }
+
+    async fn init_at(version: usize, storage: MemoryStorage) -> Store<MemoryStorage> {
+        store_at(migrator(), "test", version, storage).await
+    }
 
+    /// A store that only knows about `table`, read at `version`.
+    async fn store_at(
+        migrator: Migrator,
+        table: &str,
+        version: impl Into<Version>,
+        storage: MemoryStorage,
+    ) -> Store<MemoryStorage> {
         Store::new(
             migrator,
-            BTreeMap::from([("test".into(), 1)]),
-            MemoryStorage::default(),
+            BTreeMap::from([(table.into(), version.into())]),
+            storage,
         )
+        .await
+        .unwrap()
+    }
+
+    /// A straight line of migrations for `table`, one per set of lenses,
+    /// numbered from 1.
+    fn migrator_for(table: &str, versions: Vec<Vec<Lens>>) -> Migrator {
+        let mut migrator = Migrator::default();
+        for (version, ops) in (1..).zip(versions) {
+            migrator
+                .add_migration(migrate::Migration {
+                    schema: table.into(),
+                    version: Version::from(version),
+                    parents: Vec::new(),
+                    delete_policy: None,
+                    ops,
+                })
+                .unwrap();
+        }
+        migrator
+    }
+
+    fn add(name: &str, type_: Type, default: Value) -> Lens {
+        Lens::Add(AddRemoveField {
+            name: name.into(),
+            type_,
+            default,
+        })
+    }
+
+    fn migrator() -> Migrator {
+        migrator_with(None)
+    }
+
+    fn migrator_with(delete_policy: Option<DeletePolicy>) -> Migrator {
+        let mut migrator = Migrator::default();
+        migrator
+            .add_migration(migrate::Migration {
+                schema: "test".into(),
+                version: 1.into(),
+                parents: Vec::new(),
+                delete_policy,
+                ops: vec![add("test", Type::String, "default".into())],
+            })
+            .unwrap();
+        migrator
+            .add_migration(migrate::Migration {
+                schema: "test".into(),
+                version: 2.into(),
+                parents: Vec::new(),
+                delete_policy: None,
+                ops: vec![
+                    Lens::Rename {
+                        from: "test".into(),
+                        to: "title".into(),
+                    },
+                    add("count", Type::Int, 0.into()),
+                ],
+            })
+            .unwrap();
+
+        migrator
     }
 
     #[tokio::test]
     async fn insert_stores_row() {
-        let mut store = init();
+        let mut store = init().await;
 
         store
             .insert("test".to_string(), BTreeMap::new())
@@ -143,7 +840,7 @@ mod tests {
 
     #[tokio::test]
     async fn insert_stores_field() {
-        let mut store = init();
+        let mut store = init().await;
 
         store
             .insert(
@@ -159,13 +856,13 @@ mod tests {
         assert_eq!(field.table, "test");
         assert_eq!(field.row_id, row.id);
         assert_eq!(field.field_name, "test");
-        assert_eq!(field.schema_version, 1);
+        assert_eq!(field.schema_version, 1.into());
         assert_eq!(field.value, "hooray!".into());
     }
 
     #[tokio::test]
     async fn test_validation_failure() {
-        let mut store = init();
+        let mut store = init().await;
 
         let result = store
             .insert(
@@ -183,4 +880,1236 @@ mod tests {
         assert_eq!(store.storage.rows.len(), 0);
         assert_eq!(store.storage.fields.len(), 0);
     }
+
+    #[tokio::test]
+    async fn insert_uses_increasing_timestamps() {
+        let mut store = init().await;
+
+        store
+            .insert(
+                "test".to_string(),
+                BTreeMap::from([("test".into(), "one".into())]),
+            )
+            .await
+            .unwrap();
+        store
+            .insert(
+                "test".to_string(),
+                BTreeMap::from([("test".into(), "two".into())]),
+            )
+            .await
+            .unwrap();
+
+        let rows = &store.storage.rows;
+        let fields = &store.storage.fields;
+
+        assert_eq!(rows[0].added, fields[0].timestamp);
+        assert_eq!(rows[1].added, fields[1].timestamp);
+        assert!(rows[0].added < rows[1].added);
+    }
+
+    #[tokio::test]
+    async fn insert_persists_high_water_mark() {
+        let mut store = init().await;
+
+        store
+            .insert("test".to_string(), BTreeMap::new())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            store.storage.high_water_mark,
+            store.storage.rows[0].added.counter
+        );
+    }
+
+    #[tokio::test]
+    async fn new_resumes_clock_from_storage() {
+        let far_future = u64::MAX / 2;
+        let mut store = init_with(MemoryStorage {
+            high_water_mark: far_future,
+            ..MemoryStorage::default()
+        })
+        .await;
+
+        store
+            .insert("test".to_string(), BTreeMap::new())
+            .await
+            .unwrap();
+
+        assert_eq!(store.storage.rows[0].added.counter, far_future + 1);
+    }
+
+    #[tokio::test]
+    async fn new_creates_and_persists_node_id() {
+        let store = init().await;
+
+        assert_ne!(store.node_id(), Uuid::nil());
+        assert_eq!(store.storage.node_id, Some(store.node_id()));
+    }
+
+    #[tokio::test]
+    async fn new_reuses_persisted_node_id() {
+        let node_id = Uuid::now_v7();
+        let store = init_with(MemoryStorage {
+            node_id: Some(node_id),
+            ..MemoryStorage::default()
+        })
+        .await;
+
+        assert_eq!(store.node_id(), node_id);
+    }
+
+    #[tokio::test]
+    async fn insert_stamps_ops_with_node_id() {
+        let mut store = init().await;
+
+        store
+            .insert(
+                "test".to_string(),
+                BTreeMap::from([("test".into(), "hooray!".into())]),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(store.storage.rows[0].added.node, store.node_id());
+        assert_eq!(store.storage.fields[0].timestamp.node, store.node_id());
+    }
+
+    #[tokio::test]
+    async fn rotate_node_id_persists_new_id() {
+        let mut store = init().await;
+        let original = store.node_id();
+
+        let rotated = store.rotate_node_id().await.unwrap();
+
+        assert_ne!(rotated, original);
+        assert_eq!(store.node_id(), rotated);
+        assert_eq!(store.storage.node_id, Some(rotated));
+
+        store
+            .insert("test".to_string(), BTreeMap::new())
+            .await
+            .unwrap();
+
+        assert_eq!(store.storage.rows[0].added.node, rotated);
+    }
+
+    #[tokio::test]
+    async fn list_returns_inserted_rows() {
+        let mut store = init().await;
+
+        let id = store
+            .insert(
+                "test".to_string(),
+                BTreeMap::from([("test".into(), "hooray!".into())]),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            store.list("test").await.unwrap(),
+            vec![Record {
+                id,
+                data: BTreeMap::from([("test".into(), "hooray!".into())]),
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn list_fills_in_defaults() {
+        let mut store = init().await;
+
+        let id = store
+            .insert("test".to_string(), BTreeMap::new())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            store.list("test").await.unwrap(),
+            vec![Record {
+                id,
+                data: BTreeMap::from([("test".into(), "default".into())]),
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn list_unknown_table() {
+        let store = init().await;
+
+        assert_eq!(
+            store.list("nope").await,
+            Err(Error::TableNotFound("nope".into()))
+        );
+    }
+
+    #[tokio::test]
+    async fn list_translates_older_data() {
+        let mut old = init_at(1, MemoryStorage::default()).await;
+
+        let id = old
+            .insert(
+                "test".to_string(),
+                BTreeMap::from([("test".into(), "from v1".into())]),
+            )
+            .await
+            .unwrap();
+
+        let new = init_at(2, old.storage).await;
+
+        assert_eq!(
+            new.list("test").await.unwrap(),
+            vec![Record {
+                id,
+                data: BTreeMap::from([
+                    ("title".into(), "from v1".into()),
+                    ("count".into(), 0.into()),
+                ]),
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn list_translates_newer_data() {
+        let mut new = init_at(2, MemoryStorage::default()).await;
+
+        let id = new
+            .insert(
+                "test".to_string(),
+                BTreeMap::from([
+                    ("title".into(), "from v2".into()),
+                    ("count".into(), 5.into()),
+                ]),
+            )
+            .await
+            .unwrap();
+
+        let old = init_at(1, new.storage).await;
+
+        assert_eq!(
+            old.list("test").await.unwrap(),
+            vec![Record {
+                id,
+                data: BTreeMap::from([("test".into(), "from v2".into())]),
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn list_prefers_latest_op_across_versions() {
+        let mut old = init_at(1, MemoryStorage::default()).await;
+
+        let id = old
+            .insert(
+                "test".to_string(),
+                BTreeMap::from([("test".into(), "older".into())]),
+            )
+            .await
+            .unwrap();
+
+        let mut storage = old.storage;
+        let mut newer = storage.fields[0].clone();
+        newer.field_name = "title".into();
+        newer.schema_version = 2.into();
+        newer.timestamp.counter += 1;
+        newer.value = "newer".into();
+        storage.fields.push(newer);
+
+        for version in [1, 2] {
+            let store = init_at(version, storage).await;
+
+            let records = store.list("test").await.unwrap();
+            assert_eq!(records.len(), 1);
+            assert_eq!(records[0].id, id);
+            assert!(
+                records[0]
+                    .data
+                    .values()
+                    .any(|value| value == &"newer".into()),
+                "expected the newer value at version {version}, got {records:?}"
+            );
+
+            storage = store.storage;
+        }
+    }
+
+    #[tokio::test]
+    async fn list_skips_ops_from_unknown_versions() {
+        let mut store = init().await;
+
+        let mut ids = Vec::new();
+        for value in ["first", "second"] {
+            let data = BTreeMap::from([("test".into(), value.into())]);
+            ids.push(store.insert("test".to_string(), data).await.unwrap());
+        }
+
+        // A newer client, synced before we've got its migrations.
+        let mut future = store.storage.fields[0].clone();
+        future.field_name = "headline".into();
+        future.schema_version = "3-headlines".into();
+        future.timestamp.counter += 10;
+        future.value = "from the future".into();
+        store
+            .apply(Changes {
+                rows: Vec::new(),
+                fields: vec![future],
+            })
+            .await
+            .unwrap();
+
+        let records = store.list("test").await.unwrap();
+        assert_eq!(
+            records,
+            vec![
+                Record {
+                    id: ids[0],
+                    data: BTreeMap::from([("test".into(), "first".into())]),
+                },
+                Record {
+                    id: ids[1],
+                    data: BTreeMap::from([("test".into(), "second".into())]),
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn get_returns_row() {
+        let mut store = init().await;
+
+        let id = store
+            .insert(
+                "test".to_string(),
+                BTreeMap::from([("test".into(), "hooray!".into())]),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            store.get("test", id).await,
+            Ok(BTreeMap::from([("test".into(), "hooray!".into())]))
+        );
+    }
+
+    #[tokio::test]
+    async fn get_translates_to_schema_version() {
+        let mut old = init_at(1, MemoryStorage::default()).await;
+
+        let id = old
+            .insert(
+                "test".to_string(),
+                BTreeMap::from([("test".into(), "from v1".into())]),
+            )
+            .await
+            .unwrap();
+
+        let new = init_at(2, old.storage).await;
+
+        assert_eq!(
+            new.get("test", id).await,
+            Ok(BTreeMap::from([
+                ("title".into(), "from v1".into()),
+                ("count".into(), 0.into()),
+            ]))
+        );
+    }
+
+    #[tokio::test]
+    async fn get_missing_row() {
+        let store = init().await;
+        let id = Uuid::now_v7();
+
+        assert_eq!(
+            store.get("test", id).await,
+            Err(Error::NotFound("test".into(), id))
+        );
+    }
+
+    #[tokio::test]
+    async fn get_removed_row() {
+        let mut store = init().await;
+
+        let id = store
+            .insert("test".to_string(), BTreeMap::new())
+            .await
+            .unwrap();
+
+        let added = store.storage.rows[0].added;
+        store.storage.rows[0].removed = Some(Timestamp::new(added.counter + 1, added.node));
+
+        assert_eq!(
+            store.get("test", id).await,
+            Err(Error::NotFound("test".into(), id))
+        );
+    }
+
+    #[tokio::test]
+    async fn timestamps_and_uuids_round_trip() {
+        let migrator = migrator_for(
+            "event",
+            vec![vec![
+                add("at", Type::Timestamp, "1970-01-01T00:00:00Z".into()),
+                add("owner", Type::Nullable(Box::new(Type::Uuid)), Value::Null),
+            ]],
+        );
+        let mut store = store_at(migrator, "event", 1, MemoryStorage::default()).await;
+
+        let data = BTreeMap::from([
+            ("at".into(), "2025-07-01T12:30:00+02:00".into()),
+            ("owner".into(), Uuid::from_u128(1).to_string().into()),
+        ]);
+        let id = store.insert("event".into(), data.clone()).await.unwrap();
+
+        assert_eq!(store.get("event", id).await, Ok(data));
+
+        let err = store
+            .insert(
+                "event".into(),
+                BTreeMap::from([("at".into(), "last tuesday".into())]),
+            )
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(err, Error::Validation(ref name, _) if name == "at"),
+            "Expected a validation error for `at`, got {err:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn constraints_apply_to_new_writes_only() {
+        let notes = || {
+            migrator_for(
+                "note",
+                vec![
+                    vec![add("title", Type::String, "".into())],
+                    vec![Lens::AddConstraint(FieldConstraint {
+                        name: "title".into(),
+                        constraint: Constraint::MaxLength(5),
+                    })],
+                ],
+            )
+        };
+
+        let mut old = store_at(notes(), "note", 1, MemoryStorage::default()).await;
+        let long = BTreeMap::from([("title".into(), "far too long".into())]);
+        let id = old.insert("note".into(), long.clone()).await.unwrap();
+
+        let mut new = store_at(notes(), "note", 2, old.storage().clone()).await;
+
+        assert_eq!(new.get("note", id).await, Ok(long.clone()));
+        assert_eq!(new.update("note".into(), id, long.clone()).await, Ok(()));
+
+        let err = new.insert("note".into(), long).await.unwrap_err();
+        assert_eq!(
+            err,
+            Error::Validation(
+                "title".into(),
+                type_::Error::Constraint {
+                    constraint: Constraint::MaxLength(5),
+                    got: "far too long".into(),
+                }
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn unwritten_fields_use_each_versions_default() {
+        let notes = || {
+            migrator_for(
+                "note",
+                vec![
+                    vec![add("title", Type::String, "".into())],
+                    vec![Lens::SetDefault {
+                        name: "title".into(),
+                        from: "".into(),
+                        to: "Untitled".into(),
+                    }],
+                ],
+            )
+        };
+
+        let mut old = store_at(notes(), "note", 1, MemoryStorage::default()).await;
+        let id = old.insert("note".into(), BTreeMap::new()).await.unwrap();
+
+        let new = store_at(notes(), "note", 2, old.storage().clone()).await;
+
+        assert_eq!(
+            old.get("note", id).await,
+            Ok(BTreeMap::from([("title".into(), "".into())]))
+        );
+        assert_eq!(
+            new.get("note", id).await,
+            Ok(BTreeMap::from([("title".into(), "Untitled".into())]))
+        );
+    }
+
+    fn people() -> Migrator {
+        migrator_for(
+            "person",
+            vec![
+                vec![add("name", Type::String, "".into())],
+                vec![Lens::Split(SplitMerge {
+                    name: "name".into(),
+                    template: "{first} {last}".parse().unwrap(),
+                })],
+            ],
+        )
+    }
+
+    #[tokio::test]
+    async fn split_fields_read_across_versions() {
+        let mut old = store_at(people(), "person", 1, MemoryStorage::default()).await;
+        let ada = old
+            .insert(
+                "person".into(),
+                BTreeMap::from([("name".into(), "Ada Lovelace".into())]),
+            )
+            .await
+            .unwrap();
+
+        let mut new = store_at(people(), "person", 2, old.storage().clone()).await;
+        let grace = new
+            .insert(
+                "person".into(),
+                BTreeMap::from([
+                    ("first".into(), "Grace".into()),
+                    ("last".into(), "Hopper".into()),
+                ]),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            new.get("person", ada).await,
+            Ok(BTreeMap::from([
+                ("first".into(), "Ada".into()),
+                ("last".into(), "Lovelace".into()),
+            ]))
+        );
+
+        let old = store_at(people(), "person", 1, new.storage().clone()).await;
+        assert_eq!(
+            old.get("person", grace).await,
+            Ok(BTreeMap::from([("name".into(), "Grace Hopper".into())]))
+        );
+    }
+
+    #[tokio::test]
+    async fn updating_one_part_reaches_old_clients() {
+        let mut old = store_at(people(), "person", 1, MemoryStorage::default()).await;
+        let ada = old
+            .insert(
+                "person".into(),
+                BTreeMap::from([("name".into(), "Ada Lovelace".into())]),
+            )
+            .await
+            .unwrap();
+
+        let mut new = store_at(people(), "person", 2, old.storage().clone()).await;
+        new.update(
+            "person".into(),
+            ada,
+            BTreeMap::from([("last".into(), "King".into())]),
+        )
+        .await
+        .unwrap();
+
+        let old = store_at(people(), "person", 1, new.storage().clone()).await;
+        assert_eq!(
+            old.get("person", ada).await,
+            Ok(BTreeMap::from([("name".into(), "Ada King".into())]))
+        );
+    }
+
+    #[tokio::test]
+    async fn plunged_writes_keep_the_rest_of_the_host() {
+        let object = |fields: &[(&str, &str)]| {
+            Value::Object(
+                fields
+                    .iter()
+                    .map(|(name, value)| (name.to_string(), Value::from(*value)))
+                    .collect(),
+            )
+        };
+        let profiles = || {
+            migrator_for(
+                "person",
+                vec![
+                    vec![
+                        add("bio", Type::String, "".into()),
+                        add(
+                            "profile",
+                            Type::Object(BTreeMap::from([("avatar".into(), Type::String)])),
+                            object(&[("avatar", "")]),
+                        ),
+                    ],
+                    vec![Lens::Plunge(HoistPlunge {
+                        host: "profile".into(),
+                        name: "bio".into(),
+                    })],
+                ],
+            )
+        };
+
+        let mut new = store_at(profiles(), "person", 2, MemoryStorage::default()).await;
+        let ada = new
+            .insert(
+                "person".into(),
+                BTreeMap::from([(
+                    "profile".into(),
+                    object(&[("avatar", "ada.png"), ("bio", "Hi")]),
+                )]),
+            )
+            .await
+            .unwrap();
+
+        let mut old = store_at(profiles(), "person", 1, new.storage().clone()).await;
+        old.update(
+            "person".into(),
+            ada,
+            BTreeMap::from([("bio".into(), "Hello!".into())]),
+        )
+        .await
+        .unwrap();
+
+        let new = store_at(profiles(), "person", 2, old.storage().clone()).await;
+        assert_eq!(
+            new.get("person", ada).await,
+            Ok(BTreeMap::from([(
+                "profile".into(),
+                object(&[("avatar", "ada.png"), ("bio", "Hello!")]),
+            )]))
+        );
+    }
+
+    #[tokio::test]
+    async fn branches_read_each_others_writes() {
+        let tasks = || {
+            let migration = |version: &str, parents: &[&str], ops| migrate::Migration {
+                schema: "task".into(),
+                version: version.into(),
+                parents: parents.iter().copied().map(Version::from).collect(),
+                delete_policy: None,
+                ops,
+            };
+
+            // Two branches both build on 1, and 3 merges them.
+            let mut migrator = Migrator::default();
+            migrator
+                .add_migrations(vec![
+                    migration("1", &[], vec![add("title", Type::String, "".into())]),
+                    migration(
+                        "2-priority",
+                        &["1"],
+                        vec![add("priority", Type::Int, 3.into())],
+                    ),
+                    migration(
+                        "2-done",
+                        &["1"],
+                        vec![add("done", Type::Bool, false.into())],
+                    ),
+                    migration("3", &["2-priority", "2-done"], Vec::new()),
+                ])
+                .unwrap();
+
+            migrator
+        };
+
+        let mut priority = store_at(tasks(), "task", "2-priority", MemoryStorage::default()).await;
+        let urgent = priority
+            .insert(
+                "task".into(),
+                BTreeMap::from([
+                    ("title".into(), "Urgent".into()),
+                    ("priority".into(), 1.into()),
+                ]),
+            )
+            .await
+            .unwrap();
+
+        let mut done = store_at(tasks(), "task", "2-done", priority.storage().clone()).await;
+        let finished = done
+            .insert(
+                "task".into(),
+                BTreeMap::from([
+                    ("title".into(), "Finished".into()),
+                    ("done".into(), true.into()),
+                ]),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            done.get("task", urgent).await,
+            Ok(BTreeMap::from([
+                ("title".into(), "Urgent".into()),
+                ("done".into(), false.into()),
+            ]))
+        );
+
+        let merged = store_at(tasks(), "task", "3", done.storage().clone()).await;
+        assert_eq!(
+            merged.get("task", urgent).await,
+            Ok(BTreeMap::from([
+                ("title".into(), "Urgent".into()),
+                ("priority".into(), 1.into()),
+                ("done".into(), false.into()),
+            ]))
+        );
+        assert_eq!(
+            merged.get("task", finished).await,
+            Ok(BTreeMap::from([
+                ("title".into(), "Finished".into()),
+                ("priority".into(), 3.into()),
+                ("done".into(), true.into()),
+            ]))
+        );
+    }
+
+    fn users_and_profiles() -> Migrator {
+        let avatar = AddRemoveField {
+            name: "avatar".into(),
+            type_: Type::Nullable(Box::new(Type::String)),
+            default: Value::Null,
+        };
+        let moved = CrossTable {
+            field: avatar.clone(),
+            from: "user".into(),
+            to: "profile".into(),
+            reference: "user_id".into(),
+        };
+
+        let mut migrator = Migrator::default();
+        migrator
+            .add_migrations(vec![
+                migrate::Migration {
+                    schema: "profile".into(),
+                    version: 1.into(),
+                    parents: Vec::new(),
+                    delete_policy: None,
+                    ops: vec![add(
+                        "user_id",
+                        Type::Nullable(Box::new(Type::Uuid)),
+                        Value::Null,
+                    )],
+                },
+                migrate::Migration {
+                    schema: "user".into(),
+                    version: 1.into(),
+                    parents: Vec::new(),
+                    delete_policy: None,
+                    ops: vec![Lens::Add(avatar)],
+                },
+            ])
+            .unwrap();
+        migrator
+            .add_migrations(vec![
+                migrate::Migration {
+                    schema: "profile".into(),
+                    version: 2.into(),
+                    parents: Vec::new(),
+                    delete_policy: None,
+                    ops: vec![Lens::MoveIn(moved.clone())],
+                },
+                migrate::Migration {
+                    schema: "user".into(),
+                    version: 2.into(),
+                    parents: Vec::new(),
+                    delete_policy: None,
+                    ops: vec![Lens::MoveOut(moved)],
+                },
+            ])
+            .unwrap();
+
+        migrator
+    }
+
+    async fn users_and_profiles_at(version: usize, storage: MemoryStorage) -> Store<MemoryStorage> {
+        Store::new(
+            users_and_profiles(),
+            BTreeMap::from([
+                ("user".into(), version.into()),
+                ("profile".into(), version.into()),
+            ]),
+            storage,
+        )
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn listing_moved_fields_matches_each_row() {
+        let mut new = users_and_profiles_at(2, MemoryStorage::default()).await;
+
+        let mut users = Vec::new();
+        for avatar in ["ada.png", "grace.png"] {
+            let user = new.insert("user".into(), BTreeMap::new()).await.unwrap();
+            new.insert(
+                "profile".into(),
+                BTreeMap::from([
+                    ("user_id".into(), user.to_string().into()),
+                    ("avatar".into(), avatar.into()),
+                ]),
+            )
+            .await
+            .unwrap();
+            users.push((user, avatar));
+        }
+
+        let old = users_and_profiles_at(1, new.storage().clone()).await;
+        let mut listed = old.list("user").await.unwrap();
+        listed.sort_by_key(|record| users.iter().position(|(user, _)| *user == record.id));
+
+        assert_eq!(
+            listed,
+            users
+                .into_iter()
+                .map(|(id, avatar)| Record {
+                    id,
+                    data: BTreeMap::from([("avatar".into(), avatar.into())]),
+                })
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn moved_fields_read_across_tables() {
+        let mut old = users_and_profiles_at(1, MemoryStorage::default()).await;
+        let user = old
+            .insert(
+                "user".into(),
+                BTreeMap::from([("avatar".into(), "before.png".into())]),
+            )
+            .await
+            .unwrap();
+        let profile = old
+            .insert(
+                "profile".into(),
+                BTreeMap::from([("user_id".into(), user.to_string().into())]),
+            )
+            .await
+            .unwrap();
+
+        // Newer clients find the avatar on the profile, through the user it
+        // refers to...
+        let mut new = users_and_profiles_at(2, old.storage().clone()).await;
+        assert_eq!(
+            new.get("profile", profile).await.unwrap()["avatar"],
+            Value::from("before.png")
+        );
+        assert_eq!(new.get("user", user).await, Ok(BTreeMap::new()));
+
+        // ...and older clients see what newer ones write there.
+        new.update(
+            "profile".into(),
+            profile,
+            BTreeMap::from([("avatar".into(), "after.png".into())]),
+        )
+        .await
+        .unwrap();
+
+        let old = users_and_profiles_at(1, new.storage().clone()).await;
+        assert_eq!(
+            old.get("user", user).await,
+            Ok(BTreeMap::from([("avatar".into(), "after.png".into())]))
+        );
+        assert_eq!(
+            new.get("profile", profile).await.unwrap()["avatar"],
+            Value::from("after.png")
+        );
+    }
+
+    #[tokio::test]
+    async fn moved_fields_need_matching_versions() {
+        let result = Store::new(
+            users_and_profiles(),
+            BTreeMap::from([("user".into(), 2.into()), ("profile".into(), 1.into())]),
+            MemoryStorage::default(),
+        )
+        .await;
+
+        assert!(
+            matches!(
+                result,
+                Err(Error::Versions(migrator::Error::MismatchedVersions { .. }))
+            ),
+            "expected mismatched versions, got {:?}",
+            result.err()
+        );
+    }
+
+    #[tokio::test]
+    async fn update_changes_field() {
+        let mut store = init().await;
+
+        let id = store
+            .insert(
+                "test".to_string(),
+                BTreeMap::from([("test".into(), "before".into())]),
+            )
+            .await
+            .unwrap();
+
+        store
+            .update(
+                "test".to_string(),
+                id,
+                BTreeMap::from([("test".into(), "after".into())]),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            store.get("test", id).await,
+            Ok(BTreeMap::from([("test".into(), "after".into())]))
+        );
+        assert_eq!(store.storage.fields.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn update_only_writes_changed_fields() {
+        let mut store = init_at(2, MemoryStorage::default()).await;
+
+        let id = store
+            .insert(
+                "test".to_string(),
+                BTreeMap::from([("title".into(), "title".into()), ("count".into(), 1.into())]),
+            )
+            .await
+            .unwrap();
+        let inserted_at = store.storage.rows[0].added;
+
+        store
+            .update(
+                "test".to_string(),
+                id,
+                BTreeMap::from([("title".into(), "title".into()), ("count".into(), 2.into())]),
+            )
+            .await
+            .unwrap();
+
+        let title = store
+            .storage
+            .fields
+            .iter()
+            .find(|field| field.field_name == "title")
+            .unwrap();
+        let count = store
+            .storage
+            .fields
+            .iter()
+            .find(|field| field.field_name == "count")
+            .unwrap();
+
+        assert_eq!(title.timestamp, inserted_at);
+        assert!(count.timestamp > inserted_at);
+        assert_eq!(count.value, 2.into());
+    }
+
+    #[tokio::test]
+    async fn update_keeps_newer_op() {
+        let mut store = init().await;
+
+        let id = store
+            .insert(
+                "test".to_string(),
+                BTreeMap::from([("test".into(), "before".into())]),
+            )
+            .await
+            .unwrap();
+
+        let mut older = store.storage.fields[0].clone();
+        older.value = "stale".into();
+
+        store
+            .update(
+                "test".to_string(),
+                id,
+                BTreeMap::from([("test".into(), "after".into())]),
+            )
+            .await
+            .unwrap();
+
+        let mut tx = store.storage.rw_transaction().await.unwrap();
+        tx.store_field(older).await.unwrap();
+        tx.commit().await.unwrap();
+
+        assert_eq!(
+            store.get("test", id).await,
+            Ok(BTreeMap::from([("test".into(), "after".into())]))
+        );
+    }
+
+    #[tokio::test]
+    async fn update_unknown_field() {
+        let mut store = init().await;
+
+        let id = store
+            .insert("test".to_string(), BTreeMap::new())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            store
+                .update(
+                    "test".to_string(),
+                    id,
+                    BTreeMap::from([("nope".into(), "value".into())]),
+                )
+                .await,
+            Err(Error::FieldNotFound("test".into(), "nope".into()))
+        );
+    }
+
+    #[tokio::test]
+    async fn update_accepts_whole_number_floats() {
+        let migrator = migrator_for(
+            "reading",
+            vec![vec![
+                add("label", Type::String, "".into()),
+                add("value", Type::Float, Value::Float(0.0)),
+            ]],
+        );
+        let mut store = store_at(migrator, "reading", 1, MemoryStorage::default()).await;
+
+        let id = store
+            .insert(
+                "reading".into(),
+                BTreeMap::from([("value".into(), Value::Float(2.0))]),
+            )
+            .await
+            .unwrap();
+
+        // What JavaScript hands back after a round trip: every whole number
+        // is an int.
+        store
+            .update(
+                "reading".into(),
+                id,
+                BTreeMap::from([
+                    ("label".into(), "kitchen".into()),
+                    ("value".into(), Value::Int(2)),
+                ]),
+            )
+            .await
+            .unwrap();
+        assert_eq!(store.storage.fields.len(), 2);
+
+        // And what IndexedDB hands back for a float it stored.
+        store.storage.fields[0].value = Value::Int(2);
+        assert_eq!(
+            store.get("reading", id).await,
+            Ok(BTreeMap::from([
+                ("label".into(), "kitchen".into()),
+                ("value".into(), Value::Float(2.0)),
+            ]))
+        );
+    }
+
+    #[tokio::test]
+    async fn update_validation_failure() {
+        let mut store = init().await;
+
+        let id = store
+            .insert("test".to_string(), BTreeMap::new())
+            .await
+            .unwrap();
+
+        let result = store
+            .update(
+                "test".to_string(),
+                id,
+                BTreeMap::from([("test".into(), 1.into())]),
+            )
+            .await;
+
+        assert!(
+            matches!(result, Err(Error::Validation(ref name, _)) if name == "test"),
+            "Expected validation error for \"test\", got {result:?}"
+        );
+        assert_eq!(store.storage.fields.len(), 0);
+    }
+
+    #[tokio::test]
+    async fn update_missing_row() {
+        let mut store = init().await;
+        let id = Uuid::now_v7();
+
+        assert_eq!(
+            store
+                .update(
+                    "test".to_string(),
+                    id,
+                    BTreeMap::from([("test".into(), "value".into())]),
+                )
+                .await,
+            Err(Error::NotFound("test".into(), id))
+        );
+    }
+
+    async fn insert_one(store: &mut Store<MemoryStorage>) -> Uuid {
+        store
+            .insert(
+                "test".to_string(),
+                BTreeMap::from([("test".into(), "hooray!".into())]),
+            )
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn delete_hides_row() {
+        let mut store = init().await;
+        let id = insert_one(&mut store).await;
+
+        store.delete("test".to_string(), id).await.unwrap();
+
+        assert!(store.storage.rows[0].removed.is_some());
+        assert_eq!(store.list("test").await, Ok(Vec::new()));
+        assert_eq!(
+            store.get("test", id).await,
+            Err(Error::NotFound("test".into(), id))
+        );
+    }
+
+    #[tokio::test]
+    async fn delete_missing_row() {
+        let mut store = init().await;
+        let id = Uuid::now_v7();
+
+        assert_eq!(
+            store.delete("test".to_string(), id).await,
+            Err(Error::NotFound("test".into(), id))
+        );
+    }
+
+    #[tokio::test]
+    async fn delete_already_deleted_row() {
+        let mut store = init().await;
+        let id = insert_one(&mut store).await;
+
+        store.delete("test".to_string(), id).await.unwrap();
+
+        assert_eq!(
+            store.delete("test".to_string(), id).await,
+            Err(Error::NotFound("test".into(), id))
+        );
+    }
+
+    #[tokio::test]
+    async fn undelete_restores_row() {
+        let mut store = init().await;
+        let id = insert_one(&mut store).await;
+
+        store.delete("test".to_string(), id).await.unwrap();
+        store.undelete("test".to_string(), id).await.unwrap();
+
+        assert_eq!(
+            store.get("test", id).await,
+            Ok(BTreeMap::from([("test".into(), "hooray!".into())]))
+        );
+    }
+
+    #[tokio::test]
+    async fn undelete_missing_row() {
+        let mut store = init().await;
+        let id = Uuid::now_v7();
+
+        assert_eq!(
+            store.undelete("test".to_string(), id).await,
+            Err(Error::NotFound("test".into(), id))
+        );
+    }
+
+    /// Simulate an update from another replica that hadn't seen our delete
+    /// yet, but whose clock was ahead of ours.
+    async fn concurrent_update(store: &mut Store<MemoryStorage>) {
+        let mut later = store.storage.fields[0].clone();
+        later.timestamp.counter = store.storage.rows[0].removed.unwrap().counter + 1;
+        later.value = "concurrent".into();
+
+        let mut tx = store.storage.rw_transaction().await.unwrap();
+        tx.store_field(later).await.unwrap();
+        tx.commit().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn add_wins_revives_row_on_later_update() {
+        let mut store = init().await;
+        let id = insert_one(&mut store).await;
+
+        store.delete("test".to_string(), id).await.unwrap();
+        concurrent_update(&mut store).await;
+
+        assert_eq!(
+            store.get("test", id).await,
+            Ok(BTreeMap::from([("test".into(), "concurrent".into())]))
+        );
+    }
+
+    async fn remove_wins(storage: MemoryStorage) -> Store<MemoryStorage> {
+        store_at(
+            migrator_with(Some(DeletePolicy::RemoveWins)),
+            "test",
+            1,
+            storage,
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn remove_wins_ignores_later_update() {
+        let mut store = remove_wins(MemoryStorage::default()).await;
+        let id = insert_one(&mut store).await;
+
+        store.delete("test".to_string(), id).await.unwrap();
+        concurrent_update(&mut store).await;
+
+        assert_eq!(
+            store.get("test", id).await,
+            Err(Error::NotFound("test".into(), id))
+        );
+
+        store.undelete("test".to_string(), id).await.unwrap();
+
+        assert_eq!(
+            store.get("test", id).await,
+            Ok(BTreeMap::from([("test".into(), "concurrent".into())]))
+        );
+    }
+
+    #[tokio::test]
+    async fn delete_policy_survives_restart() {
+        let mut store = remove_wins(MemoryStorage::default()).await;
+        let id = insert_one(&mut store).await;
+
+        store.delete("test".to_string(), id).await.unwrap();
+        concurrent_update(&mut store).await;
+
+        let restarted = remove_wins(store.storage.clone()).await;
+        assert_eq!(
+            restarted.get("test", id).await,
+            Err(Error::NotFound("test".into(), id))
+        );
+    }
+
+    #[tokio::test]
+    async fn row_ops_merge_in_any_order() {
+        let mut store = init().await;
+        let id = insert_one(&mut store).await;
+        let original = store.storage.rows[0].clone();
+
+        store.delete("test".to_string(), id).await.unwrap();
+
+        let mut tx = store.storage.rw_transaction().await.unwrap();
+        tx.store_row(original).await.unwrap();
+        tx.commit().await.unwrap();
+
+        assert_eq!(store.storage.rows.len(), 1);
+        assert_eq!(
+            store.get("test", id).await,
+            Err(Error::NotFound("test".into(), id))
+        );
+    }
 }
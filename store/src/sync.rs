@@ -0,0 +1,313 @@
+//! Exchanging ops between replicas, independent of transport. The receiver
+//! sends a `VersionVector` summarizing what it has, the sender answers with
+//! the `Changes` it's missing, and the receiver applies them. Applying is
+//! idempotent, so retries and overlapping changes from several peers are
+//! harmless.
+
+use crate::op::{Field, Row};
+use crate::storage::{ROTransaction, RWTransaction};
+use crate::timestamp::Timestamp;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+/// For each node, the highest timestamp counter we have an op from.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct VersionVector(BTreeMap<Uuid, u64>);
+
+impl VersionVector {
+    pub fn observe(&mut self, timestamp: Timestamp) {
+        let counter = self.0.entry(timestamp.node).or_default();
+        *counter = (*counter).max(timestamp.counter);
+    }
+
+    /// Whether the replica this vector came from already has the op at
+    /// `timestamp` (or something that supersedes it.)
+    pub fn contains(&self, timestamp: Timestamp) -> bool {
+        self.0
+            .get(&timestamp.node)
+            .is_some_and(|counter| timestamp.counter <= *counter)
+    }
+}
+
+/// Ops to send to another replica.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct Changes {
+    pub rows: Vec<Row>,
+    pub fields: Vec<Field>,
+}
+
+impl Changes {
+    /// Every timestamp mentioned in these changes.
+    pub fn timestamps(&self) -> impl Iterator<Item = Timestamp> + '_ {
+        self.rows
+            .iter()
+            .flat_map(|row| std::iter::once(row.added).chain(row.removed))
+            .chain(self.fields.iter().map(|field| field.timestamp))
+    }
+}
+
+/// Summarize the ops we have.
+pub async fn summary<T: ROTransaction>(tx: &T) -> Result<VersionVector, T::Error> {
+    let mut out = VersionVector::default();
+
+    for row in tx.all_rows().await? {
+        out.observe(row.added);
+        if let Some(removed) = row.removed {
+            out.observe(removed);
+        }
+    }
+
+    for field in tx.all_fields().await? {
+        out.observe(field.timestamp);
+    }
+
+    Ok(out)
+}
+
+/// Find the ops a replica with the given summary doesn't have yet.
+pub async fn changes_since<T: ROTransaction>(
+    tx: &T,
+    remote: &VersionVector,
+) -> Result<Changes, T::Error> {
+    let rows = tx
+        .all_rows()
+        .await?
+        .into_iter()
+        .filter(|row| {
+            !remote.contains(row.added) || row.removed.is_some_and(|ts| !remote.contains(ts))
+        })
+        .collect();
+
+    let fields = tx
+        .all_fields()
+        .await?
+        .into_iter()
+        .filter(|field| !remote.contains(field.timestamp))
+        .collect();
+
+    Ok(Changes { rows, fields })
+}
+
+/// Store ops we got from another replica.
+pub async fn apply<T: RWTransaction>(tx: &mut T, changes: Changes) -> Result<(), T::Error> {
+    for row in changes.rows {
+        tx.store_row(row).await?;
+    }
+
+    for field in changes.fields {
+        tx.store_field(field).await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::memory::MemoryStorage;
+    use crate::store::{Error, Store};
+    use crate::timestamp;
+    use migrate::{AddRemoveField, Lens, Migration, Migrator, Type, Value};
+    use pretty_assertions::assert_eq;
+
+    async fn replica() -> Store<MemoryStorage> {
+        replica_with(MemoryStorage::default()).await
+    }
+
+    async fn replica_with(storage: MemoryStorage) -> Store<MemoryStorage> {
+        let mut migrator = Migrator::default();
+        migrator
+            .add_migration(Migration {
+                schema: "test".into(),
+                version: 1.into(),
+                parents: Vec::new(),
+                delete_policy: None,
+                ops: vec![Lens::Add(AddRemoveField {
+                    name: "test".into(),
+                    type_: Type::String,
+                    default: "default".into(),
+                })],
+            })
+            .unwrap();
+
+        Store::new(
+            migrator,
+            BTreeMap::from([("test".into(), 1.into())]),
+            storage,
+        )
+        .await
+        .unwrap()
+    }
+
+    /// Send everything `from` has that `to` is missing.
+    async fn sync(from: &Store<MemoryStorage>, to: &mut Store<MemoryStorage>) {
+        let summary = to.summary().await.unwrap();
+        let changes = from.changes_since(&summary).await.unwrap();
+        to.apply(changes).await.unwrap();
+    }
+
+    fn data(value: &str) -> BTreeMap<String, Value> {
+        BTreeMap::from([("test".into(), value.into())])
+    }
+
+    #[test]
+    fn version_vector_tracks_highest_counter() {
+        let node = Uuid::now_v7();
+        let mut vv = VersionVector::default();
+
+        vv.observe(Timestamp::new(5, node));
+        vv.observe(Timestamp::new(3, node));
+
+        assert!(vv.contains(Timestamp::new(5, node)));
+        assert!(vv.contains(Timestamp::new(1, node)));
+        assert!(!vv.contains(Timestamp::new(6, node)));
+        assert!(!vv.contains(Timestamp::new(1, Uuid::now_v7())));
+    }
+
+    #[tokio::test]
+    async fn summary_of_empty_replica() {
+        let a = replica().await;
+
+        assert_eq!(a.summary().await, Ok(VersionVector::default()));
+    }
+
+    #[tokio::test]
+    async fn insert_reaches_other_replica() {
+        let mut a = replica().await;
+        let mut b = replica().await;
+
+        let id = a.insert("test".into(), data("from a")).await.unwrap();
+        sync(&a, &mut b).await;
+
+        assert_eq!(b.get("test", id).await, Ok(data("from a")));
+        assert_eq!(a.summary().await, b.summary().await);
+    }
+
+    #[tokio::test]
+    async fn changes_since_skips_known_ops() {
+        let mut a = replica().await;
+        let mut b = replica().await;
+
+        a.insert("test".into(), data("from a")).await.unwrap();
+        sync(&a, &mut b).await;
+
+        let changes = a.changes_since(&b.summary().await.unwrap()).await;
+
+        assert_eq!(changes, Ok(Changes::default()));
+    }
+
+    #[tokio::test]
+    async fn apply_is_idempotent() {
+        let mut a = replica().await;
+        let mut b = replica().await;
+
+        let id = a.insert("test".into(), data("from a")).await.unwrap();
+        let changes = a.changes_since(&VersionVector::default()).await.unwrap();
+
+        b.apply(changes.clone()).await.unwrap();
+        b.apply(changes).await.unwrap();
+
+        assert_eq!(b.list("test").await.unwrap().len(), 1);
+        assert_eq!(b.get("test", id).await, Ok(data("from a")));
+    }
+
+    #[tokio::test]
+    async fn concurrent_updates_converge() {
+        let mut a = replica().await;
+        let mut b = replica().await;
+
+        let id = a.insert("test".into(), data("original")).await.unwrap();
+        sync(&a, &mut b).await;
+
+        a.update("test".into(), id, data("from a")).await.unwrap();
+        b.update("test".into(), id, data("from b")).await.unwrap();
+
+        sync(&a, &mut b).await;
+        sync(&b, &mut a).await;
+
+        let from_a = a.get("test", id).await.unwrap();
+        let from_b = b.get("test", id).await.unwrap();
+
+        assert_eq!(from_a, from_b);
+        assert_ne!(from_a, data("original"));
+    }
+
+    #[tokio::test]
+    async fn deletes_reach_other_replica() {
+        let mut a = replica().await;
+        let mut b = replica().await;
+
+        let id = a.insert("test".into(), data("original")).await.unwrap();
+        sync(&a, &mut b).await;
+
+        b.delete("test".into(), id).await.unwrap();
+        sync(&b, &mut a).await;
+
+        assert_eq!(a.list("test").await, Ok(Vec::new()));
+    }
+
+    #[tokio::test]
+    async fn apply_moves_clock_past_remote_ops() {
+        // a's clock is running ahead of b's, but not so far that b refuses
+        // its ops.
+        let mut a = replica_with(MemoryStorage {
+            high_water_mark: timestamp::now() + 30 * 1000,
+            ..MemoryStorage::default()
+        })
+        .await;
+        let mut b = replica().await;
+
+        let id = a.insert("test".into(), data("from a")).await.unwrap();
+        sync(&a, &mut b).await;
+
+        b.update("test".into(), id, data("from b")).await.unwrap();
+        sync(&b, &mut a).await;
+
+        assert_eq!(a.get("test", id).await, Ok(data("from b")));
+    }
+
+    #[tokio::test]
+    async fn apply_rejects_timestamps_too_far_ahead() {
+        let mut a = replica().await;
+        let mut b = replica().await;
+
+        a.insert("test".into(), data("from a")).await.unwrap();
+        let mut changes = a.changes_since(&VersionVector::default()).await.unwrap();
+        let far_future = timestamp::now() + 24 * 60 * 60 * 1000;
+        changes.fields[0].timestamp.counter = far_future;
+
+        let result = b.apply(changes).await;
+
+        assert!(
+            matches!(result, Err(Error::Clock(timestamp::Error::Drift { .. }))),
+            "expected a drift error, got {result:?}"
+        );
+        assert_eq!(b.summary().await, Ok(VersionVector::default()));
+    }
+
+    #[tokio::test]
+    async fn rejected_changes_leave_clock_alone() {
+        // a's ops are ahead of b's clock, but not by enough to be refused on
+        // their own.
+        let mut a = replica_with(MemoryStorage {
+            high_water_mark: timestamp::now() + 30 * 1000,
+            ..MemoryStorage::default()
+        })
+        .await;
+        let mut b = replica().await;
+
+        a.insert("test".into(), data("first")).await.unwrap();
+        a.insert("test".into(), data("second")).await.unwrap();
+        let mut changes = a.changes_since(&VersionVector::default()).await.unwrap();
+        let last = changes.fields.len() - 1;
+        changes.fields[last].timestamp.counter = timestamp::now() + 24 * 60 * 60 * 1000;
+
+        assert!(b.apply(changes).await.is_err());
+
+        assert_eq!(b.storage().high_water_mark, 0);
+
+        b.insert("test".into(), data("from b")).await.unwrap();
+        assert!(b.storage().rows[0].added < a.storage().rows[0].added);
+    }
+}
@@ -29,6 +29,115 @@ impl Debug for Timestamp {
     }
 }
 
+/// A hybrid logical clock for a single replica.
+///
+/// Counters are milliseconds since the Unix epoch, but we never hand out the
+/// same counter twice: if the wall clock hasn't moved (or has gone backwards)
+/// since the last tick, we bump the counter by one instead. We keep counters
+/// in milliseconds rather than packing a logical component into the low bits
+/// so that they stay within JavaScript's safe integer range when we store them
+/// in IndexedDB.
+#[derive(Debug)]
+pub struct Clock {
+    node: Uuid,
+    last: u64,
+    max_drift: u64,
+}
+
+impl Clock {
+    /// How far ahead of our wall clock (in milliseconds) we'll accept remote
+    /// timestamps before refusing them.
+    pub const DEFAULT_MAX_DRIFT: u64 = 60 * 1000;
+
+    /// Create a clock for `node` that resumes from a previously-persisted
+    /// high-water mark (or 0 for a fresh replica.)
+    pub fn new(node: Uuid, last: u64) -> Self {
+        Self {
+            node,
+            last,
+            max_drift: Self::DEFAULT_MAX_DRIFT,
+        }
+    }
+
+    pub fn node(&self) -> Uuid {
+        self.node
+    }
+
+    /// Stamp future timestamps with a different node ID. The counter carries
+    /// on from where it was, so nothing we hand out afterwards will sort
+    /// before what we've already written.
+    pub fn set_node(&mut self, node: Uuid) {
+        self.node = node;
+    }
+
+    /// The highest counter this clock has handed out or observed. Persist
+    /// this and pass it back to `Clock::new` on restart.
+    pub fn high_water_mark(&self) -> u64 {
+        self.last
+    }
+
+    /// Get a timestamp for a new local event. It will be strictly greater
+    /// than every timestamp this clock has produced or observed so far.
+    pub fn tick(&mut self, now: u64) -> Result<Timestamp, Error> {
+        let next = self.last.checked_add(1).ok_or(Error::Overflow)?.max(now);
+        self.last = next;
+
+        Ok(Timestamp::new(next, self.node))
+    }
+
+    /// Move the clock forward past a timestamp we received from another
+    /// replica, so that anything we write afterwards sorts after it.
+    pub fn observe(&mut self, remote: Timestamp, now: u64) -> Result<(), Error> {
+        self.check(remote, now)?;
+        self.last = self.last.max(remote.counter);
+
+        Ok(())
+    }
+
+    /// Check whether `observe` would accept a timestamp, without moving the
+    /// clock.
+    pub fn check(&self, remote: Timestamp, now: u64) -> Result<(), Error> {
+        let drift = remote.counter.saturating_sub(now);
+        if drift > self.max_drift {
+            return Err(Error::Drift {
+                remote,
+                drift,
+                max_drift: self.max_drift,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// The current wall-clock time in milliseconds since the Unix epoch.
+#[cfg(target_arch = "wasm32")]
+pub fn now() -> u64 {
+    wasm_bindgen_futures::js_sys::Date::now() as u64
+}
+
+/// The current wall-clock time in milliseconds since the Unix epoch.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[derive(Debug, thiserror::Error, PartialEq)]
+pub enum Error {
+    #[error("Timestamp {remote} is {drift}ms ahead of our clock (we allow up to {max_drift}ms)")]
+    Drift {
+        remote: Timestamp,
+        drift: u64,
+        max_drift: u64,
+    },
+
+    #[error("Clock counter overflowed")]
+    Overflow,
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -44,6 +153,18 @@ mod test {
                 assert_eq!(a.node.cmp(&b.node), a.cmp(&b));
             }
         }
+
+        #[test]
+        fn tick_is_strictly_increasing(start: u64, nows: Vec<u64>) {
+            let mut clock = Clock::new(Uuid::nil(), start % (u64::MAX / 2));
+            let mut previous = Timestamp::new(clock.high_water_mark(), Uuid::nil());
+
+            for now in nows {
+                let next = clock.tick(now % (u64::MAX / 2)).unwrap();
+                assert!(next > previous, "{next} should be after {previous}");
+                previous = next;
+            }
+        }
     }
 
     #[test]
@@ -52,4 +173,77 @@ mod test {
 
         assert_eq!(ts.to_string(), "123@00000000-0000-0000-0000-000000000000")
     }
+
+    #[test]
+    fn tick_follows_wall_clock() {
+        let node = Uuid::now_v7();
+        let mut clock = Clock::new(node, 0);
+
+        assert_eq!(clock.tick(1000), Ok(Timestamp::new(1000, node)));
+        assert_eq!(clock.tick(2000), Ok(Timestamp::new(2000, node)));
+    }
+
+    #[test]
+    fn tick_bumps_counter_when_wall_clock_stalls() {
+        let node = Uuid::now_v7();
+        let mut clock = Clock::new(node, 0);
+
+        assert_eq!(clock.tick(1000), Ok(Timestamp::new(1000, node)));
+        assert_eq!(clock.tick(1000), Ok(Timestamp::new(1001, node)));
+        assert_eq!(clock.tick(500), Ok(Timestamp::new(1002, node)));
+    }
+
+    #[test]
+    fn tick_resumes_after_high_water_mark() {
+        let mut clock = Clock::new(Uuid::nil(), 5000);
+
+        assert_eq!(clock.tick(1000).unwrap().counter, 5001);
+    }
+
+    #[test]
+    fn tick_fails_on_overflow() {
+        let mut clock = Clock::new(Uuid::nil(), u64::MAX);
+
+        assert_eq!(clock.tick(0), Err(Error::Overflow));
+    }
+
+    #[test]
+    fn observe_moves_clock_forward() {
+        let mut clock = Clock::new(Uuid::nil(), 0);
+
+        clock
+            .observe(Timestamp::new(1500, Uuid::now_v7()), 1000)
+            .unwrap();
+
+        assert_eq!(clock.high_water_mark(), 1500);
+        assert_eq!(clock.tick(1000).unwrap().counter, 1501);
+    }
+
+    #[test]
+    fn observe_ignores_older_timestamps() {
+        let mut clock = Clock::new(Uuid::nil(), 2000);
+
+        clock
+            .observe(Timestamp::new(1500, Uuid::nil()), 1000)
+            .unwrap();
+
+        assert_eq!(clock.high_water_mark(), 2000);
+    }
+
+    #[test]
+    fn observe_rejects_excessive_drift() {
+        let mut clock = Clock::new(Uuid::nil(), 0);
+        clock.max_drift = 100;
+        let remote = Timestamp::new(1101, Uuid::nil());
+
+        assert_eq!(
+            clock.observe(remote, 1000),
+            Err(Error::Drift {
+                remote,
+                drift: 101,
+                max_drift: 100,
+            })
+        );
+        assert_eq!(clock.high_water_mark(), 0);
+    }
 }